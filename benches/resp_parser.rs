@@ -0,0 +1,194 @@
+use bytes::{Buf, BytesMut};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use rust_keyval::db::storage::Databases;
+use rust_keyval::protocol::parser::process_parts;
+use rust_keyval::protocol::resp::parser::RequestParser;
+use rust_keyval::server::session::Session;
+
+fn encode(parts: &[&[u8]]) -> Vec<u8> {
+    let mut out = format!("*{}\r\n", parts.len()).into_bytes();
+    for p in parts {
+        out.extend_from_slice(format!("${}\r\n", p.len()).as_bytes());
+        out.extend_from_slice(p);
+        out.extend_from_slice(b"\r\n");
+    }
+    out
+}
+
+fn pipeline(n: usize) -> Vec<u8> {
+    let mut out = Vec::new();
+    for i in 0..n {
+        let key = format!("key:{}", i);
+        out.extend_from_slice(&encode(&[b"SET", key.as_bytes(), b"some-value-of-moderate-size"]));
+    }
+    out
+}
+
+/// The parser this crate used before `RequestParser`: byte-by-byte line
+/// scanning, one `String` per argument, and a `Vec::drain` of the front of
+/// the buffer after every request.
+mod legacy {
+    pub fn parse_resp_one(input: &[u8]) -> Result<Option<(Vec<String>, usize)>, String> {
+        let mut i = 0;
+
+        fn read_line<'a>(input: &'a [u8], i: &mut usize) -> Option<&'a [u8]> {
+            let start = *i;
+            while *i + 1 < input.len() {
+                if input[*i] == b'\r' && input[*i + 1] == b'\n' {
+                    let line = &input[start..*i];
+                    *i += 2;
+                    return Some(line);
+                }
+                *i += 1;
+            }
+            *i = start;
+            None
+        }
+
+        let header = match read_line(input, &mut i) {
+            Some(l) => l,
+            None => return Ok(None),
+        };
+        let n: usize = std::str::from_utf8(&header[1..])
+            .map_err(|_| "utf8")?
+            .parse()
+            .map_err(|_| "len")?;
+
+        let mut parts = Vec::with_capacity(n);
+        for _ in 0..n {
+            let bulk_hdr = match read_line(input, &mut i) {
+                Some(l) => l,
+                None => return Ok(None),
+            };
+            let len: usize = std::str::from_utf8(&bulk_hdr[1..])
+                .map_err(|_| "utf8")?
+                .parse()
+                .map_err(|_| "len")?;
+            if i + len + 2 > input.len() {
+                return Ok(None);
+            }
+            parts.push(String::from_utf8_lossy(&input[i..i + len]).to_string());
+            i += len + 2;
+        }
+        Ok(Some((parts, i)))
+    }
+
+    /// Feeds `chunks` into an accumulator the way the old connection loop did.
+    pub fn drive(chunks: &[&[u8]]) -> usize {
+        let mut acc: Vec<u8> = Vec::new();
+        let mut args = 0;
+        for chunk in chunks {
+            acc.extend_from_slice(chunk);
+            while let Ok(Some((parts, consumed))) = parse_resp_one(&acc) {
+                args += parts.len();
+                acc.drain(..consumed);
+            }
+        }
+        args
+    }
+}
+
+fn drive_zero_copy(chunks: &[&[u8]], owned: bool) -> usize {
+    let mut buf = BytesMut::with_capacity(4096);
+    let mut parser = RequestParser::new();
+    let mut args = 0;
+    for chunk in chunks {
+        if let Some(len) = parser.pending_len() {
+            buf.reserve(len.saturating_sub(buf.len()));
+        }
+        buf.extend_from_slice(chunk);
+        while let Ok(Some(req)) = parser.parse(&buf) {
+            args += if owned {
+                req.to_strings().map_or(0, |parts| parts.len())
+            } else {
+                req.args().map(black_box).count()
+            };
+            let consumed = req.consumed();
+            buf.advance(consumed);
+        }
+    }
+    args
+}
+
+fn bench_pipeline(c: &mut Criterion) {
+    let mut group = c.benchmark_group("pipeline");
+
+    for depth in [10, 100, 1000] {
+        let input = pipeline(depth);
+        let chunks = [input.as_slice()];
+        group.throughput(Throughput::Bytes(input.len() as u64));
+
+        group.bench_with_input(BenchmarkId::new("legacy", depth), &chunks, |b, chunks| {
+            b.iter(|| legacy::drive(black_box(chunks)))
+        });
+        group.bench_with_input(BenchmarkId::new("zero_copy", depth), &chunks, |b, chunks| {
+            b.iter(|| drive_zero_copy(black_box(chunks), false))
+        });
+        group.bench_with_input(BenchmarkId::new("zero_copy_owned", depth), &chunks, |b, chunks| {
+            b.iter(|| drive_zero_copy(black_box(chunks), true))
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_large_bulk(c: &mut Criterion) {
+    let mut group = c.benchmark_group("large_bulk_in_4k_reads");
+    group.sample_size(20);
+
+    for size in [64 * 1024, 1024 * 1024] {
+        let value = vec![b'x'; size];
+        let input = encode(&[b"SET", b"big", &value]);
+        let chunks: Vec<&[u8]> = input.chunks(4096).collect();
+        group.throughput(Throughput::Bytes(input.len() as u64));
+
+        group.bench_with_input(BenchmarkId::new("legacy", size), &chunks, |b, chunks| {
+            b.iter(|| legacy::drive(black_box(chunks)))
+        });
+        group.bench_with_input(BenchmarkId::new("zero_copy", size), &chunks, |b, chunks| {
+            b.iter(|| drive_zero_copy(black_box(chunks), false))
+        });
+    }
+
+    group.finish();
+}
+
+/// Parses a pipeline and runs every request through the dispatcher, as a
+/// connection does: the argument copies are measured along with the
+/// commands themselves.
+async fn parse_and_dispatch(input: &[u8], dbs: &Databases, session: &mut Session) -> usize {
+    let mut parser = RequestParser::new();
+    let mut pos = 0;
+    let mut replies = 0;
+    while let Ok(Some(req)) = parser.parse(&input[pos..]) {
+        pos += req.consumed();
+        let parts = req.to_strings().expect("valid UTF-8");
+        black_box(process_parts(&parts, session, dbs).await);
+        replies += 1;
+    }
+    replies
+}
+
+fn bench_dispatch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse_and_dispatch");
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let dbs = Databases::new(1);
+    let mut session = Session::default();
+
+    for depth in [10, 100, 1000] {
+        let input = pipeline(depth);
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_with_input(BenchmarkId::new("set", depth), &input, |b, input| {
+            b.iter(|| rt.block_on(parse_and_dispatch(black_box(input), &dbs, &mut session)))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_pipeline, bench_large_bulk, bench_dispatch);
+criterion_main!(benches);
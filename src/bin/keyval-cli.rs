@@ -4,11 +4,12 @@ use std::io::{self, Read, Stdin, Stdout, Write};
 use std::net::TcpStream;
 use std::time::{Duration, Instant};
 
-fn encode_cmd(parts: &[String]) -> Vec<u8> {
+use rust_keyval::protocol::inline::split_args;
+
+fn encode_cmd(parts: &[Vec<u8>]) -> Vec<u8> {
     let mut out: Vec<u8> = Vec::new();
     out.extend_from_slice(format!("*{}\r\n", parts.len()).as_bytes());
-    for p in parts {
-        let b = p.as_bytes();
+    for b in parts {
         out.extend_from_slice(format!("${}\r\n", b.len()).as_bytes());
         out.extend_from_slice(b);
         out.extend_from_slice(b"\r\n");
@@ -169,56 +170,13 @@ fn pretty_print(resp: &Resp) {
     }
 }
 
-fn split_args(line: &str) -> Result<Vec<String>, String> {
-    let mut out: Vec<String> = Vec::new();
-    let mut cur: String = String::new();
-    let mut in_quotes: bool = false;
-    let mut escape: bool = false;
-
-    for c in line.chars() {
-        if escape {
-            cur.push(c);
-            escape = false;
-            continue;
-        }
-        if c == '\\' {
-            escape = true;
-            continue;
-        }
-        if c == '"' {
-            in_quotes = !in_quotes;
-            continue;
-        }
-        if !in_quotes && c.is_whitespace() {
-            if !cur.is_empty() {
-                out.push(cur.clone());
-                cur.clear();
-            }
-            continue;
-        }
-        cur.push(c);
-    }
-
-    if escape {
-        return Err("dangling escape (\\) at end".into());
-    }
-    if in_quotes {
-        return Err("unclosed quote (\")".into());
-    }
-    if !cur.is_empty() {
-        out.push(cur);
-    }
-
-    Ok(out)
-}
-
 fn main() -> Result<(), String> {
     let mut host: String = "127.0.0.1".to_string();
     let mut port: String = "6374".to_string();
 
     let args: Vec<String> = env::args().collect();
     let mut i: usize = 1;
-    let mut cmd_parts: Vec<String> = Vec::new();
+    let mut cmd_parts: Vec<Vec<u8>> = Vec::new();
 
     while i < args.len() {
         match args[i].as_str() {
@@ -231,7 +189,7 @@ fn main() -> Result<(), String> {
                 port = args.get(i).ok_or("missing --port value")?.clone();
             }
             _ => {
-                cmd_parts = args[i..].to_vec();
+                cmd_parts = args[i..].iter().map(|a| a.clone().into_bytes()).collect();
                 break;
             }
         }
@@ -271,7 +229,13 @@ fn main() -> Result<(), String> {
             break;
         }
 
-        let parts: Vec<String> = split_args(line)?;
+        let parts: Vec<Vec<u8>> = match split_args(line.as_bytes()) {
+            Ok(parts) => parts,
+            Err(e) => {
+                eprintln!("(error) {}", e);
+                continue;
+            }
+        };
         if parts.is_empty() {
             continue;
         }
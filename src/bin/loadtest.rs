@@ -6,7 +6,7 @@ use std::time::{Duration, Instant};
 
 fn resp_bulk(s: &str) -> Vec<u8> {
     let mut out = Vec::new();
-    out.extend_from_slice(format!("${}\r\n", s.as_bytes().len()).as_bytes());
+    out.extend_from_slice(format!("${}\r\n", s.len()).as_bytes());
     out.extend_from_slice(s.as_bytes());
     out.extend_from_slice(b"\r\n");
     out
@@ -190,7 +190,7 @@ fn main() {
                     sent_ops += 1;
                 }
 
-                if let Err(_) = s.write_all(&payload) {
+                if s.write_all(&payload).is_err() {
                     bad.fetch_add(expected_replies as u64, Ordering::Relaxed);
                     return;
                 }
@@ -0,0 +1,114 @@
+use crate::protocol::resp::encoder::RespValue;
+use crate::server::acl;
+use crate::server::session::Session;
+
+pub async fn execute(parts: &[String], session: &Session) -> RespValue {
+    let sub = match parts.get(1) {
+        Some(s) => s.to_uppercase(),
+        None => return RespValue::Error("ERR usage ACL <subcommand> [arguments ...]".into()),
+    };
+    let args = &parts[2..];
+
+    match sub.as_str() {
+        "WHOAMI" if args.is_empty() => RespValue::bulk(session.user.clone()),
+        "SETUSER" if !args.is_empty() => match acl::set_user(&args[0], &args[1..]) {
+            Ok(()) => RespValue::SimpleString("OK".into()),
+            Err(e) => RespValue::Error(format!("ERR {}", e)),
+        },
+        "GETUSER" if args.len() == 1 => {
+            acl::get_user(&args[0]).map_or(RespValue::Null, |u| u.to_resp())
+        }
+        "DELUSER" if !args.is_empty() => match acl::del_users(args) {
+            Ok(n) => RespValue::Integer(n as i64),
+            Err(e) => RespValue::Error(format!("ERR {}", e)),
+        },
+        "LIST" if args.is_empty() => RespValue::Array(
+            acl::users()
+                .iter()
+                .map(|u| RespValue::bulk(u.describe()))
+                .collect(),
+        ),
+        "USERS" if args.is_empty() => RespValue::Array(
+            acl::users()
+                .into_iter()
+                .map(|u| RespValue::bulk(u.name))
+                .collect(),
+        ),
+        "CAT" if args.len() <= 1 => cat(args.first()),
+        "LOG" if args.len() <= 1 => log(args.first()),
+        "LOAD" if args.is_empty() => match acl::reload_file() {
+            Ok(()) => RespValue::SimpleString("OK".into()),
+            Err(e) => RespValue::Error(format!("ERR {}", e)),
+        },
+        "SAVE" if args.is_empty() => match acl::save_file() {
+            Ok(()) => RespValue::SimpleString("OK".into()),
+            Err(e) => RespValue::Error(format!("ERR {}", e)),
+        },
+        _ => RespValue::Error(format!(
+            "ERR unknown subcommand or wrong number of arguments for '{}'",
+            parts[1]
+        )),
+    }
+}
+
+/// ACL CAT [category]
+fn cat(category: Option<&String>) -> RespValue {
+    let names = match category {
+        None => acl::CATEGORIES.to_vec(),
+        Some(c) => match acl::category_commands(&c.to_ascii_lowercase()) {
+            Some(names) => names,
+            None => return RespValue::Error(format!("ERR Unknown category '{}'", c)),
+        },
+    };
+    RespValue::Array(names.into_iter().map(RespValue::bulk).collect())
+}
+
+/// ACL LOG [count | RESET]
+fn log(arg: Option<&String>) -> RespValue {
+    let count = match arg {
+        None => 10,
+        Some(a) if a.eq_ignore_ascii_case("RESET") => {
+            acl::reset_log();
+            return RespValue::SimpleString("OK".into());
+        }
+        Some(a) => match a.parse::<usize>() {
+            Ok(n) => n,
+            Err(_) => {
+                return RespValue::Error("ERR value is out of range, must be positive".into())
+            }
+        },
+    };
+
+    let now_ms = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64);
+
+    let entries = acl::log(count)
+        .into_iter()
+        .map(|e| {
+            let age = now_ms.saturating_sub(e.created_ms) as f64 / 1000.0;
+            RespValue::Map(vec![
+                (RespValue::bulk("count"), RespValue::Integer(e.count as i64)),
+                (RespValue::bulk("reason"), RespValue::bulk(e.reason)),
+                (RespValue::bulk("context"), RespValue::bulk("toplevel")),
+                (RespValue::bulk("object"), RespValue::bulk(e.object)),
+                (RespValue::bulk("username"), RespValue::bulk(e.username)),
+                (RespValue::bulk("age-seconds"), RespValue::Double(age)),
+                (
+                    RespValue::bulk("client-info"),
+                    RespValue::bulk(e.client_info),
+                ),
+                (RespValue::bulk("entry-id"), RespValue::Integer(e.id as i64)),
+                (
+                    RespValue::bulk("timestamp-created"),
+                    RespValue::Integer(e.created_ms as i64),
+                ),
+                (
+                    RespValue::bulk("timestamp-last-updated"),
+                    RespValue::Integer(e.updated_ms as i64),
+                ),
+            ])
+        })
+        .collect();
+    RespValue::Array(entries)
+}
@@ -0,0 +1,28 @@
+use crate::protocol::resp::encoder::RespValue;
+use crate::server::acl;
+use crate::server::session::Session;
+
+/// AUTH password | AUTH username password
+pub async fn execute(parts: &[String], session: &mut Session) -> RespValue {
+    let (user, password) = match parts.len() {
+        2 => (acl::DEFAULT_USER, &parts[1]),
+        3 => (parts[1].as_str(), &parts[2]),
+        _ => return RespValue::Error("ERR usage AUTH [username] password".into()),
+    };
+
+    if parts.len() == 2 && !acl::default_has_password() {
+        return RespValue::Error(
+            "ERR AUTH <password> called without any password configured for the default user. Are you sure your configuration is correct?".into(),
+        );
+    }
+
+    if !acl::authenticate(user, password, session.id) {
+        return RespValue::Error(
+            "WRONGPASS invalid username-password pair or user is disabled.".into(),
+        );
+    }
+
+    session.user = user.to_string();
+    session.authenticated = true;
+    RespValue::SimpleString("OK".into())
+}
@@ -0,0 +1,153 @@
+use std::time::Duration;
+
+use crate::protocol::resp::encoder::RespValue;
+use crate::server::clients::{self, PauseMode};
+use crate::server::session::Session;
+
+pub async fn execute(parts: &[String], session: &mut Session) -> RespValue {
+    let sub = match parts.get(1) {
+        Some(s) => s.to_uppercase(),
+        None => return RespValue::Error("ERR usage CLIENT <subcommand> [arguments ...]".into()),
+    };
+    let args = &parts[2..];
+
+    match sub.as_str() {
+        "ID" if args.is_empty() => RespValue::Integer(session.id as i64),
+        "LIST" => list(args),
+        "INFO" if args.is_empty() => match clients::get(session.id) {
+            Some(c) => RespValue::Verbatim("txt", format!("{}\n", c.describe())),
+            None => RespValue::Null,
+        },
+        "SETNAME" if args.len() == 1 => setname(&args[0], session),
+        "GETNAME" if args.is_empty() => session.name.clone().map_or(RespValue::Null, RespValue::bulk),
+        "KILL" if !args.is_empty() => kill(args, session),
+        "PAUSE" if !args.is_empty() && args.len() <= 2 => pause(args),
+        "UNPAUSE" if args.is_empty() => {
+            clients::unpause();
+            RespValue::SimpleString("OK".into())
+        }
+        "NO-EVICT" if args.len() == 1 => match args[0].to_lowercase().as_str() {
+            "on" | "off" => {
+                if let Some(c) = clients::get(session.id) {
+                    c.set_no_evict(args[0].eq_ignore_ascii_case("on"));
+                }
+                RespValue::SimpleString("OK".into())
+            }
+            _ => RespValue::Error("ERR syntax error".into()),
+        },
+        _ => RespValue::Error(format!(
+            "ERR unknown subcommand or wrong number of arguments for '{}'",
+            parts[1]
+        )),
+    }
+}
+
+/// CLIENT LIST [ID id [id ...]]
+fn list(args: &[String]) -> RespValue {
+    let mut ids: Option<Vec<u64>> = None;
+
+    if let Some(opt) = args.first() {
+        if !opt.eq_ignore_ascii_case("ID") || args.len() < 2 {
+            return RespValue::Error("ERR syntax error".into());
+        }
+        let mut wanted = Vec::with_capacity(args.len() - 1);
+        for a in &args[1..] {
+            match a.parse::<u64>() {
+                Ok(id) if id > 0 => wanted.push(id),
+                _ => return RespValue::Error(format!("ERR Invalid client ID '{}'", a)),
+            }
+        }
+        ids = Some(wanted);
+    }
+
+    let mut out = String::new();
+    for c in clients::all() {
+        if ids.as_ref().is_some_and(|ids| !ids.contains(&c.id)) {
+            continue;
+        }
+        out.push_str(&c.describe());
+        out.push('\n');
+    }
+    RespValue::Verbatim("txt", out)
+}
+
+/// CLIENT SETNAME name; an empty name clears it.
+fn setname(name: &str, session: &mut Session) -> RespValue {
+    if name.bytes().any(|b| !(b'!'..=b'~').contains(&b)) {
+        return RespValue::Error(
+            "ERR Client names cannot contain spaces, newlines or special characters.".into(),
+        );
+    }
+    session.name = (!name.is_empty()).then(|| name.to_string());
+    RespValue::SimpleString("OK".into())
+}
+
+/// CLIENT KILL addr:port, or CLIENT KILL [ID id] [ADDR addr] [LADDR addr]
+/// [USER name] [SKIPME yes|no]. The old form answers OK, the new one the
+/// number of clients killed.
+fn kill(args: &[String], session: &Session) -> RespValue {
+    if args.len() == 1 {
+        return match clients::all().into_iter().find(|c| c.addr == args[0]) {
+            Some(c) => {
+                c.kill();
+                RespValue::SimpleString("OK".into())
+            }
+            None => RespValue::Error("ERR No such client".into()),
+        };
+    }
+    if !args.len().is_multiple_of(2) {
+        return RespValue::Error("ERR syntax error".into());
+    }
+
+    let (mut id, mut addr, mut laddr, mut user) = (None, None, None, None);
+    let mut skipme = true;
+    for pair in args.chunks(2) {
+        let value = &pair[1];
+        match pair[0].to_uppercase().as_str() {
+            "ID" => match value.parse::<u64>() {
+                Ok(n) if n > 0 => id = Some(n),
+                _ => return RespValue::Error("ERR client-id should be greater than 0".into()),
+            },
+            "ADDR" => addr = Some(value),
+            "LADDR" => laddr = Some(value),
+            "USER" => user = Some(value),
+            "SKIPME" => match value.to_lowercase().as_str() {
+                "yes" => skipme = true,
+                "no" => skipme = false,
+                _ => return RespValue::Error("ERR syntax error".into()),
+            },
+            _ => return RespValue::Error("ERR syntax error".into()),
+        }
+    }
+
+    let mut killed = 0;
+    for c in clients::all() {
+        let matches = id.is_none_or(|id| c.id == id)
+            && addr.is_none_or(|a| c.addr == *a)
+            && laddr.is_none_or(|a| c.laddr == *a)
+            && user.is_none_or(|u| c.user() == *u)
+            && !(skipme && c.id == session.id);
+        if matches {
+            c.kill();
+            killed += 1;
+        }
+    }
+    RespValue::Integer(killed)
+}
+
+/// CLIENT PAUSE timeout-ms [WRITE|ALL]
+fn pause(args: &[String]) -> RespValue {
+    let ms = match args[0].parse::<u64>() {
+        Ok(ms) => ms,
+        Err(_) => return RespValue::Error("ERR timeout is not an integer or out of range".into()),
+    };
+    let mode = match args.get(1).map(|m| m.to_uppercase()).as_deref() {
+        None | Some("ALL") => PauseMode::All,
+        Some("WRITE") => PauseMode::Write,
+        Some(_) => return RespValue::Error("ERR syntax error".into()),
+    };
+    if !clients::pause(Duration::from_millis(ms), mode) {
+        return RespValue::Error("ERR timeout is out of range".into());
+    }
+    RespValue::SimpleString("OK".into())
+}
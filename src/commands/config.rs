@@ -0,0 +1,119 @@
+use crate::protocol::resp::encoder::RespValue;
+use crate::server::config;
+
+pub async fn execute(parts: &[String]) -> RespValue {
+    let sub = match parts.get(1) {
+        Some(s) => s.to_uppercase(),
+        None => return RespValue::Error("ERR usage CONFIG <subcommand> [arguments ...]".into()),
+    };
+    let args = &parts[2..];
+
+    match sub.as_str() {
+        "GET" if !args.is_empty() => get(args),
+        "SET" if !args.is_empty() && args.len().is_multiple_of(2) => {
+            let pairs: Vec<(String, String)> = args
+                .chunks(2)
+                .map(|c| (c[0].clone(), c[1].clone()))
+                .collect();
+            match config::set_runtime(&pairs) {
+                Ok(()) => RespValue::SimpleString("OK".into()),
+                Err(e) => RespValue::Error(format!("ERR {}", e)),
+            }
+        }
+        "REWRITE" if args.is_empty() => match config::rewrite() {
+            Ok(()) => RespValue::SimpleString("OK".into()),
+            Err(e) => RespValue::Error(format!("ERR {}", e)),
+        },
+        _ => RespValue::Error(format!(
+            "ERR unknown subcommand or wrong number of arguments for '{}'",
+            parts[1]
+        )),
+    }
+}
+
+/// CONFIG GET pattern [pattern ...]; each parameter is listed once.
+fn get(patterns: &[String]) -> RespValue {
+    let mut pairs: Vec<(&'static str, String)> = Vec::new();
+    for pattern in patterns {
+        for (name, value) in config::matching(pattern) {
+            if !pairs.iter().any(|(n, _)| *n == name) {
+                pairs.push((name, value));
+            }
+        }
+    }
+    RespValue::Map(
+        pairs
+            .into_iter()
+            .map(|(name, value)| (RespValue::bulk(name), RespValue::bulk(value)))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn reply(args: &[&str]) -> String {
+        let mut parts = vec!["CONFIG".to_string()];
+        parts.extend(args.iter().map(|a| a.to_string()));
+        String::from_utf8(execute(&parts).await.to_bytes()).unwrap()
+    }
+
+    // One test, since every step shares the process-wide configuration.
+    #[tokio::test]
+    async fn set_get_and_rewrite_round_trip() {
+        let dir = std::env::temp_dir().join(format!("keyval-config-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("keyval.conf");
+        std::fs::write(&file, "# kept\ntimeout 5\nbind 127.0.0.1:6399\n").unwrap();
+        config::init(&[file.display().to_string()]).unwrap();
+
+        assert_eq!(reply(&["GET", "timeout"]).await, "*2\r\n$7\r\ntimeout\r\n$1\r\n5\r\n");
+        assert_eq!(reply(&["SET", "timeout", "30", "maxclients", "500"]).await, "+OK\r\n");
+        assert_eq!(
+            reply(&["GET", "maxclients", "time*", "maxclients"]).await,
+            "*4\r\n$10\r\nmaxclients\r\n$3\r\n500\r\n$7\r\ntimeout\r\n$2\r\n30\r\n"
+        );
+
+        // A bad pair anywhere leaves every parameter as it was.
+        let err = reply(&["SET", "maxclients", "7", "timeout", "soon"]).await;
+        assert!(err.starts_with("-ERR CONFIG SET failed (possibly related to argument 'timeout')"), "{}", err);
+        let err = reply(&["SET", "maxclients", "7", "no-such-param", "1"]).await;
+        assert!(err.starts_with("-ERR Unknown option or number of arguments for CONFIG SET - 'no-such-param'"), "{}", err);
+        let err = reply(&["SET", "bind", "127.0.0.1:1"]).await;
+        assert!(err.contains("can't set immutable config"), "{}", err);
+        let err = reply(&["SET", "timeout", "1", "TIMEOUT", "2"]).await;
+        assert!(err.contains("duplicate parameter"), "{}", err);
+        assert!(reply(&["SET", "timeout"]).await.starts_with("-ERR unknown subcommand"));
+        assert_eq!(config::with(|c| (c.maxclients, c.timeout)), (500, 30));
+
+        assert_eq!(reply(&["REWRITE"]).await, "+OK\r\n");
+        let text = std::fs::read_to_string(&file).unwrap();
+        assert!(text.starts_with("# kept\ntimeout 30\nbind 127.0.0.1:6399\n"), "{}", text);
+        assert!(text.contains("\nmaxclients 500\n"), "{}", text);
+
+        // The rewritten file loads back to the same values.
+        let loaded = config::init(&[file.display().to_string()]).unwrap();
+        assert_eq!((loaded.maxclients, loaded.timeout), (500, 30));
+
+        // Concurrent CONFIG SETs of different parameters all stick.
+        let threads: Vec<_> = ["timeout", "slowlog-max-len"]
+            .into_iter()
+            .map(|name| {
+                std::thread::spawn(move || {
+                    for i in 1..=200 {
+                        config::set_runtime(&[(name.to_string(), i.to_string())]).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert_eq!(config::with(|c| (c.timeout, c.slowlog_max_len)), (200, 200));
+
+        config::init(&[]).unwrap();
+        assert_eq!(reply(&["REWRITE"]).await, "-ERR The server is running without a config file\r\n");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,74 @@
+use std::time::Instant;
+
+use crate::db::keyspace::Keyspace;
+use crate::db::storage::Databases;
+use crate::db::value::ValueEntry;
+use crate::protocol::resp::encoder::RespValue;
+use crate::server::notify;
+use crate::server::session::Session;
+
+pub async fn execute(parts: &[String], session: &Session, dbs: &Databases) -> RespValue {
+    if parts.len() < 3 {
+        return RespValue::Error("ERR usage COPY source destination [DB destination-db] [REPLACE]".into());
+    }
+
+    let mut replace = false;
+    let mut target = session.db;
+
+    let mut i = 3;
+    while i < parts.len() {
+        match parts[i].to_uppercase().as_str() {
+            "REPLACE" => replace = true,
+            "DB" if i + 1 < parts.len() => {
+                i += 1;
+                target = match dbs.parse_index(&parts[i]) {
+                    Ok(index) => index,
+                    Err(e) => return RespValue::Error(e.into()),
+                };
+            }
+            _ => return RespValue::Error("ERR syntax error".into()),
+        }
+        i += 1;
+    }
+
+    let (src, dst) = (&parts[1], &parts[2]);
+    if target == session.db && src == dst {
+        return RespValue::Error("ERR source and destination objects are the same".into());
+    }
+
+    let now = Instant::now();
+
+    let copied = if target == session.db {
+        let mut db = dbs.get(target).expect("selected db exists").lock().await;
+        match snapshot(&db, src, now) {
+            Some(entry) => store(&mut db, dst, entry, replace, now),
+            None => false,
+        }
+    } else {
+        let (from, mut to) = dbs.lock_pair(session.db, target).await;
+        match snapshot(&from, src, now) {
+            Some(entry) => store(&mut to, dst, entry, replace, now),
+            None => false,
+        }
+    };
+
+    if copied {
+        notify::notify(notify::GENERIC, "copy_to", dst, target);
+    }
+    RespValue::Boolean(copied)
+}
+
+/// A fresh entry with the live value and TTL of `key`, if it exists.
+fn snapshot(db: &Keyspace, key: &str, now: Instant) -> Option<ValueEntry> {
+    db.get(key)
+        .filter(|e| !e.is_expired(now))
+        .map(|e| ValueEntry::new(e.value.clone(), e.expire_at))
+}
+
+fn store(db: &mut Keyspace, key: &str, entry: ValueEntry, replace: bool, now: Instant) -> bool {
+    if !replace && db.get(key).is_some_and(|e| !e.is_expired(now)) {
+        return false;
+    }
+    db.insert(key.to_string(), entry);
+    true
+}
@@ -0,0 +1,7 @@
+use crate::db::storage::Db;
+use crate::protocol::resp::encoder::RespValue;
+
+pub async fn execute(_parts: &[String], db: &Db) -> RespValue {
+    let db = db.lock().await;
+    RespValue::Integer(db.len() as i64)
+}
@@ -0,0 +1,48 @@
+use crate::protocol::resp::encoder::RespValue;
+
+pub async fn execute(parts: &[String]) -> RespValue {
+    match parts.get(1).map(|s| s.to_uppercase()).as_deref() {
+        Some("PROTOCOL") if parts.len() == 3 => protocol_sample(&parts[2]),
+        _ => RespValue::Error("ERR usage DEBUG PROTOCOL <type>".into()),
+    }
+}
+
+/// Replies with a fixed sample of the requested type, so client libraries can
+/// check how each RESP3 type (and its RESP2 fallback) is decoded.
+fn protocol_sample(name: &str) -> RespValue {
+    let ints = || (0..3).map(RespValue::Integer).collect::<Vec<_>>();
+
+    match name.to_lowercase().as_str() {
+        "string" => RespValue::bulk("Hello World"),
+        "integer" => RespValue::Integer(12345),
+        "double" => RespValue::Double(3.5),
+        "bignum" => RespValue::BigNumber("1234567999999999999999999999999999999".into()),
+        "null" => RespValue::Null,
+        "array" => RespValue::Array(ints()),
+        "set" => RespValue::Set(ints()),
+        "map" => RespValue::Map(
+            (0..3)
+                .map(|i| (RespValue::Integer(i), RespValue::Boolean(i == 1)))
+                .collect(),
+        ),
+        "attrib" => RespValue::Attribute(
+            vec![(RespValue::bulk("key-popularity"), RespValue::Array(vec![
+                RespValue::bulk("key:123"),
+                RespValue::Integer(90),
+            ]))],
+            Box::new(RespValue::bulk("Some real reply following the attribute")),
+        ),
+        "push" => RespValue::Push(vec![
+            RespValue::bulk("server-cpu-usage"),
+            RespValue::Integer(42),
+        ]),
+        "verbatim" => RespValue::Verbatim("txt", "This is a verbatim\nstring".into()),
+        "true" => RespValue::Boolean(true),
+        "false" => RespValue::Boolean(false),
+        _ => RespValue::Error(
+            "ERR Wrong protocol type name. Please use one of the following: \
+             string|integer|double|bignum|null|array|set|map|attrib|push|verbatim|true|false"
+                .into(),
+        ),
+    }
+}
@@ -1,14 +1,26 @@
+use std::time::Instant;
+
 use crate::db::storage::Db;
 use crate::protocol::resp::encoder::RespValue;
+use crate::server::notify;
 
-pub async fn execute(parts: Vec<String>, db: &Db) -> RespValue {
+pub async fn execute(parts: &[String], db: &Db, index: usize) -> RespValue {
     if parts.len() < 2 {
-        return RespValue::Error("ERR usage DEL key".into());
+        return RespValue::Error("ERR usage DEL key [key ...]".into());
     }
 
-    let key = &parts[1];
+    let now = Instant::now();
     let mut db = db.lock().await;
 
-    let deleted = if db.remove(key).is_some() { 1 } else { 0 };
+    let mut deleted = 0;
+    for key in &parts[1..] {
+        if let Some(entry) = db.remove(key) {
+            if !entry.is_expired(now) {
+                deleted += 1;
+                notify::notify(notify::GENERIC, "del", key, index);
+            }
+        }
+    }
+
     RespValue::Integer(deleted)
 }
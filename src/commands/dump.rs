@@ -0,0 +1,20 @@
+use std::time::Instant;
+
+use crate::db::dump;
+use crate::db::storage::Db;
+use crate::protocol::resp::encoder::RespValue;
+
+pub async fn execute(parts: &[String], db: &Db) -> RespValue {
+    if parts.len() != 2 {
+        return RespValue::Error("ERR usage DUMP key".into());
+    }
+
+    let db = db.lock().await;
+
+    match db.get(&parts[1]) {
+        Some(entry) if !entry.is_expired(Instant::now()) => {
+            RespValue::Bulk(Some(dump::serialize(&entry.value)))
+        }
+        _ => RespValue::Bulk(None),
+    }
+}
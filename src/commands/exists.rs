@@ -1,13 +1,20 @@
 use crate::db::storage::Db;
 use crate::protocol::resp::encoder::RespValue;
 
-pub async fn execute(parts: Vec<String>, db: &Db) -> RespValue {
+pub async fn execute(parts: &[String], db: &Db, index: usize) -> RespValue {
     if parts.len() < 2 {
-        return RespValue::Error("ERR usage EXISTS key".into());
+        return RespValue::Error("ERR usage EXISTS key [key ...]".into());
     }
 
-    let key = &parts[1];
-    let db = db.lock().await;
+    let mut db = db.lock().await;
 
-    RespValue::Integer(if db.contains_key(key) { 1 } else { 0 })
+    // Repeated keys are counted once per occurrence, like Redis.
+    let mut found = 0;
+    for key in &parts[1..] {
+        if db.get_live(key, index).is_some() {
+            found += 1;
+        }
+    }
+
+    RespValue::Integer(found)
 }
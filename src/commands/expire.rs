@@ -2,8 +2,9 @@ use std::time::{Duration, Instant};
 
 use crate::db::storage::Db;
 use crate::protocol::resp::encoder::RespValue;
+use crate::server::notify;
 
-pub async fn execute(parts: Vec<String>, db: &Db) -> RespValue {
+pub async fn execute(parts: &[String], db: &Db, index: usize) -> RespValue {
     if parts.len() < 3 {
         return RespValue::Error("ERR usage EXPIRE key seconds".into());
     }
@@ -16,10 +17,12 @@ pub async fn execute(parts: Vec<String>, db: &Db) -> RespValue {
 
     let mut db = db.lock().await;
 
-    if let Some(entry) = db.get_mut(key) {
+    if let Some(entry) = db.get_mut(key).filter(|e| !e.is_expired(Instant::now())) {
+        entry.touch();
         entry.expire_at = Some(Instant::now() + Duration::from_secs(seconds));
-        RespValue::Integer(1)
+        notify::notify(notify::GENERIC, "expire", key, index);
+        RespValue::Boolean(true)
     } else {
-        RespValue::Integer(0)
+        RespValue::Boolean(false)
     }
 }
@@ -1,8 +1,15 @@
-use crate::db::storage::Db;
+use crate::commands::flushdb;
+use crate::db::storage::Databases;
 use crate::protocol::resp::encoder::RespValue;
 
-pub async fn execute(_parts: Vec<String>, db: &Db) -> RespValue {
-    let mut db = db.lock().await;
-    db.clear();
+pub async fn execute(parts: &[String], dbs: &Databases) -> RespValue {
+    let lazy = match flushdb::parse_mode(parts) {
+        Ok(lazy) => lazy,
+        Err(e) => return e,
+    };
+
+    for db in dbs.iter() {
+        flushdb::flush(db, lazy).await;
+    }
     RespValue::SimpleString("OK".into())
 }
@@ -0,0 +1,37 @@
+use crate::db::keyspace::Keyspace;
+use crate::db::storage::Db;
+use crate::protocol::resp::encoder::RespValue;
+
+pub async fn execute(parts: &[String], db: &Db) -> RespValue {
+    let lazy = match parse_mode(parts) {
+        Ok(lazy) => lazy,
+        Err(e) => return e,
+    };
+
+    flush(db, lazy).await;
+    RespValue::SimpleString("OK".into())
+}
+
+/// Parses the optional `ASYNC`/`SYNC` argument shared by FLUSHDB and FLUSHALL.
+pub fn parse_mode(parts: &[String]) -> Result<bool, RespValue> {
+    match parts.get(1).map(|s| s.to_uppercase()).as_deref() {
+        _ if parts.len() > 2 => Err(RespValue::Error("ERR syntax error".into())),
+        None | Some("SYNC") => Ok(false),
+        Some("ASYNC") => Ok(true),
+        Some(_) => Err(RespValue::Error("ERR syntax error".into())),
+    }
+}
+
+/// Empties one database. With `lazy`, the old table is swapped out under the
+/// lock and freed on a blocking worker, so the lock is held for O(1).
+pub async fn flush(db: &Db, lazy: bool) {
+    let mut guard = db.lock().await;
+
+    if lazy {
+        let old: Keyspace = std::mem::take(&mut *guard);
+        drop(guard);
+        tokio::task::spawn_blocking(move || drop(old));
+    } else {
+        guard.clear();
+    }
+}
@@ -1,8 +1,8 @@
-use std::time::Instant;
 use crate::db::storage::Db;
 use crate::protocol::resp::encoder::RespValue;
+use crate::server::stats;
 
-pub async fn execute(parts: Vec<String>, db: &Db) -> RespValue {
+pub async fn execute(parts: &[String], db: &Db, index: usize) -> RespValue {
     if parts.len() < 2 {
         return RespValue::Error("ERR usage GET key".into());
     }
@@ -10,15 +10,12 @@ pub async fn execute(parts: Vec<String>, db: &Db) -> RespValue {
     let key = &parts[1];
     let mut db = db.lock().await;
 
-    if let Some(entry) = db.get(key) {
-        if let Some(exp) = entry.expire_at {
-            if Instant::now() > exp {
-                db.remove(key);
-                return RespValue::Bulk(None);
-            }
-        }
+    if let Some(entry) = db.get_live(key, index) {
+        entry.touch();
+        stats::count(&stats::KEYSPACE_HITS);
         return RespValue::Bulk(Some(entry.value.clone().into_bytes()));
     }
 
+    stats::count(&stats::KEYSPACE_MISSES);
     RespValue::Bulk(None)
 }
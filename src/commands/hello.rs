@@ -0,0 +1,68 @@
+use crate::protocol::resp::encoder::{Protocol, RespValue};
+use crate::server::acl;
+use crate::server::session::Session;
+
+pub async fn execute(parts: &[String], session: &mut Session) -> RespValue {
+    let mut protocol = session.protocol;
+    let mut name: Option<String> = None;
+    let mut user: Option<String> = None;
+
+    if let Some(ver) = parts.get(1) {
+        protocol = match ver.parse::<i64>() {
+            Ok(2) => Protocol::Resp2,
+            Ok(3) => Protocol::Resp3,
+            Ok(_) => return RespValue::Error("NOPROTO unsupported protocol version".into()),
+            Err(_) => return RespValue::Error("ERR Protocol version is not an integer or out of range".into()),
+        };
+    }
+
+    let mut i = 2;
+    while i < parts.len() {
+        match parts[i].to_uppercase().as_str() {
+            "AUTH" if i + 2 < parts.len() => {
+                if !acl::authenticate(&parts[i + 1], &parts[i + 2], session.id) {
+                    return RespValue::Error(
+                        "WRONGPASS invalid username-password pair or user is disabled.".into(),
+                    );
+                }
+                user = Some(parts[i + 1].clone());
+                i += 3;
+            }
+            "SETNAME" if i + 1 < parts.len() => {
+                let n = &parts[i + 1];
+                if n.chars().any(|c| c <= ' ' || c > '~') {
+                    return RespValue::Error(
+                        "ERR Client names cannot contain spaces, newlines or special characters.".into(),
+                    );
+                }
+                name = Some(n.clone());
+                i += 2;
+            }
+            _ => return RespValue::Error(format!("ERR Syntax error in HELLO option '{}'", parts[i])),
+        }
+    }
+
+    if user.is_none() && !session.authenticated {
+        return RespValue::Error("NOAUTH HELLO must be called with the client already authenticated, otherwise the HELLO <proto> AUTH <user> <pass> option can be used to authenticate the client and select the RESP protocol version at the same time".into());
+    }
+
+    // Options are only applied once the whole command has been validated.
+    session.protocol = protocol;
+    if let Some(u) = user {
+        session.user = u;
+        session.authenticated = true;
+    }
+    if let Some(n) = name {
+        session.name = if n.is_empty() { None } else { Some(n) };
+    }
+
+    RespValue::Map(vec![
+        (RespValue::bulk("server"), RespValue::bulk("keyval")),
+        (RespValue::bulk("version"), RespValue::bulk(env!("CARGO_PKG_VERSION"))),
+        (RespValue::bulk("proto"), RespValue::Integer(protocol.version())),
+        (RespValue::bulk("id"), RespValue::Integer(session.id as i64)),
+        (RespValue::bulk("mode"), RespValue::bulk("standalone")),
+        (RespValue::bulk("role"), RespValue::bulk("master")),
+        (RespValue::bulk("modules"), RespValue::Array(vec![])),
+    ])
+}
@@ -1,10 +1,9 @@
-use std::time::Instant;
-
 use crate::db::storage::Db;
 use crate::db::value::ValueEntry;
 use crate::protocol::resp::encoder::RespValue;
+use crate::server::notify;
 
-pub async fn execute(parts: Vec<String>, db: &Db) -> RespValue {
+pub async fn execute(parts: &[String], db: &Db, index: usize) -> RespValue {
     if parts.len() < 2 {
         return RespValue::Error("ERR usage INCR key".into());
     }
@@ -12,26 +11,39 @@ pub async fn execute(parts: Vec<String>, db: &Db) -> RespValue {
     let key = parts[1].clone();
     let mut db = db.lock().await;
 
-    if let Some(entry) = db.get(&key) {
-        if let Some(exp) = entry.expire_at {
-            if Instant::now() > exp {
-                db.remove(&key);
-            }
-        }
-    }
+    // An expired value counts as missing and starts over from 0.
+    db.get_live(&key, index);
 
-    let entry = db.entry(key).or_insert(ValueEntry {
-        value: "0".into(),
-        expire_at: None,
-    });
+    let entry = db.get_or_insert_with(key.clone(), || ValueEntry::new("0".into(), None));
+    entry.touch();
 
     let current: i64 = match entry.value.parse() {
         Ok(n) => n,
         Err(_) => return RespValue::Error("ERR value is not an integer".into()),
     };
 
-    let next = current + 1;
-    entry.value = next.to_string();
+    let Some(next) = current.checked_add(1) else {
+        return RespValue::Error("ERR increment or decrement would overflow".into());
+    };
+    entry.set_value(next.to_string());
+    notify::notify(notify::STRING, "incrby", &key, index);
 
     RespValue::Integer(next)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::storage::new_db;
+
+    #[tokio::test]
+    async fn incr_refuses_to_overflow() {
+        let db = new_db();
+        let parts = |k: &str| vec!["INCR".to_string(), k.to_string()];
+        db.lock().await.insert("max".into(), ValueEntry::new(i64::MAX.to_string(), None));
+
+        assert!(matches!(execute(&parts("max"), &db, 0).await, RespValue::Error(e) if e.contains("overflow")));
+        assert_eq!(db.lock().await.get("max").unwrap().value, i64::MAX.to_string());
+        assert!(matches!(execute(&parts("n"), &db, 0).await, RespValue::Integer(1)));
+    }
+}
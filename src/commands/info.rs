@@ -0,0 +1,219 @@
+use crate::db::storage::Databases;
+use crate::protocol::resp::encoder::RespValue;
+use crate::server::{clients, config, linux_proc, metrics_prom, pubsub, stats, tcp_server};
+
+/// Sections in the order INFO prints them; the last one is left out unless
+/// asked for, as in Redis.
+const SECTIONS: [&str; 8] = [
+    "server",
+    "clients",
+    "memory",
+    "persistence",
+    "stats",
+    "cpu",
+    "keyspace",
+    "commandstats",
+];
+
+/// INFO [section ...]
+pub async fn execute(parts: &[String], dbs: &Databases) -> RespValue {
+    let asked: Vec<String> = parts[1..].iter().map(|s| s.to_lowercase()).collect();
+    let wanted = |name: &str| {
+        if asked.is_empty() || asked.iter().any(|a| a == "default") {
+            return name != "commandstats" || asked.iter().any(|a| a == name);
+        }
+        asked
+            .iter()
+            .any(|a| a == name || a == "all" || a == "everything")
+    };
+
+    let mut out = String::new();
+    for name in SECTIONS.iter().filter(|name| wanted(name)) {
+        if !out.is_empty() {
+            out.push_str("\r\n");
+        }
+        match *name {
+            "server" => server(&mut out),
+            "clients" => clients(&mut out),
+            "memory" => memory(&mut out),
+            "persistence" => persistence(&mut out),
+            "stats" => stats(&mut out),
+            "cpu" => cpu(&mut out),
+            "keyspace" => keyspace(&mut out, dbs).await,
+            _ => commandstats(&mut out),
+        }
+    }
+    RespValue::Verbatim("txt", out)
+}
+
+fn field(out: &mut String, name: &str, value: impl std::fmt::Display) {
+    out.push_str(&format!("{}:{}\r\n", name, value));
+}
+
+/// Bytes the way Redis' `*_human` fields show them, e.g. `1.50M`.
+fn human(bytes: u64) -> String {
+    const UNITS: [(&str, u64); 3] = [("G", 1 << 30), ("M", 1 << 20), ("K", 1 << 10)];
+    UNITS
+        .iter()
+        .find(|(_, size)| bytes >= *size)
+        .map_or(format!("{}B", bytes), |(unit, size)| {
+            format!("{:.2}{}", bytes as f64 / *size as f64, unit)
+        })
+}
+
+fn server(out: &mut String) {
+    let listening = tcp_server::listening();
+    let tcp_port = listening
+        .tcp
+        .as_deref()
+        .and_then(|a| a.rsplit(':').next())
+        .unwrap_or("0");
+    let uptime = stats::uptime_secs();
+
+    out.push_str("# Server\r\n");
+    field(out, "keyval_version", env!("CARGO_PKG_VERSION"));
+    field(
+        out,
+        "os",
+        format!("{} {}", std::env::consts::OS, std::env::consts::ARCH),
+    );
+    field(out, "arch_bits", usize::BITS);
+    field(out, "process_id", std::process::id());
+    field(out, "tcp_port", tcp_port);
+    if let Some(unix) = listening.unix {
+        field(out, "unixsocket", unix.path.display());
+        if let Some(perm) = unix.perm {
+            field(out, "unixsocketperm", format!("{:o}", perm));
+        }
+    }
+    field(out, "server_time_usec", stats::unix_time_secs() * 1_000_000);
+    field(out, "uptime_in_seconds", uptime);
+    field(out, "uptime_in_days", uptime / 86400);
+    let executable = std::env::current_exe()
+        .map(|p| p.display().to_string())
+        .unwrap_or_default();
+    field(out, "executable", executable);
+    let config_file = config::file()
+        .and_then(|p| p.canonicalize().ok())
+        .map_or(String::new(), |p| p.display().to_string());
+    field(out, "config_file", config_file);
+}
+
+fn clients(out: &mut String) {
+    let all = clients::all();
+    let max_in = all.iter().map(|c| c.qbuf()).max().unwrap_or(0);
+    let max_out = all.iter().map(|c| c.omem()).max().unwrap_or(0);
+
+    out.push_str("# Clients\r\n");
+    field(out, "connected_clients", metrics_prom::ACTIVE_CONNS.get());
+    field(out, "maxclients", config::with(|c| c.maxclients));
+    field(out, "client_recent_max_input_buffer", max_in);
+    field(out, "client_recent_max_output_buffer", max_out);
+}
+
+fn memory(out: &mut String) {
+    let rss = linux_proc::rss_bytes().unwrap_or(0);
+    let peak = linux_proc::peak_rss_bytes().unwrap_or(0);
+    let total = linux_proc::total_memory_bytes().unwrap_or(0);
+
+    out.push_str("# Memory\r\n");
+    field(out, "used_memory_rss", rss);
+    field(out, "used_memory_rss_human", human(rss));
+    field(out, "used_memory_peak_rss", peak);
+    field(out, "used_memory_peak_rss_human", human(peak));
+    field(out, "total_system_memory", total);
+    field(out, "total_system_memory_human", human(total));
+    // There is no memory limit or eviction.
+    field(out, "maxmemory", 0);
+    field(out, "maxmemory_human", "0B");
+    field(out, "maxmemory_policy", "noeviction");
+}
+
+/// Nothing is persisted yet; the fields are there for tools that expect them.
+fn persistence(out: &mut String) {
+    out.push_str("# Persistence\r\n");
+    field(out, "loading", 0);
+    field(out, "rdb_bgsave_in_progress", 0);
+    field(out, "rdb_saves", 0);
+    field(out, "aof_enabled", 0);
+}
+
+fn stats(out: &mut String) {
+    let kbps = |bytes_per_sec: i64| format!("{:.2}", bytes_per_sec as f64 / 1024.0);
+
+    out.push_str("# Stats\r\n");
+    field(
+        out,
+        "total_connections_received",
+        stats::get(&stats::TOTAL_CONNECTIONS),
+    );
+    field(out, "total_commands_processed", stats::total_commands());
+    field(
+        out,
+        "instantaneous_ops_per_sec",
+        stats::get(&stats::OPS_PER_SEC),
+    );
+    field(out, "total_net_input_bytes", metrics_prom::BYTES_IN.get());
+    field(out, "total_net_output_bytes", metrics_prom::BYTES_OUT.get());
+    field(
+        out,
+        "instantaneous_input_kbps",
+        kbps(metrics_prom::NET_INPUT_BYTES_PER_SEC.get()),
+    );
+    field(
+        out,
+        "instantaneous_output_kbps",
+        kbps(metrics_prom::NET_OUTPUT_BYTES_PER_SEC.get()),
+    );
+    field(out, "rejected_connections", stats::rejected_connections());
+    field(out, "expired_keys", stats::get(&stats::EXPIRED_KEYS));
+    field(out, "keyspace_hits", stats::get(&stats::KEYSPACE_HITS));
+    field(out, "keyspace_misses", stats::get(&stats::KEYSPACE_MISSES));
+    field(out, "pubsub_channels", pubsub::channels(None).len());
+    field(out, "pubsub_patterns", pubsub::numpat());
+}
+
+fn cpu(out: &mut String) {
+    let (user, sys) = linux_proc::cpu_seconds().unwrap_or((0.0, 0.0));
+
+    out.push_str("# CPU\r\n");
+    field(out, "used_cpu_sys", format!("{:.6}", sys));
+    field(out, "used_cpu_user", format!("{:.6}", user));
+}
+
+/// Databases with keys. `expires` and `avg_ttl` come from the TTL cleaner's
+/// last pass, so they can lag by `expire-interval-ms`.
+async fn keyspace(out: &mut String, dbs: &Databases) {
+    out.push_str("# Keyspace\r\n");
+    for (index, db) in dbs.iter().enumerate() {
+        let keys = db.lock().await.len();
+        if keys == 0 {
+            continue;
+        }
+        let (expires, avg_ttl) = stats::expires(index);
+        field(
+            out,
+            &format!("db{}", index),
+            format!("keys={},expires={},avg_ttl={}", keys, expires.min(keys), avg_ttl),
+        );
+    }
+}
+
+fn commandstats(out: &mut String) {
+    out.push_str("# Commandstats\r\n");
+    for s in stats::command_stats() {
+        let per_call = if s.calls > 0 {
+            s.usec as f64 / s.calls as f64
+        } else {
+            0.0
+        };
+        field(
+            out,
+            &format!("cmdstat_{}", s.name),
+            format!(
+                "calls={},usec={},usec_per_call={:.2}",
+                s.calls, s.usec, per_call
+            ),
+        );
+    }
+}
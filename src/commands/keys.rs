@@ -1,13 +1,43 @@
+use std::time::Instant;
+
 use crate::db::storage::Db;
 use crate::protocol::resp::encoder::RespValue;
+use crate::util::glob::glob_match;
+
+/// Keys visited per lock acquisition, so a large keyspace does not stall
+/// other clients for the whole walk.
+const BATCH: usize = 1024;
+
+pub async fn execute(parts: &[String], db: &Db) -> RespValue {
+    if parts.len() > 2 {
+        return RespValue::Error("ERR usage KEYS [pattern]".into());
+    }
 
-pub async fn execute(_parts: Vec<String>, db: &Db) -> RespValue {
-    let db = db.lock().await;
+    let pattern = parts.get(1).map(String::as_str).unwrap_or("*");
+    let match_all = pattern == "*";
 
     let mut items = Vec::new();
-    for k in db.keys() {
-        items.push(RespValue::Bulk(Some(k.clone().into_bytes())));
+    let mut cursor = 0;
+
+    loop {
+        let db = db.lock().await;
+        let now = Instant::now();
+
+        let (next, batch) = db.scan(cursor, BATCH);
+        for (k, v) in batch {
+            if v.is_expired(now) {
+                continue;
+            }
+            if match_all || glob_match(pattern, k) {
+                items.push(RespValue::Bulk(Some(k.clone().into_bytes())));
+            }
+        }
+
+        if next == 0 {
+            break;
+        }
+        cursor = next;
     }
 
     RespValue::Array(items)
-}
\ No newline at end of file
+}
@@ -0,0 +1,18 @@
+use std::time::Instant;
+
+use crate::db::storage::Db;
+use crate::protocol::resp::encoder::RespValue;
+
+pub async fn execute(parts: &[String], db: &Db) -> RespValue {
+    if parts.len() != 2 {
+        return RespValue::Error("ERR usage TYPE key".into());
+    }
+
+    let db = db.lock().await;
+
+    // Every value is a string for now.
+    match db.get(&parts[1]) {
+        Some(entry) if !entry.is_expired(Instant::now()) => RespValue::SimpleString("string".into()),
+        _ => RespValue::SimpleString("none".into()),
+    }
+}
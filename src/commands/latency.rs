@@ -0,0 +1,69 @@
+use crate::protocol::resp::encoder::RespValue;
+use crate::server::latency;
+
+pub async fn execute(parts: &[String]) -> RespValue {
+    let sub = match parts.get(1) {
+        Some(s) => s.to_uppercase(),
+        None => return RespValue::Error("ERR usage LATENCY <subcommand> [arguments ...]".into()),
+    };
+    let args = &parts[2..];
+
+    match sub.as_str() {
+        "LATEST" if args.is_empty() => RespValue::Array(
+            latency::latest()
+                .into_iter()
+                .map(|(name, ts, ms, max)| {
+                    RespValue::Array(vec![
+                        RespValue::bulk(name),
+                        RespValue::Integer(ts as i64),
+                        RespValue::Integer(ms as i64),
+                        RespValue::Integer(max as i64),
+                    ])
+                })
+                .collect(),
+        ),
+        "HISTORY" if args.len() == 1 => RespValue::Array(
+            latency::history(&args[0].to_lowercase())
+                .into_iter()
+                .map(|(ts, ms)| {
+                    RespValue::Array(vec![
+                        RespValue::Integer(ts as i64),
+                        RespValue::Integer(ms as i64),
+                    ])
+                })
+                .collect(),
+        ),
+        "RESET" => RespValue::Integer(latency::reset(args) as i64),
+        "HISTOGRAM" => histogram(args),
+        "DOCTOR" if args.is_empty() => RespValue::Verbatim("txt", latency::doctor()),
+        _ => RespValue::Error(format!(
+            "ERR unknown subcommand or wrong number of arguments for '{}'",
+            parts[1]
+        )),
+    }
+}
+
+/// LATENCY HISTOGRAM [command ...]: every command that ran, or the named
+/// ones, with cumulative counts per run-time bucket in microseconds.
+fn histogram(names: &[String]) -> RespValue {
+    let wanted: Vec<String> = names.iter().map(|n| n.to_lowercase()).collect();
+    let commands = latency::command_histograms()
+        .into_iter()
+        .filter(|h| h.calls > 0 && (wanted.is_empty() || wanted.contains(&h.name)))
+        .map(|h| {
+            let buckets = h
+                .buckets
+                .into_iter()
+                .map(|(usec, count)| (RespValue::Integer(usec as i64), RespValue::Integer(count as i64)))
+                .collect();
+            (
+                RespValue::bulk(h.name),
+                RespValue::Map(vec![
+                    (RespValue::bulk("calls"), RespValue::Integer(h.calls as i64)),
+                    (RespValue::bulk("histogram_usec"), RespValue::Map(buckets)),
+                ]),
+            )
+        })
+        .collect();
+    RespValue::Map(commands)
+}
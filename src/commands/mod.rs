@@ -6,3 +6,30 @@ pub(crate) mod exists;
 pub(crate) mod flushall;
 pub(crate) mod incr;
 pub(crate) mod expire;
+pub(crate) mod unlink;
+pub(crate) mod touch;
+pub(crate) mod scan;
+pub(crate) mod keytype;
+pub(crate) mod rename;
+pub(crate) mod copy;
+pub(crate) mod randomkey;
+pub(crate) mod dbsize;
+pub(crate) mod object;
+pub(crate) mod select;
+pub(crate) mod move_key;
+pub(crate) mod swapdb;
+pub(crate) mod flushdb;
+pub(crate) mod dump;
+pub(crate) mod restore;
+pub(crate) mod hello;
+pub(crate) mod debug;
+pub(crate) mod client;
+pub(crate) mod publish;
+pub(crate) mod pubsub;
+pub(crate) mod info;
+pub(crate) mod auth;
+pub(crate) mod acl;
+pub(crate) mod config;
+pub(crate) mod shutdown;
+pub(crate) mod slowlog;
+pub(crate) mod latency;
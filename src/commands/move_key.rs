@@ -0,0 +1,39 @@
+use std::time::Instant;
+
+use crate::db::storage::Databases;
+use crate::protocol::resp::encoder::RespValue;
+use crate::server::notify;
+use crate::server::session::Session;
+
+pub async fn execute(parts: &[String], session: &Session, dbs: &Databases) -> RespValue {
+    if parts.len() != 3 {
+        return RespValue::Error("ERR usage MOVE key db".into());
+    }
+
+    let target = match dbs.parse_index(&parts[2]) {
+        Ok(index) => index,
+        Err(e) => return RespValue::Error(e.into()),
+    };
+    if target == session.db {
+        return RespValue::Error("ERR source and destination objects are the same".into());
+    }
+
+    let key = &parts[1];
+    let now = Instant::now();
+    let (mut src, mut dst) = dbs.lock_pair(session.db, target).await;
+
+    match src.get(key) {
+        Some(entry) if !entry.is_expired(now) => {}
+        _ => return RespValue::Boolean(false),
+    }
+    if dst.get(key).is_some_and(|e| !e.is_expired(now)) {
+        return RespValue::Boolean(false);
+    }
+
+    if let Some(entry) = src.remove(key) {
+        dst.insert(key.clone(), entry);
+        notify::notify(notify::GENERIC, "move_from", key, session.db);
+        notify::notify(notify::GENERIC, "move_to", key, target);
+    }
+    RespValue::Boolean(true)
+}
@@ -0,0 +1,26 @@
+use std::time::Instant;
+
+use crate::db::storage::Db;
+use crate::protocol::resp::encoder::RespValue;
+
+pub async fn execute(parts: &[String], db: &Db) -> RespValue {
+    if parts.len() != 3 {
+        return RespValue::Error("ERR usage OBJECT ENCODING|IDLETIME|FREQ key".into());
+    }
+
+    let sub = parts[1].to_uppercase();
+    let db = db.lock().await;
+
+    // OBJECT only inspects the entry; it must not count as an access.
+    let entry = match db.get(&parts[2]) {
+        Some(e) if !e.is_expired(Instant::now()) => e,
+        _ => return RespValue::Bulk(None),
+    };
+
+    match sub.as_str() {
+        "ENCODING" => RespValue::Bulk(Some(entry.encoding().as_bytes().to_vec())),
+        "IDLETIME" => RespValue::Integer(entry.idle_secs() as i64),
+        "FREQ" => RespValue::Integer(entry.lfu_decayed() as i64),
+        _ => RespValue::Error(format!("ERR unknown subcommand '{}'", parts[1])),
+    }
+}
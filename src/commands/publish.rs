@@ -0,0 +1,10 @@
+use crate::protocol::resp::encoder::RespValue;
+use crate::server::pubsub;
+
+pub async fn execute(parts: &[String]) -> RespValue {
+    if parts.len() != 3 {
+        return RespValue::Error("ERR usage PUBLISH channel message".into());
+    }
+
+    RespValue::Integer(pubsub::publish(&parts[1], &parts[2]) as i64)
+}
@@ -0,0 +1,29 @@
+use crate::protocol::resp::encoder::RespValue;
+use crate::server::pubsub;
+
+pub async fn execute(parts: &[String]) -> RespValue {
+    let sub = match parts.get(1) {
+        Some(s) => s.to_uppercase(),
+        None => return RespValue::Error("ERR usage PUBSUB <subcommand> [arguments ...]".into()),
+    };
+
+    match sub.as_str() {
+        "CHANNELS" if parts.len() <= 3 => {
+            let channels = pubsub::channels(parts.get(2).map(String::as_str));
+            RespValue::Array(channels.into_iter().map(RespValue::bulk).collect())
+        }
+        "NUMSUB" => {
+            let mut out = Vec::with_capacity((parts.len() - 2) * 2);
+            for channel in &parts[2..] {
+                out.push(RespValue::bulk(channel.clone()));
+                out.push(RespValue::Integer(pubsub::numsub(channel) as i64));
+            }
+            RespValue::Array(out)
+        }
+        "NUMPAT" if parts.len() == 2 => RespValue::Integer(pubsub::numpat() as i64),
+        _ => RespValue::Error(format!(
+            "ERR unknown subcommand or wrong number of arguments for '{}'",
+            parts[1]
+        )),
+    }
+}
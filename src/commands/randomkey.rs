@@ -0,0 +1,24 @@
+use crate::db::storage::Db;
+use crate::protocol::resp::encoder::RespValue;
+use crate::util::rand;
+
+/// Expired keys picked along the way are dropped; give up after this many.
+const MAX_TRIES: usize = 16;
+
+pub async fn execute(_parts: &[String], db: &Db, index: usize) -> RespValue {
+    let mut db = db.lock().await;
+
+    for _ in 0..MAX_TRIES {
+        let key = match db.key_at_or_after(rand::next_u64()) {
+            Some(k) => k.clone(),
+            None => break,
+        };
+
+        if db.get_live(&key, index).is_none() {
+            continue;
+        }
+        return RespValue::Bulk(Some(key.into_bytes()));
+    }
+
+    RespValue::Bulk(None)
+}
@@ -0,0 +1,54 @@
+use std::time::Instant;
+
+use crate::db::storage::Db;
+use crate::protocol::resp::encoder::RespValue;
+use crate::server::notify;
+
+pub async fn execute(parts: &[String], db: &Db, index: usize) -> RespValue {
+    if parts.len() != 3 {
+        return RespValue::Error("ERR usage RENAME key newkey".into());
+    }
+
+    match rename(&parts[1], &parts[2], db, index, false).await {
+        Ok(_) => RespValue::SimpleString("OK".into()),
+        Err(e) => e,
+    }
+}
+
+pub async fn execute_nx(parts: &[String], db: &Db, index: usize) -> RespValue {
+    if parts.len() != 3 {
+        return RespValue::Error("ERR usage RENAMENX key newkey".into());
+    }
+
+    match rename(&parts[1], &parts[2], db, index, true).await {
+        Ok(renamed) => RespValue::Boolean(renamed),
+        Err(e) => e,
+    }
+}
+
+/// Moves the entry, TTL included, from `src` to `dst`. Returns `Ok(false)`
+/// when `nx` is set and `dst` already exists.
+async fn rename(src: &str, dst: &str, db: &Db, index: usize, nx: bool) -> Result<bool, RespValue> {
+    let now = Instant::now();
+    let mut db = db.lock().await;
+
+    match db.get(src) {
+        Some(entry) if !entry.is_expired(now) => {}
+        _ => return Err(RespValue::Error("ERR no such key".into())),
+    }
+
+    if src == dst {
+        return Ok(!nx);
+    }
+
+    if nx && db.get(dst).is_some_and(|e| !e.is_expired(now)) {
+        return Ok(false);
+    }
+
+    if let Some(entry) = db.remove(src) {
+        db.insert(dst.to_string(), entry);
+        notify::notify(notify::GENERIC, "rename_from", src, index);
+        notify::notify(notify::GENERIC, "rename_to", dst, index);
+    }
+    Ok(true)
+}
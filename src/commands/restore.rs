@@ -0,0 +1,102 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::db::dump;
+use crate::db::storage::Db;
+use crate::db::value::ValueEntry;
+use crate::protocol::resp::encoder::RespValue;
+use crate::server::notify;
+
+pub async fn execute(parts: &[String], db: &Db, index: usize) -> RespValue {
+    if parts.len() < 4 {
+        return RespValue::Error(
+            "ERR usage RESTORE key ttl serialized-value [REPLACE] [ABSTTL] [IDLETIME seconds] [FREQ frequency]".into(),
+        );
+    }
+
+    let ttl_ms: i64 = match parts[2].parse() {
+        Ok(n) if n >= 0 => n,
+        Ok(_) => return RespValue::Error("ERR Invalid TTL value, must be >= 0".into()),
+        Err(_) => return RespValue::Error("ERR value is not an integer or out of range".into()),
+    };
+
+    let mut replace = false;
+    let mut absttl = false;
+    let mut idle: Option<u64> = None;
+    let mut freq: Option<u8> = None;
+
+    let mut i = 4;
+    while i < parts.len() {
+        match parts[i].to_uppercase().as_str() {
+            "REPLACE" => replace = true,
+            "ABSTTL" => absttl = true,
+            "IDLETIME" if i + 1 < parts.len() && freq.is_none() => {
+                i += 1;
+                idle = match parts[i].parse() {
+                    Ok(n) => Some(n),
+                    Err(_) => return RespValue::Error("ERR Invalid IDLETIME value, must be >= 0".into()),
+                };
+            }
+            "FREQ" if i + 1 < parts.len() && idle.is_none() => {
+                i += 1;
+                freq = match parts[i].parse() {
+                    Ok(n) => Some(n),
+                    Err(_) => return RespValue::Error("ERR Invalid FREQ value, must be >= 0 and <= 255".into()),
+                };
+            }
+            _ => return RespValue::Error("ERR syntax error".into()),
+        }
+        i += 1;
+    }
+
+    let value = match dump::deserialize(parts[3].as_bytes()) {
+        Ok(v) => v,
+        Err(e) => return RespValue::Error(e.into()),
+    };
+
+    // 0 means no expiry, whether the TTL is relative or absolute; decided
+    // before converting, as an absolute TTL of right now converts to 0 too.
+    let persistent = ttl_ms == 0;
+
+    // With ABSTTL the TTL is a unix time in milliseconds; turn it into a
+    // relative one.
+    let ttl_ms = if absttl && !persistent {
+        let now_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or(0);
+        ttl_ms - now_ms
+    } else {
+        ttl_ms
+    };
+
+    let now = Instant::now();
+    let expire_at = (!persistent).then(|| now + Duration::from_millis(ttl_ms.max(0) as u64));
+
+    let key = parts[1].clone();
+    let mut db = db.lock().await;
+
+    if !replace && db.get(&key).is_some_and(|e| !e.is_expired(now)) {
+        return RespValue::Error("BUSYKEY Target key name already exists.".into());
+    }
+
+    // An absolute TTL that has already passed is accepted, but nothing is
+    // stored; a key it replaces is deleted, as in Redis.
+    if !persistent && ttl_ms <= 0 {
+        if db.remove(&key).is_some() {
+            notify::notify(notify::GENERIC, "del", &key, index);
+        }
+        return RespValue::SimpleString("OK".into());
+    }
+
+    let mut entry = ValueEntry::new(value, expire_at);
+    if let Some(secs) = idle {
+        entry.last_access = now.checked_sub(Duration::from_secs(secs)).unwrap_or(entry.last_access);
+    }
+    if let Some(f) = freq {
+        entry.lfu = f;
+    }
+    db.insert(key.clone(), entry);
+    notify::notify(notify::GENERIC, "restore", &key, index);
+
+    RespValue::SimpleString("OK".into())
+}
@@ -0,0 +1,73 @@
+use std::time::Instant;
+
+use crate::db::storage::Db;
+use crate::protocol::resp::encoder::RespValue;
+use crate::util::glob::glob_match;
+
+const DEFAULT_COUNT: usize = 10;
+
+pub async fn execute(parts: &[String], db: &Db) -> RespValue {
+    if parts.len() < 2 {
+        return RespValue::Error("ERR usage SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]".into());
+    }
+
+    let cursor: u64 = match parts[1].parse() {
+        Ok(c) => c,
+        Err(_) => return RespValue::Error("ERR invalid cursor".into()),
+    };
+
+    let mut pattern: Option<&str> = None;
+    let mut count = DEFAULT_COUNT;
+    let mut type_filter: Option<String> = None;
+
+    let mut i = 2;
+    while i < parts.len() {
+        let opt = parts[i].to_uppercase();
+        let arg = match parts.get(i + 1) {
+            Some(a) => a,
+            None => return RespValue::Error("ERR syntax error".into()),
+        };
+        match opt.as_str() {
+            "MATCH" => pattern = Some(arg.as_str()).filter(|p| *p != "*"),
+            "COUNT" => {
+                count = match arg.parse::<usize>() {
+                    Ok(n) if n >= 1 => n,
+                    Ok(_) => return RespValue::Error("ERR syntax error".into()),
+                    Err(_) => return RespValue::Error("ERR value is not an integer or out of range".into()),
+                }
+            }
+            "TYPE" => type_filter = Some(arg.to_lowercase()),
+            _ => return RespValue::Error("ERR syntax error".into()),
+        }
+        i += 2;
+    }
+
+    let db = db.lock().await;
+    let now = Instant::now();
+
+    let (next, batch) = db.scan(cursor, count);
+
+    let mut keys = Vec::new();
+    for (k, v) in batch {
+        if v.is_expired(now) {
+            continue;
+        }
+        if let Some(p) = pattern {
+            if !glob_match(p, k) {
+                continue;
+            }
+        }
+        // Every value is a string for now.
+        if let Some(t) = &type_filter {
+            if t != "string" {
+                continue;
+            }
+        }
+        keys.push(RespValue::Bulk(Some(k.clone().into_bytes())));
+    }
+
+    RespValue::Array(vec![
+        RespValue::Bulk(Some(next.to_string().into_bytes())),
+        RespValue::Array(keys),
+    ])
+}
@@ -0,0 +1,17 @@
+use crate::db::storage::Databases;
+use crate::protocol::resp::encoder::RespValue;
+use crate::server::session::Session;
+
+pub async fn execute(parts: &[String], session: &mut Session, dbs: &Databases) -> RespValue {
+    if parts.len() != 2 {
+        return RespValue::Error("ERR usage SELECT index".into());
+    }
+
+    match dbs.parse_index(&parts[1]) {
+        Ok(index) => {
+            session.db = index;
+            RespValue::SimpleString("OK".into())
+        }
+        Err(e) => RespValue::Error(e.into()),
+    }
+}
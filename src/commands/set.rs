@@ -2,8 +2,9 @@ use std::time::{Duration, Instant};
 use crate::db::storage::Db;
 use crate::db::value::ValueEntry;
 use crate::protocol::resp::encoder::RespValue;
+use crate::server::notify;
 
-pub async fn execute(parts: Vec<String>, db: &Db) -> RespValue {
+pub async fn execute(parts: &[String], db: &Db, index: usize) -> RespValue {
     if parts.len() < 3 {
         return RespValue::Error("ERR usage SET key value [EX seconds]".into());
     }
@@ -19,7 +20,12 @@ pub async fn execute(parts: Vec<String>, db: &Db) -> RespValue {
     }
 
     let mut db = db.lock().await;
-    db.insert(key, ValueEntry { value, expire_at: expire });
+    db.insert(key.clone(), ValueEntry::new(value, expire));
+
+    notify::notify(notify::STRING, "set", &key, index);
+    if expire.is_some() {
+        notify::notify(notify::GENERIC, "expire", &key, index);
+    }
 
     RespValue::SimpleString("OK".into())
 }
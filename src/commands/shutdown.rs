@@ -0,0 +1,41 @@
+use crate::protocol::resp::encoder::RespValue;
+use crate::server::shutdown::{self, State};
+
+/// SHUTDOWN [NOSAVE|SAVE] [NOW] [FORCE] [ABORT]
+///
+/// On success the caller gets no reply: the connection closes as the server
+/// exits, like in Redis.
+pub async fn execute(parts: &[String]) -> RespValue {
+    let (mut now, mut abort) = (false, false);
+    for arg in &parts[1..] {
+        match arg.to_uppercase().as_str() {
+            "NOSAVE" | "FORCE" => {}
+            // There is no persistence to flush yet, so a save cannot happen.
+            "SAVE" => {
+                return RespValue::Error(
+                    "ERR SAVE is not supported: this server has no persistence".into(),
+                )
+            }
+            "NOW" => now = true,
+            "ABORT" => abort = true,
+            _ => return RespValue::Error("ERR syntax error".into()),
+        }
+    }
+
+    if abort {
+        if parts.len() > 2 {
+            return RespValue::Error("ERR syntax error".into());
+        }
+        return if shutdown::abort() {
+            RespValue::SimpleString("OK".into())
+        } else {
+            RespValue::Error("ERR No shutdown in progress.".into())
+        };
+    }
+
+    shutdown::request(now);
+    match shutdown::outcome().await {
+        State::Stopped => std::future::pending().await,
+        _ => RespValue::Error("ERR Errors trying to SHUTDOWN. Check logs.".into()),
+    }
+}
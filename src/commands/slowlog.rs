@@ -0,0 +1,48 @@
+use crate::protocol::resp::encoder::RespValue;
+use crate::server::slowlog;
+
+pub async fn execute(parts: &[String]) -> RespValue {
+    let sub = match parts.get(1) {
+        Some(s) => s.to_uppercase(),
+        None => return RespValue::Error("ERR usage SLOWLOG <subcommand> [arguments ...]".into()),
+    };
+    let args = &parts[2..];
+
+    match sub.as_str() {
+        "GET" if args.len() <= 1 => get(args.first()),
+        "LEN" if args.is_empty() => RespValue::Integer(slowlog::len() as i64),
+        "RESET" if args.is_empty() => {
+            slowlog::reset();
+            RespValue::SimpleString("OK".into())
+        }
+        _ => RespValue::Error(format!(
+            "ERR unknown subcommand or wrong number of arguments for '{}'",
+            parts[1]
+        )),
+    }
+}
+
+/// SLOWLOG GET [count]: 10 entries by default, all of them with -1.
+fn get(count: Option<&String>) -> RespValue {
+    let count = match count.map(|c| c.parse::<i64>()) {
+        None => 10,
+        Some(Ok(-1)) => usize::MAX,
+        Some(Ok(n)) if n >= 0 => n as usize,
+        _ => return RespValue::Error("ERR count should be greater than or equal to -1".into()),
+    };
+
+    let entries = slowlog::get(count)
+        .into_iter()
+        .map(|e| {
+            RespValue::Array(vec![
+                RespValue::Integer(e.id as i64),
+                RespValue::Integer(e.timestamp as i64),
+                RespValue::Integer(e.duration_us as i64),
+                RespValue::Array(e.args.into_iter().map(RespValue::bulk).collect()),
+                RespValue::bulk(e.client_addr),
+                RespValue::bulk(e.client_name),
+            ])
+        })
+        .collect();
+    RespValue::Array(entries)
+}
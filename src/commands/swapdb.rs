@@ -0,0 +1,21 @@
+use crate::db::storage::Databases;
+use crate::protocol::resp::encoder::RespValue;
+
+pub async fn execute(parts: &[String], dbs: &Databases) -> RespValue {
+    if parts.len() != 3 {
+        return RespValue::Error("ERR usage SWAPDB index1 index2".into());
+    }
+
+    let (a, b) = match (dbs.parse_index(&parts[1]), dbs.parse_index(&parts[2])) {
+        (Ok(a), Ok(b)) => (a, b),
+        (Err(e), _) | (_, Err(e)) => return RespValue::Error(e.into()),
+    };
+
+    if a != b {
+        // Clients keep their selected index, so they see the other data set.
+        let (mut ga, mut gb) = dbs.lock_pair(a, b).await;
+        std::mem::swap(&mut *ga, &mut *gb);
+    }
+
+    RespValue::SimpleString("OK".into())
+}
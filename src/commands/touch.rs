@@ -0,0 +1,20 @@
+use crate::db::storage::Db;
+use crate::protocol::resp::encoder::RespValue;
+
+pub async fn execute(parts: &[String], db: &Db, index: usize) -> RespValue {
+    if parts.len() < 2 {
+        return RespValue::Error("ERR usage TOUCH key [key ...]".into());
+    }
+
+    let mut db = db.lock().await;
+
+    let mut touched = 0;
+    for key in &parts[1..] {
+        if let Some(entry) = db.get_live(key, index) {
+            entry.touch();
+            touched += 1;
+        }
+    }
+
+    RespValue::Integer(touched)
+}
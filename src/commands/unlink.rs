@@ -0,0 +1,40 @@
+use std::time::Instant;
+
+use crate::db::storage::Db;
+use crate::db::value::ValueEntry;
+use crate::protocol::resp::encoder::RespValue;
+use crate::server::notify;
+
+/// Values at least this large are freed on a blocking worker instead of the
+/// connection task.
+const LAZYFREE_THRESHOLD_BYTES: usize = 64 * 1024;
+
+pub async fn execute(parts: &[String], db: &Db, index: usize) -> RespValue {
+    if parts.len() < 2 {
+        return RespValue::Error("ERR usage UNLINK key [key ...]".into());
+    }
+
+    let now = Instant::now();
+    let mut removed: Vec<ValueEntry> = Vec::new();
+    let mut unlinked = 0;
+
+    {
+        let mut db = db.lock().await;
+        for key in &parts[1..] {
+            if let Some(entry) = db.remove(key) {
+                if !entry.is_expired(now) {
+                    unlinked += 1;
+                    notify::notify(notify::GENERIC, "del", key, index);
+                }
+                removed.push(entry);
+            }
+        }
+    }
+
+    let pending: usize = removed.iter().map(|e| e.value.len()).sum();
+    if pending >= LAZYFREE_THRESHOLD_BYTES {
+        tokio::task::spawn_blocking(move || drop(removed));
+    }
+
+    RespValue::Integer(unlinked)
+}
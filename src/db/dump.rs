@@ -0,0 +1,113 @@
+//! Serialization format used by DUMP and RESTORE.
+//!
+//! ```text
+//! <type:1> <value:*> <version:1> <crc64:16 hex digits>
+//! ```
+//!
+//! The checksum (CRC-64/Jones, as in Redis) covers everything before it.
+//! Command arguments are handled as UTF-8 strings, so the payload is kept
+//! valid UTF-8 end to end: the type and version are small ASCII codes and
+//! the checksum is hex-encoded.
+
+pub const DUMP_VERSION: u8 = 1;
+
+const TYPE_STRING: u8 = 0;
+const TRAILER_LEN: usize = 1 + 16;
+
+pub fn serialize(value: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(1 + value.len() + TRAILER_LEN);
+    out.push(TYPE_STRING);
+    out.extend_from_slice(value.as_bytes());
+    out.push(DUMP_VERSION);
+    let crc = crc64(&out);
+    out.extend_from_slice(format!("{:016x}", crc).as_bytes());
+    out
+}
+
+pub fn deserialize(payload: &[u8]) -> Result<String, &'static str> {
+    const BAD: &str = "ERR DUMP payload version or checksum are wrong";
+
+    if payload.len() < 1 + TRAILER_LEN {
+        return Err(BAD);
+    }
+
+    let (body, crc_hex) = payload.split_at(payload.len() - 16);
+    let expected = std::str::from_utf8(crc_hex)
+        .ok()
+        .and_then(|h| u64::from_str_radix(h, 16).ok())
+        .ok_or(BAD)?;
+    if crc64(body) != expected {
+        return Err(BAD);
+    }
+
+    let (data, version) = body.split_at(body.len() - 1);
+    if version[0] != DUMP_VERSION {
+        return Err(BAD);
+    }
+
+    match data[0] {
+        TYPE_STRING => String::from_utf8(data[1..].to_vec()).map_err(|_| "ERR Bad data format"),
+        _ => Err("ERR Bad data format"),
+    }
+}
+
+/// CRC-64/Jones, reflected, as used by Redis for DUMP payloads.
+fn crc64(data: &[u8]) -> u64 {
+    const POLY: u64 = 0x95ac_9329_ac4b_c9b5;
+
+    static TABLE: std::sync::OnceLock<[u64; 256]> = std::sync::OnceLock::new();
+    let table = TABLE.get_or_init(|| {
+        let mut t = [0u64; 256];
+        for (i, slot) in t.iter_mut().enumerate() {
+            let mut crc = i as u64;
+            for _ in 0..8 {
+                crc = if crc & 1 == 1 { (crc >> 1) ^ POLY } else { crc >> 1 };
+            }
+            *slot = crc;
+        }
+        t
+    });
+
+    data.iter()
+        .fold(0u64, |crc, &b| table[((crc ^ b as u64) & 0xff) as usize] ^ (crc >> 8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc64_matches_redis() {
+        // The check value Redis' crc64 self-test uses.
+        assert_eq!(crc64(b"123456789"), 0xe9c6_d914_c4b8_d9ca);
+        assert_eq!(crc64(b""), 0);
+    }
+
+    #[test]
+    fn round_trip() {
+        for value in ["", "hello", "ünïcödé", "line\r\nbreak"] {
+            assert_eq!(deserialize(&serialize(value)).as_deref(), Ok(value));
+        }
+    }
+
+    #[test]
+    fn rejects_tampered_payloads() {
+        let mut payload = serialize("hello");
+        payload[1] = b'j';
+        assert!(deserialize(&payload).is_err());
+
+        let payload = serialize("hello");
+        assert!(deserialize(&payload[..payload.len() - 1]).is_err());
+        assert!(deserialize(b"short").is_err());
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut body = vec![TYPE_STRING];
+        body.extend_from_slice(b"hello");
+        body.push(DUMP_VERSION + 1);
+        let crc = crc64(&body);
+        body.extend_from_slice(format!("{:016x}", crc).as_bytes());
+        assert!(deserialize(&body).is_err());
+    }
+}
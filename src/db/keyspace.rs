@@ -0,0 +1,314 @@
+use std::collections::hash_map::RandomState;
+use std::collections::BTreeMap;
+use std::hash::BuildHasher;
+use std::time::Instant;
+
+use hashbrown::hash_table::Entry;
+use hashbrown::HashTable;
+
+use super::value::ValueEntry;
+use crate::server::{clients, notify, stats};
+
+struct Slot {
+    /// Kept so growing the table and scanning never rehash the key.
+    hash: u64,
+    key: String,
+    entry: ValueEntry,
+}
+
+/// The key table of one database.
+///
+/// `SCAN` cursors are positions in the hash space: a key's hash never
+/// changes while it exists, so a full iteration returns every key that was
+/// present from start to finish, no matter how the table grows or shrinks
+/// in between. To walk the hash space in order, `hashes` counts the keys per
+/// hash; the keys themselves are only stored in the table, which is probed
+/// by hash to find them.
+pub struct Keyspace {
+    table: HashTable<Slot>,
+    hashes: BTreeMap<u64, u32>,
+    hasher: RandomState,
+}
+
+impl Default for Keyspace {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Keyspace {
+    pub fn new() -> Self {
+        Keyspace {
+            table: HashTable::new(),
+            hashes: BTreeMap::new(),
+            hasher: RandomState::new(),
+        }
+    }
+
+    fn hash_of(&self, key: &str) -> u64 {
+        self.hasher.hash_one(key)
+    }
+
+    fn forget_hash(hashes: &mut BTreeMap<u64, u32>, hash: u64) {
+        if let Some(n) = hashes.get_mut(&hash) {
+            *n -= 1;
+            if *n == 0 {
+                hashes.remove(&hash);
+            }
+        }
+    }
+
+    /// The keys with exactly this hash; usually one.
+    fn keys_with_hash(&self, hash: u64) -> impl Iterator<Item = &Slot> {
+        self.table.iter_hash(hash).filter(move |s| s.hash == hash)
+    }
+
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&ValueEntry> {
+        self.table
+            .find(self.hash_of(key), |s| s.key == key)
+            .map(|s| &s.entry)
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut ValueEntry> {
+        let hash = self.hash_of(key);
+        self.table
+            .find_mut(hash, |s| s.key == key)
+            .map(|s| &mut s.entry)
+    }
+
+    /// The entry for `key` unless it has expired. An expired key is removed
+    /// on the spot, with the `expired` notification and count the TTL
+    /// cleaner would have given it; `index` is this database's number.
+    /// While writes are paused it is only reported missing, and left for
+    /// the cleaner to remove once the pause ends.
+    pub fn get_live(&mut self, key: &str, index: usize) -> Option<&mut ValueEntry> {
+        if self.get(key).is_some_and(|e| e.is_expired(Instant::now())) {
+            if clients::writes_paused() {
+                return None;
+            }
+            self.remove(key);
+            notify::notify(notify::EXPIRED, "expired", key, index);
+            stats::count(&stats::EXPIRED_KEYS);
+            return None;
+        }
+        self.get_mut(key)
+    }
+
+    pub fn insert(&mut self, key: String, entry: ValueEntry) -> Option<ValueEntry> {
+        let hash = self.hash_of(&key);
+        match self.table.entry(hash, |s| s.key == key, |s| s.hash) {
+            Entry::Occupied(mut o) => Some(std::mem::replace(&mut o.get_mut().entry, entry)),
+            Entry::Vacant(v) => {
+                v.insert(Slot { hash, key, entry });
+                *self.hashes.entry(hash).or_insert(0) += 1;
+                None
+            }
+        }
+    }
+
+    pub fn get_or_insert_with(
+        &mut self,
+        key: String,
+        default: impl FnOnce() -> ValueEntry,
+    ) -> &mut ValueEntry {
+        let hash = self.hash_of(&key);
+        match self.table.entry(hash, |s| s.key == key, |s| s.hash) {
+            Entry::Occupied(o) => &mut o.into_mut().entry,
+            Entry::Vacant(v) => {
+                *self.hashes.entry(hash).or_insert(0) += 1;
+                let entry = default();
+                &mut v.insert(Slot { hash, key, entry }).into_mut().entry
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<ValueEntry> {
+        let hash = self.hash_of(key);
+        let (slot, _) = self.table.find_entry(hash, |s| s.key == key).ok()?.remove();
+        Self::forget_hash(&mut self.hashes, hash);
+        Some(slot.entry)
+    }
+
+    pub fn retain(&mut self, mut keep: impl FnMut(&String, &mut ValueEntry) -> bool) {
+        let hashes = &mut self.hashes;
+        self.table.retain(|s| {
+            if keep(&s.key, &mut s.entry) {
+                return true;
+            }
+            Self::forget_hash(hashes, s.hash);
+            false
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.table.clear();
+        self.hashes.clear();
+    }
+
+    /// Picks the first key at or after `seed` in hash order, wrapping around.
+    /// With a uniformly random `seed` this is a cheap random key choice.
+    pub fn key_at_or_after(&self, seed: u64) -> Option<&String> {
+        let (&hash, _) = self
+            .hashes
+            .range(seed..)
+            .next()
+            .or_else(|| self.hashes.iter().next())?;
+        self.keys_with_hash(hash).next().map(|s| &s.key)
+    }
+
+    /// Visits up to `count` keys starting at `cursor` and returns the cursor
+    /// for the next call (0 once the whole keyspace has been covered).
+    ///
+    /// Keys sharing a hash are never split across two calls, so a batch may
+    /// slightly exceed `count`.
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<(&String, &ValueEntry)>) {
+        let mut out = Vec::new();
+        let mut last_hash = None;
+
+        for &hash in self.hashes.range(cursor..).map(|(h, _)| h) {
+            if out.len() >= count {
+                break;
+            }
+            out.extend(self.keys_with_hash(hash).map(|s| (&s.key, &s.entry)));
+            last_hash = Some(hash);
+        }
+
+        let next = match last_hash.and_then(|h| h.checked_add(1)) {
+            Some(next) if self.hashes.range(next..).next().is_some() => next,
+            _ => 0,
+        };
+
+        (next, out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn entry() -> ValueEntry {
+        ValueEntry::new("v".into(), None)
+    }
+
+    /// Inserts `key` under a chosen hash, to make collisions on purpose.
+    fn insert_with_hash(ks: &mut Keyspace, hash: u64, key: &str) {
+        let key = key.to_string();
+        ks.table.insert_unique(hash, Slot { hash, key, entry: entry() }, |s| s.hash);
+        *ks.hashes.entry(hash).or_insert(0) += 1;
+    }
+
+    fn remove_with_hash(ks: &mut Keyspace, hash: u64, key: &str) {
+        if let Ok(found) = ks.table.find_entry(hash, |s| s.key == key) {
+            found.remove();
+            Keyspace::forget_hash(&mut ks.hashes, hash);
+        }
+    }
+
+    /// Scans with a small COUNT, calling `between` after every batch.
+    fn scan_all(ks: &mut Keyspace, count: usize, mut between: impl FnMut(&mut Keyspace, usize)) -> HashSet<String> {
+        let mut seen = HashSet::new();
+        let mut cursor = 0;
+        let mut round = 0;
+        loop {
+            let (next, batch) = ks.scan(cursor, count);
+            seen.extend(batch.into_iter().map(|(k, _)| k.clone()));
+            if next == 0 {
+                return seen;
+            }
+            cursor = next;
+            between(ks, round);
+            round += 1;
+        }
+    }
+
+    #[test]
+    fn scan_returns_keys_present_throughout_while_table_changes() {
+        let mut ks = Keyspace::new();
+        for i in 0..200 {
+            ks.insert(format!("stable:{}", i), entry());
+        }
+        for i in 0..200 {
+            ks.insert(format!("doomed:{}", i), entry());
+        }
+
+        // The first batches delete a few keys and add many more, so the
+        // table goes through several resizes in the middle of the scan.
+        let seen = scan_all(&mut ks, 3, |ks, round| {
+            if round >= 50 {
+                return;
+            }
+            for i in 0..4 {
+                ks.remove(&format!("doomed:{}", round * 4 + i));
+            }
+            for i in 0..80 {
+                ks.insert(format!("new:{}:{}", round, i), entry());
+            }
+        });
+
+        for i in 0..200 {
+            assert!(seen.contains(&format!("stable:{}", i)), "stable:{} missed", i);
+        }
+        assert_eq!(ks.len(), 200 + 4000);
+    }
+
+    #[test]
+    fn scan_keeps_colliding_keys_together() {
+        let mut ks = Keyspace::new();
+        // Ten hashes with five keys each, spread over the hash space.
+        let hash = |h: u64| h * (u64::MAX / 10);
+        for h in 0..10 {
+            for k in 0..5 {
+                insert_with_hash(&mut ks, hash(h), &format!("{}:{}", h, k));
+            }
+        }
+
+        let mut batches = Vec::new();
+        let mut cursor = 0;
+        loop {
+            let (next, batch) = ks.scan(cursor, 2);
+            batches.push(batch.into_iter().map(|(k, _)| k.clone()).collect::<Vec<_>>());
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+            // A new collision behind the cursor and one ahead of it.
+            insert_with_hash(&mut ks, hash(0), &format!("late:{}", cursor));
+            insert_with_hash(&mut ks, hash(9), &format!("late:{}", cursor));
+            remove_with_hash(&mut ks, hash(9), "9:0");
+        }
+
+        for h in 0..10 {
+            let prefix = format!("{}:", h);
+            let holding: Vec<_> = batches
+                .iter()
+                .filter(|b| b.iter().any(|k| k.starts_with(&prefix)))
+                .collect();
+            assert_eq!(holding.len(), 1, "hash {} split across batches", h);
+        }
+        let seen: HashSet<_> = batches.into_iter().flatten().collect();
+        for h in 0..9 {
+            for k in 0..5 {
+                assert!(seen.contains(&format!("{}:{}", h, k)));
+            }
+        }
+        for k in 1..5 {
+            assert!(seen.contains(&format!("9:{}", k)));
+        }
+    }
+
+    #[test]
+    fn expiry_at_now_counts_as_expired() {
+        let now = Instant::now();
+        assert!(ValueEntry::new("v".into(), Some(now)).is_expired(now));
+        assert!(!ValueEntry::new("v".into(), Some(now + std::time::Duration::from_secs(1))).is_expired(now));
+    }
+}
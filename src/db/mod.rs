@@ -1,3 +1,5 @@
 pub mod storage;
+pub mod keyspace;
 pub mod value;
-pub mod ttl_cleaner;
\ No newline at end of file
+pub mod dump;
+pub mod ttl_cleaner;
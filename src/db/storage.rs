@@ -1,11 +1,100 @@
-use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::time::Instant;
+use tokio::sync::{Mutex, MutexGuard};
 
-use super::value::ValueEntry;
+use super::keyspace::Keyspace;
+use crate::server::{latency, metrics_prom};
 
-pub type Db = Arc<Mutex<HashMap<String, ValueEntry>>>;
+pub const DEFAULT_DATABASES: usize = 16;
+
+/// One database's keyspace behind the lock every command on it takes.
+pub struct Database {
+    keyspace: Mutex<Keyspace>,
+}
+
+impl Database {
+    /// Locks the keyspace. Time spent waiting goes to `keyval_lock_wait_seconds`
+    /// and, past the threshold, to the latency monitor's `lock-wait` event.
+    pub async fn lock(&self) -> MutexGuard<'_, Keyspace> {
+        if let Ok(guard) = self.keyspace.try_lock() {
+            metrics_prom::LOCK_WAIT.observe(0.0);
+            return guard;
+        }
+        let t0 = Instant::now();
+        let guard = self.keyspace.lock().await;
+        let waited = t0.elapsed();
+        metrics_prom::LOCK_WAIT.observe(waited.as_secs_f64());
+        latency::sample(latency::LOCK_WAIT, waited);
+        guard
+    }
+}
+
+pub type Db = Arc<Database>;
 
 pub fn new_db() -> Db {
-    Arc::new(Mutex::new(HashMap::new()))
+    Arc::new(Database {
+        keyspace: Mutex::new(Keyspace::new()),
+    })
+}
+
+/// The numbered logical databases (`SELECT 0` .. `SELECT n-1`).
+#[derive(Clone)]
+pub struct Databases {
+    dbs: Arc<Vec<Db>>,
+}
+
+impl Databases {
+    pub fn new(n: usize) -> Self {
+        Databases {
+            dbs: Arc::new((0..n.max(1)).map(|_| new_db()).collect()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.dbs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dbs.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Db> {
+        self.dbs.get(index)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Db> {
+        self.dbs.iter()
+    }
+
+    /// Locks two distinct databases, always in index order so two clients
+    /// doing `MOVE`/`SWAPDB` in opposite directions cannot deadlock.
+    /// The guards are returned in argument order.
+    pub async fn lock_pair(
+        &self,
+        a: usize,
+        b: usize,
+    ) -> (MutexGuard<'_, Keyspace>, MutexGuard<'_, Keyspace>) {
+        assert_ne!(a, b, "lock_pair needs two distinct databases");
+
+        if a < b {
+            let ga = self.dbs[a].lock().await;
+            let gb = self.dbs[b].lock().await;
+            (ga, gb)
+        } else {
+            let gb = self.dbs[b].lock().await;
+            let ga = self.dbs[a].lock().await;
+            (ga, gb)
+        }
+    }
+
+    /// Parses a client-supplied DB index and checks it is in range.
+    pub fn parse_index(&self, s: &str) -> Result<usize, &'static str> {
+        let n: i64 = s
+            .parse()
+            .map_err(|_| "ERR value is not an integer or out of range")?;
+        if n < 0 || n as usize >= self.len() {
+            return Err("ERR DB index is out of range");
+        }
+        Ok(n as usize)
+    }
 }
@@ -1,28 +1,46 @@
 use std::time::Instant;
 use tokio::time::{sleep, Duration};
 
-use super::storage::Db;
+use super::storage::Databases;
+use crate::server::{clients, config, latency, notify, stats};
 
-pub async fn start_cleaner(db: Db) {
+pub async fn start_cleaner(dbs: Databases) {
 
     tokio::spawn(async move {
 
         loop {
 
-            {
+            // CLIENT PAUSE WRITE freezes the data, expiry included.
+            let paused = clients::writes_paused();
+
+            for (index, db) in dbs.iter().enumerate().filter(|_| !paused) {
                 let mut db = db.lock().await;
                 let now = Instant::now();
+                // The keys that stay, for INFO keyspace.
+                let (mut expires, mut ttl_total_ms) = (0usize, 0u128);
 
-                db.retain(|_, v| {
+                db.retain(|key, v| {
                     if let Some(exp) = v.expire_at {
+                        if exp <= now {
+                            notify::notify(notify::EXPIRED, "expired", key, index);
+                            stats::count(&stats::EXPIRED_KEYS);
+                        } else {
+                            expires += 1;
+                            ttl_total_ms += (exp - now).as_millis();
+                        }
                         exp > now
                     } else {
                         true
                     }
                 });
+
+                let avg_ttl_ms = if expires > 0 { ttl_total_ms / expires as u128 } else { 0 };
+                stats::record_expires(index, expires, avg_ttl_ms as u64);
+                // The scan holds the lock, so it stalls every client of this database.
+                latency::sample(latency::EXPIRE_CYCLE, now.elapsed());
             }
 
-            sleep(Duration::from_secs(5)).await;
+            sleep(Duration::from_millis(config::with(|c| c.expire_interval_ms))).await;
         }
 
     });
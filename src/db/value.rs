@@ -1,7 +1,87 @@
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Instant;
 
+use crate::util::rand;
+
+/// Initial LFU counter for new keys, so they are not the first to look cold.
+const LFU_INIT_VAL: u8 = 5;
+/// Higher values make the logarithmic counter saturate more slowly.
+const LFU_LOG_FACTOR: f64 = 10.0;
+/// The counter loses one point for every period of this many idle seconds.
+const LFU_DECAY_SECS: u64 = 60;
+
+static NEXT_CAS: AtomicU64 = AtomicU64::new(1);
+
+/// A fresh CAS unique, as handed out by memcached on every modification.
+pub fn next_cas() -> u64 {
+    NEXT_CAS.fetch_add(1, Ordering::Relaxed)
+}
+
 #[derive(Clone)]
 pub struct ValueEntry {
     pub value: String,
     pub expire_at: Option<Instant>,
+    pub last_access: Instant,
+    pub lfu: u8,
+    /// Opaque client flags stored alongside the value by memcached clients.
+    pub flags: u32,
+    /// Changes whenever the value does; checked by memcached `cas`.
+    pub cas: u64,
+}
+
+impl ValueEntry {
+    pub fn new(value: String, expire_at: Option<Instant>) -> Self {
+        ValueEntry {
+            value,
+            expire_at,
+            last_access: Instant::now(),
+            lfu: LFU_INIT_VAL,
+            flags: 0,
+            cas: next_cas(),
+        }
+    }
+
+    /// Replaces the value in place, keeping TTL and flags.
+    pub fn set_value(&mut self, value: String) {
+        self.value = value;
+        self.cas = next_cas();
+    }
+
+    pub fn is_expired(&self, now: Instant) -> bool {
+        matches!(self.expire_at, Some(exp) if exp <= now)
+    }
+
+    /// Records an access for OBJECT IDLETIME / OBJECT FREQ.
+    pub fn touch(&mut self) {
+        self.lfu = self.lfu_decayed();
+        if self.lfu < u8::MAX {
+            let base = self.lfu.saturating_sub(LFU_INIT_VAL) as f64;
+            if rand::next_f64() < 1.0 / (base * LFU_LOG_FACTOR + 1.0) {
+                self.lfu += 1;
+            }
+        }
+        self.last_access = Instant::now();
+    }
+
+    pub fn idle_secs(&self) -> u64 {
+        self.last_access.elapsed().as_secs()
+    }
+
+    /// Logarithmic access frequency, decayed by the time since the last access.
+    pub fn lfu_decayed(&self) -> u8 {
+        let periods = self.idle_secs() / LFU_DECAY_SECS;
+        self.lfu.saturating_sub(periods.min(u8::MAX as u64) as u8)
+    }
+
+    /// Internal representation name reported by OBJECT ENCODING.
+    pub fn encoding(&self) -> &'static str {
+        let v = &self.value;
+        if v.len() <= 20 && v.parse::<i64>().is_ok_and(|n| n.to_string() == *v) {
+            "int"
+        } else if v.len() <= 44 {
+            "embstr"
+        } else {
+            "raw"
+        }
+    }
 }
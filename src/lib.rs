@@ -0,0 +1,5 @@
+pub mod server;
+pub mod db;
+pub mod protocol;
+pub mod commands;
+pub mod util;
@@ -1,26 +1,92 @@
-mod server;
-mod db;
-mod protocol;
-mod commands;
-
+use std::collections::VecDeque;
+use std::sync::atomic::Ordering;
 use std::time::Duration;
-use crate::db::storage::{new_db, Db};
-use crate::db::ttl_cleaner::start_cleaner;
-use crate::server::http_metrics;
+use rust_keyval::db::storage::Databases;
+use rust_keyval::db::ttl_cleaner::start_cleaner;
+use rust_keyval::server;
+use rust_keyval::server::tcp_server::UnixSocket;
+use rust_keyval::server::tls::{self, ClientAuth, Tls, TlsSettings};
+use std::sync::Arc;
+use rust_keyval::server::http_metrics;
 use tokio::time::{interval, Interval};
 
+/// Seconds of one-per-second samples the network rate gauges average over,
+/// like Redis' `instantaneous_input_kbps`.
+const NET_RATE_SAMPLES: usize = 16;
+
+#[derive(Default)]
+struct NetRate {
+    last: Option<(u64, u64)>,
+    samples: VecDeque<(u64, u64)>,
+}
+
+impl NetRate {
+    fn sample(&mut self, bytes_in: u64, bytes_out: u64) {
+        if let Some((prev_in, prev_out)) = self.last {
+            if self.samples.len() == NET_RATE_SAMPLES {
+                self.samples.pop_front();
+            }
+            self.samples.push_back((bytes_in - prev_in, bytes_out - prev_out));
+
+            let n = self.samples.len() as u64;
+            let (sum_in, sum_out) = self
+                .samples
+                .iter()
+                .fold((0, 0), |(a, b), (i, o)| (a + i, b + o));
+            server::metrics_prom::NET_INPUT_BYTES_PER_SEC.set((sum_in / n) as i64);
+            server::metrics_prom::NET_OUTPUT_BYTES_PER_SEC.set((sum_out / n) as i64);
+        }
+        self.last = Some((bytes_in, bytes_out));
+    }
+}
+
+/// Commands per second over the same window, for `instantaneous_ops_per_sec`.
+#[derive(Default)]
+struct OpsRate {
+    last: Option<u64>,
+    samples: VecDeque<u64>,
+}
+
+impl OpsRate {
+    fn sample(&mut self, total: u64) {
+        if let Some(prev) = self.last {
+            if self.samples.len() == NET_RATE_SAMPLES {
+                self.samples.pop_front();
+            }
+            self.samples.push_back(total.saturating_sub(prev));
+
+            let n = self.samples.len() as u64;
+            let sum: u64 = self.samples.iter().sum();
+            server::stats::OPS_PER_SEC.store(sum / n, Ordering::Relaxed);
+        }
+        self.last = Some(total);
+    }
+}
+
 #[tokio::main]
 async fn main() {
-    let db: Db = new_db();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let config = server::config::init(&args).unwrap_or_else(|e| {
+        eprintln!("Invalid configuration: {}", e);
+        std::process::exit(1);
+    });
+
+    let dbs = Databases::new(config.databases);
+    // Uptime counts from here.
+    let _ = &*server::stats::STARTED;
 
     tokio::spawn({
-        let db = db.clone();
+        let dbs = dbs.clone();
         async move {
             let mut tick = interval(Duration::from_secs(1));
             loop {
                 tick.tick().await;
-                let n = db.lock().await.len() as i64;
-                server::metrics_prom::KEYS_COUNT.set(n);
+                for (i, db) in dbs.iter().enumerate() {
+                    let n = db.lock().await.len() as i64;
+                    server::metrics_prom::KEYS_COUNT
+                        .with_label_values(&[i.to_string().as_str()])
+                        .set(n);
+                }
             }
         }
     });
@@ -28,15 +94,23 @@ async fn main() {
     tokio::spawn(async move {
         let mut tick: Interval = interval(Duration::from_secs(1));
         let mut last_cpu: Option<f64> = None;
+        let mut net = NetRate::default();
+        let mut ops = OpsRate::default();
 
         loop {
             tick.tick().await;
 
+            net.sample(
+                server::metrics_prom::BYTES_IN.get(),
+                server::metrics_prom::BYTES_OUT.get(),
+            );
+            ops.sample(server::stats::total_commands());
+
             if let Some(rss) = server::linux_proc::rss_bytes() {
                 server::metrics_prom::PROCESS_RSS_BYTES.set(rss as i64);
             }
 
-            if let Some(cpu_now) = crate::server::linux_proc::cpu_seconds_total() {
+            if let Some(cpu_now) = server::linux_proc::cpu_seconds_total() {
                 match last_cpu {
                     Some(prev) if cpu_now >= prev => {
                         server::metrics_prom::PROCESS_CPU_SECONDS_TOTAL.inc_by(cpu_now - prev);
@@ -53,29 +127,92 @@ async fn main() {
         }
     });
 
-    start_cleaner(db.clone()).await;
-
-    let keyval_bind: String = std::env::var("KEYVAL_BIND")
-        .unwrap_or_else(|_| "127.0.0.1:6374".into());
+    start_cleaner(dbs.clone()).await;
 
-    let metrics_bind: String = std::env::var("METRICS_BIND")
-        .unwrap_or_else(|_| "127.0.0.1:9100".into());
+    let unix_socket = (!config.unixsocket.is_empty()).then(|| UnixSocket {
+        path: config.unixsocket.clone().into(),
+        perm: config.unixsocketperm,
+    });
+    // An empty bind turns TCP off, for Unix-socket-only setups.
+    let tcp_bind = (!config.bind.is_empty()).then_some(config.bind.as_str());
 
+    // TLS is on once a certificate and key are given, for both listeners.
+    let tls = if !config.tls_cert_file.is_empty() && !config.tls_key_file.is_empty() {
+        let ca_file = (!config.tls_ca_cert_file.is_empty()).then(|| config.tls_ca_cert_file.clone());
+        let client_auth = match config.tls_auth_clients {
+            Some(mode) => mode,
+            None if ca_file.is_some() => ClientAuth::Required,
+            None => ClientAuth::No,
+        };
+        let settings = TlsSettings {
+            cert_file: config.tls_cert_file.clone().into(),
+            key_file: config.tls_key_file.clone().into(),
+            ca_file: ca_file.map(Into::into),
+            client_auth,
+        };
+        let tls = Arc::new(Tls::new(settings).expect("failed to load TLS certificates"));
+        tls::reload_on_sighup(tls.clone());
+        Some(tls)
+    } else {
+        None
+    };
 
     let _ = &*server::metrics_prom::ACTIVE_CONNS;
     let _ = &*server::metrics_prom::KEYS_COUNT;
     let _ = &*server::metrics_prom::CMD_TOTAL;
     let _ = &*server::metrics_prom::CMD_LATENCY;
+    let _ = &*server::metrics_prom::LOCK_WAIT;
     let _ = &*server::metrics_prom::PROCESS_RSS_BYTES;
     let _ = &*server::metrics_prom::PROCESS_CPU_SECONDS_TOTAL;
+    let _ = &*server::metrics_prom::PROTOCOL_REJECTIONS;
+    let _ = &*server::metrics_prom::OUTPUT_LIMIT_DISCONNECTS;
+    let _ = &*server::metrics_prom::CMD_REPLY_BYTES;
+    let _ = &*server::metrics_prom::BYTES_IN;
+    let _ = &*server::metrics_prom::BYTES_OUT;
+    let _ = &*server::metrics_prom::NET_INPUT_BYTES_PER_SEC;
+    let _ = &*server::metrics_prom::NET_OUTPUT_BYTES_PER_SEC;
+    let _ = &*server::metrics_prom::TLS_HANDSHAKE_FAILURES;
+    let _ = &*server::metrics_prom::REJECTED_CONNECTIONS;
+    let _ = &*server::metrics_prom::IDLE_TIMEOUTS;
+    let _ = &*server::metrics_prom::ACCEPT_ERRORS;
 
-    println!("Metrics running on {}", metrics_bind);
-    {
-        let mb = metrics_bind.clone();
+    println!("Metrics running on {}", config.metrics_bind);
+    let metrics = {
+        let mb = config.metrics_bind.clone();
+        let dbs = dbs.clone();
+        let tls = tls.clone();
         tokio::spawn(async move {
-            http_metrics::serve(&mb).await;
-        });
+            http_metrics::serve(&mb, dbs, tls).await;
+        })
+    };
+
+    if !config.memcached_bind.is_empty() {
+        // Memcached clients cannot authenticate, so they would get past it.
+        if server::acl::default_has_password() {
+            eprintln!(
+                "Invalid configuration: memcached-bind needs the default user to have no password"
+            );
+            std::process::exit(1);
+        }
+        let listener = match server::memcached::bind(&config.memcached_bind).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                eprintln!("Failed to bind memcached listener on {}: {}", config.memcached_bind, e);
+                std::process::exit(1);
+            }
+        };
+        tokio::spawn(server::memcached::start(listener, dbs.clone(), tls.clone()));
     }
 
-    server::tcp_server::start(&keyval_bind, db).await;
+    server::shutdown::handle_signals();
+    tokio::join!(
+        server::tcp_server::start(tcp_bind, unix_socket, tls, dbs),
+        server::shutdown::run(),
+    );
+
+    // Nothing is persisted yet, so there is nothing to flush before exiting.
+    if tokio::time::timeout(Duration::from_secs(10), metrics).await.is_err() {
+        eprintln!("Metrics server did not stop in time");
+    }
+    println!("Server stopped");
 }
\ No newline at end of file
@@ -0,0 +1,139 @@
+//! Inline (telnet-style) argument splitting, following Redis' `sdssplitargs`
+//! grammar so `redis-cli`-style quoting behaves the same here:
+//!
+//! - arguments are separated by whitespace;
+//! - `"..."` supports `\n`, `\r`, `\t`, `\b`, `\a`, `\\`, `\"` and `\xHH`;
+//! - `'...'` is literal except for `\'`;
+//! - a closing quote must be followed by whitespace or the end of the line;
+//! - outside quotes, a backslash is an ordinary character.
+
+pub const UNBALANCED_QUOTES: &str = "unbalanced quotes in request";
+
+pub fn split_args(line: &[u8]) -> Result<Vec<Vec<u8>>, &'static str> {
+    let mut args = Vec::new();
+    let mut i = 0;
+
+    loop {
+        while i < line.len() && is_space(line[i]) {
+            i += 1;
+        }
+        if i >= line.len() {
+            return Ok(args);
+        }
+
+        let mut cur = Vec::new();
+        let mut in_dq = false;
+        let mut in_sq = false;
+
+        loop {
+            if in_dq {
+                let c = *line.get(i).ok_or(UNBALANCED_QUOTES)?;
+                if c == b'\\' && i + 3 < line.len() && line[i + 1] == b'x' {
+                    if let (Some(h), Some(l)) = (hex(line[i + 2]), hex(line[i + 3])) {
+                        cur.push(h * 16 + l);
+                        i += 4;
+                        continue;
+                    }
+                }
+                if c == b'\\' && i + 1 < line.len() {
+                    cur.push(match line[i + 1] {
+                        b'n' => b'\n',
+                        b'r' => b'\r',
+                        b't' => b'\t',
+                        b'b' => 0x08,
+                        b'a' => 0x07,
+                        other => other,
+                    });
+                    i += 2;
+                } else if c == b'"' {
+                    // The closing quote must end the argument.
+                    if line.get(i + 1).is_some_and(|&n| !is_space(n)) {
+                        return Err(UNBALANCED_QUOTES);
+                    }
+                    i += 1;
+                    break;
+                } else {
+                    cur.push(c);
+                    i += 1;
+                }
+            } else if in_sq {
+                let c = *line.get(i).ok_or(UNBALANCED_QUOTES)?;
+                if c == b'\\' && line.get(i + 1) == Some(&b'\'') {
+                    cur.push(b'\'');
+                    i += 2;
+                } else if c == b'\'' {
+                    if line.get(i + 1).is_some_and(|&n| !is_space(n)) {
+                        return Err(UNBALANCED_QUOTES);
+                    }
+                    i += 1;
+                    break;
+                } else {
+                    cur.push(c);
+                    i += 1;
+                }
+            } else {
+                match line.get(i) {
+                    None => break,
+                    Some(&c) if is_space(c) => break,
+                    Some(b'"') => in_dq = true,
+                    Some(b'\'') => in_sq = true,
+                    Some(&c) => cur.push(c),
+                }
+                i += 1;
+            }
+        }
+
+        args.push(cur);
+    }
+}
+
+pub fn is_space(c: u8) -> bool {
+    matches!(c, b' ' | b'\n' | b'\r' | b'\t' | b'\x0b' | b'\x0c' | b'\0')
+}
+
+fn hex(c: u8) -> Option<u8> {
+    (c as char).to_digit(16).map(|d| d as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(line: &str) -> Result<Vec<String>, &'static str> {
+        split_args(line.as_bytes())
+            .map(|args| args.into_iter().map(|a| String::from_utf8(a).unwrap()).collect())
+    }
+
+    #[test]
+    fn splits_on_whitespace() {
+        assert_eq!(split("SET  key\tvalue ").unwrap(), ["SET", "key", "value"]);
+        assert!(split("   ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn double_quotes_unescape() {
+        assert_eq!(split(r#"SET k "a b\n\x41\"""#).unwrap(), ["SET", "k", "a b\nA\""]);
+        assert_eq!(split(r#""""#).unwrap(), [""]);
+        // Not a valid hex escape: the backslash is dropped as for any other
+        // unknown escape.
+        assert_eq!(split(r#""\xZZ""#).unwrap(), ["xZZ"]);
+    }
+
+    #[test]
+    fn single_quotes_are_literal() {
+        assert_eq!(split(r"'a\nb' 'it\'s'").unwrap(), [r"a\nb", "it's"]);
+    }
+
+    #[test]
+    fn backslash_outside_quotes_is_literal() {
+        assert_eq!(split(r"a\nb").unwrap(), [r"a\nb"]);
+    }
+
+    #[test]
+    fn unbalanced_quotes() {
+        assert_eq!(split(r#"SET "k"#), Err(UNBALANCED_QUOTES));
+        assert_eq!(split("SET 'k"), Err(UNBALANCED_QUOTES));
+        assert_eq!(split(r#""a"b"#), Err(UNBALANCED_QUOTES));
+        assert_eq!(split("'a'b"), Err(UNBALANCED_QUOTES));
+    }
+}
@@ -1,2 +1,3 @@
 pub mod parser;
-pub(crate) mod resp;
\ No newline at end of file
+pub mod inline;
+pub mod resp;
\ No newline at end of file
@@ -1,28 +1,136 @@
-use crate::db::storage::Db;
+use crate::db::storage::Databases;
 use crate::commands;
+use crate::protocol::inline;
 use crate::protocol::resp::encoder::RespValue;
+use crate::server::acl;
+use crate::server::clients;
+use crate::server::monitor;
+use crate::server::session::Session;
 
-pub async fn process_parts(parts: Vec<String>, db: &Db) -> RespValue {
+/// The reply to a request with an argument that is not valid UTF-8.
+pub const NOT_UTF8: &str = "ERR arguments must be valid UTF-8";
+
+pub async fn process_parts(parts: &[String], session: &mut Session, dbs: &Databases) -> RespValue {
     if parts.is_empty() {
         return RespValue::Error("ERR empty command".into());
     }
 
+    if let Err(e) = acl::check(session, parts) {
+        return e;
+    }
+    clients::wait_unpaused(&parts[0].to_lowercase()).await;
+    if monitor::active() {
+        monitor::feed(parts, session);
+    }
+
+    let db = match dbs.get(session.db) {
+        Some(db) => db,
+        None => return RespValue::Error("ERR DB index is out of range".into()),
+    };
+
     match parts[0].to_uppercase().as_str() {
         "PING" => RespValue::SimpleString("PONG".into()),
-        "SET" => commands::set::execute(parts, db).await,
-        "GET" => commands::get::execute(parts, db).await,
-        "INCR" => commands::incr::execute(parts, db).await,
-        "DEL" => commands::del::execute(parts, db).await,
-        "EXISTS" => commands::exists::execute(parts, db).await,
-        "EXPIRE" => commands::expire::execute(parts, db).await,
+        "HELLO" => commands::hello::execute(parts, session).await,
+        "AUTH" => commands::auth::execute(parts, session).await,
+        "ACL" => commands::acl::execute(parts, session).await,
+        "CLIENT" => commands::client::execute(parts, session).await,
+        "DEBUG" => commands::debug::execute(parts).await,
+        "INFO" => commands::info::execute(parts, dbs).await,
+        "CONFIG" => commands::config::execute(parts).await,
+        "SHUTDOWN" => commands::shutdown::execute(parts).await,
+        "SLOWLOG" => commands::slowlog::execute(parts).await,
+        "LATENCY" => commands::latency::execute(parts).await,
+        "SET" => commands::set::execute(parts, db, session.db).await,
+        "GET" => commands::get::execute(parts, db, session.db).await,
+        "INCR" => commands::incr::execute(parts, db, session.db).await,
+        "DEL" => commands::del::execute(parts, db, session.db).await,
+        "UNLINK" => commands::unlink::execute(parts, db, session.db).await,
+        "EXISTS" => commands::exists::execute(parts, db, session.db).await,
+        "TOUCH" => commands::touch::execute(parts, db, session.db).await,
+        "EXPIRE" => commands::expire::execute(parts, db, session.db).await,
         "KEYS" => commands::keys::execute(parts, db).await,
-        "FLUSHALL" => commands::flushall::execute(parts, db).await,
+        "SCAN" => commands::scan::execute(parts, db).await,
+        "TYPE" => commands::keytype::execute(parts, db).await,
+        "RENAME" => commands::rename::execute(parts, db, session.db).await,
+        "RENAMENX" => commands::rename::execute_nx(parts, db, session.db).await,
+        "COPY" => commands::copy::execute(parts, session, dbs).await,
+        "RANDOMKEY" => commands::randomkey::execute(parts, db, session.db).await,
+        "DBSIZE" => commands::dbsize::execute(parts, db).await,
+        "OBJECT" => commands::object::execute(parts, db).await,
+        "DUMP" => commands::dump::execute(parts, db).await,
+        "RESTORE" => commands::restore::execute(parts, db, session.db).await,
+        "SELECT" => commands::select::execute(parts, session, dbs).await,
+        "MOVE" => commands::move_key::execute(parts, session, dbs).await,
+        "SWAPDB" => commands::swapdb::execute(parts, dbs).await,
+        "FLUSHDB" => commands::flushdb::execute(parts, db).await,
+        "FLUSHALL" => commands::flushall::execute(parts, dbs).await,
+        "PUBLISH" => commands::publish::execute(parts).await,
+        "PUBSUB" => commands::pubsub::execute(parts).await,
+        "SUBSCRIBE" | "PSUBSCRIBE" | "UNSUBSCRIBE" | "PUNSUBSCRIBE" => {
+            RespValue::Error("ERR subscriptions are only available on the WebSocket endpoint".into())
+        }
+        "MONITOR" => RespValue::Error("ERR MONITOR is only available on RESP connections".into()),
         _ => RespValue::Error("ERR unknown command".into()),
     }
 }
 
-pub async fn process(input: String, db: &Db) -> String {
-    let parts: Vec<String> = input.split_whitespace().map(|s| s.to_string()).collect();
-    let resp = process_parts(parts, db).await;
+/// Runs one inline (telnet-style) command line, quoting rules included.
+pub async fn process(input: String, dbs: &Databases) -> String {
+    let resp = match inline::split_args(input.as_bytes()) {
+        Ok(args) => {
+            let parts: Option<Vec<String>> = args.into_iter().map(|a| String::from_utf8(a).ok()).collect();
+            match parts {
+                Some(parts) => process_parts(&parts, &mut Session::default(), dbs).await,
+                None => RespValue::Error(NOT_UTF8.into()),
+            }
+        }
+        Err(e) => RespValue::Error(format!("ERR Protocol error: {}", e)),
+    };
     String::from_utf8_lossy(&resp.to_bytes()).to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::resp::encoder::Protocol;
+
+    /// Runs a command and encodes its reply for RESP2 and RESP3.
+    async fn run(args: &[&str], session: &mut Session, dbs: &Databases) -> (String, String) {
+        let parts: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        let reply = process_parts(&parts, session, dbs).await;
+        let text = |p| String::from_utf8(reply.encode(p)).unwrap();
+        (text(Protocol::Resp2), text(Protocol::Resp3))
+    }
+
+    #[tokio::test]
+    async fn replies_use_resp3_types_and_resp2_shapes() {
+        let dbs = Databases::new(2);
+        let mut session = Session::new();
+        let same = |s: &str| (s.to_string(), s.to_string());
+        let pair = |resp2: &str, resp3: &str| (resp2.to_string(), resp3.to_string());
+
+        assert_eq!(run(&["SET", "k", "v"], &mut session, &dbs).await, same("+OK\r\n"));
+        assert_eq!(run(&["EXPIRE", "k", "100"], &mut session, &dbs).await, pair(":1\r\n", "#t\r\n"));
+        assert_eq!(run(&["EXPIRE", "missing", "100"], &mut session, &dbs).await, pair(":0\r\n", "#f\r\n"));
+        assert_eq!(run(&["COPY", "k", "k2"], &mut session, &dbs).await, pair(":1\r\n", "#t\r\n"));
+        assert_eq!(run(&["COPY", "k", "k2"], &mut session, &dbs).await, pair(":0\r\n", "#f\r\n"));
+        assert_eq!(run(&["RENAMENX", "k2", "k"], &mut session, &dbs).await, pair(":0\r\n", "#f\r\n"));
+        assert_eq!(run(&["RENAMENX", "k2", "k3"], &mut session, &dbs).await, pair(":1\r\n", "#t\r\n"));
+        assert_eq!(run(&["MOVE", "k3", "1"], &mut session, &dbs).await, pair(":1\r\n", "#t\r\n"));
+        assert_eq!(run(&["MOVE", "k3", "1"], &mut session, &dbs).await, pair(":0\r\n", "#f\r\n"));
+        // EXISTS counts keys, repeats included, so it stays an integer.
+        assert_eq!(run(&["EXISTS", "k", "k", "k3"], &mut session, &dbs).await, same(":2\r\n"));
+
+        let (resp2, resp3) = run(&["CONFIG", "GET", "maxclients"], &mut session, &dbs).await;
+        assert!(resp2.starts_with("*2\r\n$10\r\nmaxclients\r\n"), "{:?}", resp2);
+        assert!(resp3.starts_with("%1\r\n$10\r\nmaxclients\r\n"), "{:?}", resp3);
+
+        let (resp2, resp3) = run(&["ACL", "GETUSER", "default"], &mut session, &dbs).await;
+        assert!(resp2.starts_with("*10\r\n$5\r\nflags\r\n"), "{:?}", resp2);
+        assert!(resp3.starts_with("%5\r\n$5\r\nflags\r\n"), "{:?}", resp3);
+
+        let (resp2, resp3) = run(&["INFO", "server"], &mut session, &dbs).await;
+        assert!(resp2.starts_with('$') && resp2.contains("\r\n# Server"), "{:?}", resp2);
+        assert!(resp3.starts_with('=') && resp3.contains("\r\ntxt:# Server"), "{:?}", resp3);
+    }
+}
@@ -1,3 +1,22 @@
+/// Wire protocol version negotiated with HELLO.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Protocol {
+    #[default]
+    Resp2,
+    Resp3,
+}
+
+impl Protocol {
+    pub fn version(self) -> i64 {
+        match self {
+            Protocol::Resp2 => 2,
+            Protocol::Resp3 => 3,
+        }
+    }
+}
+
+/// A command reply. RESP3-only types are downgraded to their closest RESP2
+/// form when the connection has not negotiated RESP3.
 #[derive(Debug, Clone)]
 pub enum RespValue {
     SimpleString(String),
@@ -5,30 +24,171 @@ pub enum RespValue {
     Integer(i64),
     Bulk(Option<Vec<u8>>),
     Array(Vec<RespValue>),
+    /// RESP3 `_`; `$-1` in RESP2.
+    Null,
+    /// RESP3 `,`; a bulk string in RESP2.
+    Double(f64),
+    /// RESP3 `#`; `:1`/`:0` in RESP2.
+    Boolean(bool),
+    /// RESP3 `(`; a bulk string in RESP2.
+    BigNumber(String),
+    /// RESP3 `=` with a three letter format such as `txt`; a bulk string in RESP2.
+    Verbatim(&'static str, String),
+    /// RESP3 `%`; a flat key/value array in RESP2.
+    Map(Vec<(RespValue, RespValue)>),
+    /// RESP3 `~`; an array in RESP2.
+    Set(Vec<RespValue>),
+    /// RESP3 `|` metadata followed by the reply; just the reply in RESP2.
+    Attribute(Vec<(RespValue, RespValue)>, Box<RespValue>),
+    /// RESP3 `>` out-of-band message; an array in RESP2.
+    Push(Vec<RespValue>),
 }
 
 impl RespValue {
+    pub fn bulk(s: impl Into<String>) -> RespValue {
+        RespValue::Bulk(Some(s.into().into_bytes()))
+    }
+
+    /// RESP2 encoding.
     pub fn to_bytes(&self) -> Vec<u8> {
+        self.encode(Protocol::Resp2)
+    }
+
+    pub fn encode(&self, proto: Protocol) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.write_to(proto, &mut out);
+        out
+    }
+
+    fn write_to(&self, proto: Protocol, out: &mut Vec<u8>) {
+        let resp3 = proto == Protocol::Resp3;
+
         match self {
-            RespValue::SimpleString(s) => format!("+{}\r\n", s).into_bytes(),
-            RespValue::Error(s) => format!("-{}\r\n", s).into_bytes(),
-            RespValue::Integer(n) => format!(":{}\r\n", n).into_bytes(),
-            RespValue::Bulk(None) => b"$-1\r\n".to_vec(),
-            RespValue::Bulk(Some(b)) => {
-                let mut out = Vec::new();
-                out.extend_from_slice(format!("${}\r\n", b.len()).as_bytes());
-                out.extend_from_slice(b);
-                out.extend_from_slice(b"\r\n");
-                out
+            RespValue::SimpleString(s) => write_line(out, b'+', s),
+            RespValue::Error(s) => write_line(out, b'-', s),
+            RespValue::Integer(n) => write_line(out, b':', &n.to_string()),
+            RespValue::Bulk(None) | RespValue::Null if resp3 => out.extend_from_slice(b"_\r\n"),
+            RespValue::Bulk(None) | RespValue::Null => out.extend_from_slice(b"$-1\r\n"),
+            RespValue::Bulk(Some(b)) => write_blob(out, b'$', b),
+            RespValue::Array(items) => write_items(out, proto, b'*', items),
+            RespValue::Double(d) => {
+                let s = if d.is_infinite() {
+                    if *d > 0.0 { "inf".to_string() } else { "-inf".to_string() }
+                } else if d.is_nan() {
+                    "nan".to_string()
+                } else {
+                    d.to_string()
+                };
+                if resp3 {
+                    write_line(out, b',', &s);
+                } else {
+                    write_blob(out, b'$', s.as_bytes());
+                }
+            }
+            RespValue::Boolean(b) if resp3 => write_line(out, b'#', if *b { "t" } else { "f" }),
+            RespValue::Boolean(b) => write_line(out, b':', if *b { "1" } else { "0" }),
+            RespValue::BigNumber(n) if resp3 => write_line(out, b'(', n),
+            RespValue::BigNumber(n) => write_blob(out, b'$', n.as_bytes()),
+            RespValue::Verbatim(format, text) if resp3 => {
+                write_blob(out, b'=', format!("{}:{}", format, text).as_bytes())
+            }
+            RespValue::Verbatim(_, text) => write_blob(out, b'$', text.as_bytes()),
+            RespValue::Map(pairs) => {
+                if resp3 {
+                    write_line(out, b'%', &pairs.len().to_string());
+                } else {
+                    write_line(out, b'*', &(pairs.len() * 2).to_string());
+                }
+                for (k, v) in pairs {
+                    k.write_to(proto, out);
+                    v.write_to(proto, out);
+                }
             }
-            RespValue::Array(items) => {
-                let mut out = Vec::new();
-                out.extend_from_slice(format!("*{}\r\n", items.len()).as_bytes());
-                for it in items {
-                    out.extend_from_slice(&it.to_bytes());
+            RespValue::Set(items) => write_items(out, proto, if resp3 { b'~' } else { b'*' }, items),
+            RespValue::Attribute(attrs, value) => {
+                if resp3 {
+                    write_line(out, b'|', &attrs.len().to_string());
+                    for (k, v) in attrs {
+                        k.write_to(proto, out);
+                        v.write_to(proto, out);
+                    }
                 }
-                out
+                value.write_to(proto, out);
             }
+            RespValue::Push(items) => write_items(out, proto, if resp3 { b'>' } else { b'*' }, items),
+        }
+    }
+}
+
+fn write_line(out: &mut Vec<u8>, prefix: u8, s: &str) {
+    out.push(prefix);
+    out.extend_from_slice(s.as_bytes());
+    out.extend_from_slice(b"\r\n");
+}
+
+fn write_blob(out: &mut Vec<u8>, prefix: u8, b: &[u8]) {
+    write_line(out, prefix, &b.len().to_string());
+    out.extend_from_slice(b);
+    out.extend_from_slice(b"\r\n");
+}
+
+fn write_items(out: &mut Vec<u8>, proto: Protocol, prefix: u8, items: &[RespValue]) {
+    write_line(out, prefix, &items.len().to_string());
+    for it in items {
+        it.write_to(proto, out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn both(v: RespValue) -> (String, String) {
+        let text = |p| String::from_utf8(v.encode(p)).unwrap();
+        (text(Protocol::Resp2), text(Protocol::Resp3))
+    }
+
+    #[test]
+    fn shared_types_encode_the_same_in_both_protocols() {
+        let cases = [
+            (RespValue::SimpleString("OK".into()), "+OK\r\n"),
+            (RespValue::Error("ERR no".into()), "-ERR no\r\n"),
+            (RespValue::Integer(-7), ":-7\r\n"),
+            (RespValue::bulk("a\r\nb"), "$4\r\na\r\nb\r\n"),
+            (RespValue::Array(vec![RespValue::Integer(1), RespValue::bulk("x")]), "*2\r\n:1\r\n$1\r\nx\r\n"),
+        ];
+        for (v, want) in cases {
+            assert_eq!(both(v), (want.to_string(), want.to_string()));
+        }
+    }
+
+    #[test]
+    fn resp3_types_downgrade_to_resp2_shapes() {
+        let pair = |k: &str, v: RespValue| (RespValue::bulk(k), v);
+        let cases = [
+            (RespValue::Null, "$-1\r\n", "_\r\n"),
+            (RespValue::Bulk(None), "$-1\r\n", "_\r\n"),
+            (RespValue::Boolean(true), ":1\r\n", "#t\r\n"),
+            (RespValue::Boolean(false), ":0\r\n", "#f\r\n"),
+            (RespValue::Double(1.5), "$3\r\n1.5\r\n", ",1.5\r\n"),
+            (RespValue::Double(f64::NEG_INFINITY), "$4\r\n-inf\r\n", ",-inf\r\n"),
+            (RespValue::BigNumber("123456789012345678901".into()), "$21\r\n123456789012345678901\r\n", "(123456789012345678901\r\n"),
+            (RespValue::Verbatim("txt", "a\nb".into()), "$3\r\na\nb\r\n", "=7\r\ntxt:a\nb\r\n"),
+            (
+                RespValue::Map(vec![pair("k", RespValue::Integer(1)), pair("b", RespValue::Boolean(true))]),
+                "*4\r\n$1\r\nk\r\n:1\r\n$1\r\nb\r\n:1\r\n",
+                "%2\r\n$1\r\nk\r\n:1\r\n$1\r\nb\r\n#t\r\n",
+            ),
+            (RespValue::Set(vec![RespValue::bulk("m")]), "*1\r\n$1\r\nm\r\n", "~1\r\n$1\r\nm\r\n"),
+            (
+                RespValue::Attribute(vec![pair("ttl", RespValue::Integer(3))], Box::new(RespValue::bulk("v"))),
+                "$1\r\nv\r\n",
+                "|1\r\n$3\r\nttl\r\n:3\r\n$1\r\nv\r\n",
+            ),
+            (RespValue::Push(vec![RespValue::bulk("message")]), "*1\r\n$7\r\nmessage\r\n", ">1\r\n$7\r\nmessage\r\n"),
+        ];
+        for (v, resp2, resp3) in cases {
+            assert_eq!(both(v), (resp2.to_string(), resp3.to_string()));
         }
     }
 }
@@ -1,3 +1,2 @@
-pub mod types;
 pub mod parser;
-pub mod encoder;
\ No newline at end of file
+pub mod encoder;
@@ -1,78 +1,462 @@
-pub fn parse_resp_one(input: &[u8]) -> Result<Option<(Vec<String>, usize)>, String> {
-    let mut i = 0;
+use std::ops::Range;
 
-    fn read_line<'a>(input: &'a [u8], i: &mut usize) -> Option<&'a [u8]> {
-        let start = *i;
+use crate::protocol::inline;
 
-        while *i + 1 < input.len() {
-            if input[*i] == b'\r' && input[*i + 1] == b'\n' {
-                let line = &input[start..*i];
-                *i += 2;
-                return Some(line);
-            }
-            *i += 1;
+/// Longest `*<n>` / `$<n>` header line we wait for before giving up.
+const MAX_HEADER_LINE: usize = 64 * 1024;
+/// Upper bound for argument slots reserved from an untrusted `*<n>`.
+const MAX_ARGS_PREALLOC: usize = 1024;
+
+/// Size limits applied to every request, mirroring Redis' `proto-max-bulk-len`,
+/// `client-query-buffer-limit` and friends.
+#[derive(Debug, Clone, Copy)]
+pub struct ProtocolLimits {
+    /// Largest accepted `$<len>` bulk.
+    pub max_bulk_len: usize,
+    /// Largest accepted `*<n>` argument count.
+    pub max_multibulk_len: usize,
+    /// Longest accepted inline request line.
+    pub max_inline_len: usize,
+    /// Most unprocessed bytes a client may have buffered.
+    pub query_buffer_limit: usize,
+}
+
+impl Default for ProtocolLimits {
+    fn default() -> Self {
+        ProtocolLimits {
+            max_bulk_len: 512 * 1024 * 1024,
+            max_multibulk_len: 1024 * 1024,
+            max_inline_len: 64 * 1024,
+            query_buffer_limit: 1024 * 1024 * 1024,
         }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtocolError {
+    /// Short label for metrics, e.g. `bulk_len`.
+    pub reason: &'static str,
+    pub message: String,
+}
+
+impl std::fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Protocol error: {}", self.message)
+    }
+}
+
+fn err<T>(reason: &'static str, msg: impl Into<String>) -> Result<T, ProtocolError> {
+    Err(ProtocolError {
+        reason,
+        message: msg.into(),
+    })
+}
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    /// Nothing parsed yet for the current request.
+    Start,
+    /// Inside `*<n>`: `remaining` bulks left, next header expected at `pos`.
+    Header { remaining: usize, pos: usize },
+    /// Waiting for `len` bytes of bulk data (plus CRLF) starting at `start`.
+    Body { remaining: usize, start: usize, len: usize },
+}
+
+/// Incremental parser for client requests, both RESP multibulk
+/// (`*2\r\n$3\r\nGET\r\n$1\r\nk\r\n`) and inline (`GET k\r\n`).
+///
+/// The parser never copies or allocates per argument: it records byte
+/// ranges into the caller's read buffer. When a request is incomplete it
+/// keeps its position, so the next call resumes where it stopped instead of
+/// re-scanning the buffer from the start. This matters for large bulks that
+/// arrive over many reads and for deep pipelines.
+///
+/// The buffer passed to [`parse`](Self::parse) must start at the same byte
+/// across calls until a request is returned; the caller then drops
+/// [`Request::consumed`] bytes from the front.
+pub struct RequestParser {
+    limits: ProtocolLimits,
+    state: State,
+    args: Vec<Range<usize>>,
+    /// Arguments of an inline request that needed unquoting, which cannot
+    /// be expressed as ranges of the buffer.
+    unquoted: Vec<Vec<u8>>,
+    /// How far a pending line has already been scanned for `\r`.
+    scanned: usize,
+}
+
+enum Args<'a> {
+    Borrowed(&'a [u8], &'a [Range<usize>]),
+    Owned(&'a [Vec<u8>]),
+}
+
+/// A complete request whose arguments borrow from the read buffer.
+pub struct Request<'a> {
+    args: Args<'a>,
+    consumed: usize,
+}
 
-        *i = start;
-        None
+impl<'a> Request<'a> {
+    pub fn len(&self) -> usize {
+        match self.args {
+            Args::Borrowed(_, ranges) => ranges.len(),
+            Args::Owned(args) => args.len(),
+        }
     }
 
-    let header = match read_line(input, &mut i) {
-        Some(l) => l,
-        None => return Ok(None),
-    };
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 
-    if header.first() != Some(&b'*') {
-        return Err("expected array header *<n>".into());
+    /// Bytes of the buffer taken by this request.
+    pub fn consumed(&self) -> usize {
+        self.consumed
     }
 
-    let n: usize = std::str::from_utf8(&header[1..])
-        .map_err(|_| "invalid utf8 in array length")?
-        .parse()
-        .map_err(|_| "invalid array length")?;
+    pub fn arg(&self, i: usize) -> &'a [u8] {
+        match self.args {
+            Args::Borrowed(buf, ranges) => &buf[ranges[i].clone()],
+            Args::Owned(args) => &args[i],
+        }
+    }
+
+    pub fn args(&self) -> impl Iterator<Item = &'a [u8]> + '_ {
+        (0..self.len()).map(move |i| self.arg(i))
+    }
+
+    /// Owned copies of the arguments, in the form commands take them, or
+    /// `None` if one is not valid UTF-8: values are stored as text, so binary
+    /// data is refused rather than changed.
+    pub fn to_strings(&self) -> Option<Vec<String>> {
+        self.args()
+            .map(|a| std::str::from_utf8(a).ok().map(str::to_owned))
+            .collect()
+    }
+}
+
+impl Default for RequestParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RequestParser {
+    pub fn new() -> Self {
+        Self::with_limits(ProtocolLimits::default())
+    }
+
+    pub fn with_limits(limits: ProtocolLimits) -> Self {
+        RequestParser {
+            limits,
+            state: State::Start,
+            args: Vec::new(),
+            unquoted: Vec::new(),
+            scanned: 0,
+        }
+    }
+
+    /// Drops any partial request, e.g. after a protocol error.
+    pub fn reset(&mut self) {
+        self.state = State::Start;
+        self.args.clear();
+        self.scanned = 0;
+    }
+
+    /// Total buffer length needed to finish the bulk currently being read,
+    /// if known. Lets the caller grow its buffer once instead of repeatedly.
+    pub fn pending_len(&self) -> Option<usize> {
+        match self.state {
+            State::Body { start, len, .. } => Some(start + len + 2),
+            _ => None,
+        }
+    }
+
+    pub fn parse<'a>(&'a mut self, buf: &'a [u8]) -> Result<Option<Request<'a>>, ProtocolError> {
+        loop {
+            match self.state {
+                State::Start => {
+                    if buf.is_empty() {
+                        return Ok(None);
+                    }
+                    if buf[0] != b'*' {
+                        return self.parse_inline(buf);
+                    }
+
+                    let (line, next) = match self.find_line(buf, 0)? {
+                        Some(found) => found,
+                        None => return Ok(None),
+                    };
+                    let n = match parse_int(&line[1..]) {
+                        Some(n) if n <= self.limits.max_multibulk_len as i64 => n,
+                        _ => return err("multibulk_len", "invalid multibulk length"),
+                    };
+
+                    self.args.clear();
+                    if n <= 0 {
+                        return Ok(Some(self.finish(buf, next)));
+                    }
+                    self.args.reserve((n as usize).min(MAX_ARGS_PREALLOC));
+                    self.state = State::Header { remaining: n as usize, pos: next };
+                }
+
+                State::Header { remaining, pos } => {
+                    if pos >= buf.len() {
+                        return Ok(None);
+                    }
+                    if buf[pos] != b'$' {
+                        return err("syntax", format!("expected '$', got '{}'", buf[pos] as char));
+                    }
 
-    let mut parts = Vec::with_capacity(n);
+                    let (line, next) = match self.find_line(buf, pos)? {
+                        Some(found) => found,
+                        None => return Ok(None),
+                    };
+                    let len = match parse_int(&line[1..]) {
+                        Some(len) if len >= 0 && len as u64 <= self.limits.max_bulk_len as u64 => len as usize,
+                        _ => return err("bulk_len", "invalid bulk length"),
+                    };
+                    self.state = State::Body { remaining, start: next, len };
+                }
 
-    for _ in 0..n {
-        let bulk_hdr = match read_line(input, &mut i) {
-            Some(l) => l,
+                State::Body { remaining, start, len } => {
+                    let end = start + len;
+                    if buf.len() < end + 2 {
+                        return Ok(None);
+                    }
+                    if &buf[end..end + 2] != b"\r\n" {
+                        return err("syntax", "bulk data not terminated with CRLF");
+                    }
+
+                    self.args.push(start..end);
+                    if remaining == 1 {
+                        return Ok(Some(self.finish(buf, end + 2)));
+                    }
+                    self.state = State::Header { remaining: remaining - 1, pos: end + 2 };
+                }
+            }
+        }
+    }
+
+    fn parse_inline<'a>(&'a mut self, buf: &'a [u8]) -> Result<Option<Request<'a>>, ProtocolError> {
+        let (line, next) = match self.find_line_lf(buf)? {
+            Some(found) => found,
             None => return Ok(None),
         };
 
-        if bulk_hdr.first() != Some(&b'$') {
-            return Err("expected bulk header $<len>".into());
+        // Quotes need unescaping into new buffers; plain lines are split in
+        // place like multibulk requests.
+        if memchr::memchr2(b'"', b'\'', line).is_some() {
+            self.unquoted = inline::split_args(line)
+                .map_err(|e| ProtocolError { reason: "syntax", message: e.into() })?;
+            self.state = State::Start;
+            self.scanned = 0;
+            return Ok(Some(Request {
+                args: Args::Owned(&self.unquoted),
+                consumed: next,
+            }));
+        }
+
+        self.args.clear();
+        let mut i = 0;
+        while i < line.len() {
+            while i < line.len() && inline::is_space(line[i]) {
+                i += 1;
+            }
+            let start = i;
+            while i < line.len() && !inline::is_space(line[i]) {
+                i += 1;
+            }
+            if i > start {
+                self.args.push(start..i);
+            }
         }
 
-        let len_i64: i64 = std::str::from_utf8(&bulk_hdr[1..])
-            .map_err(|_| "invalid utf8 in bulk length")?
-            .parse()
-            .map_err(|_| "invalid bulk length")?;
+        Ok(Some(self.finish(buf, next)))
+    }
 
-        if len_i64 < -1 {
-            return Err("bulk length < -1".into());
+    fn finish<'a>(&'a mut self, buf: &'a [u8], consumed: usize) -> Request<'a> {
+        self.state = State::Start;
+        self.scanned = 0;
+        Request {
+            args: Args::Borrowed(buf, &self.args),
+            consumed,
         }
+    }
+
+    /// Finds the CRLF-terminated line starting at `from`. Returns the line
+    /// without its terminator and the offset just past it.
+    fn find_line<'a>(&mut self, buf: &'a [u8], from: usize) -> Result<Option<(&'a [u8], usize)>, ProtocolError> {
+        let scan_from = from.max(self.scanned);
+
+        match memchr::memchr(b'\r', &buf[scan_from..]) {
+            Some(off) => {
+                let cr = scan_from + off;
+                if cr + 1 >= buf.len() {
+                    self.scanned = cr;
+                    return Ok(None);
+                }
+                if buf[cr + 1] != b'\n' {
+                    return err("syntax", "expected CRLF after header");
+                }
+                self.scanned = 0;
+                Ok(Some((&buf[from..cr], cr + 2)))
+            }
+            None => {
+                if buf.len() - from > MAX_HEADER_LINE {
+                    return err("header_len", "too big mbulk count string");
+                }
+                self.scanned = buf.len();
+                Ok(None)
+            }
+        }
+    }
+
+    /// Inline requests end at `\n`, with an optional `\r` before it.
+    fn find_line_lf<'a>(&mut self, buf: &'a [u8]) -> Result<Option<(&'a [u8], usize)>, ProtocolError> {
+        match memchr::memchr(b'\n', &buf[self.scanned..]) {
+            Some(off) => {
+                let lf = self.scanned + off;
+                if lf > self.limits.max_inline_len {
+                    return err("inline_len", "too big inline request");
+                }
+                let line = &buf[..lf];
+                let line = line.strip_suffix(b"\r").unwrap_or(line);
+                Ok(Some((line, lf + 1)))
+            }
+            None => {
+                if buf.len() > self.limits.max_inline_len {
+                    return err("inline_len", "too big inline request");
+                }
+                self.scanned = buf.len();
+                Ok(None)
+            }
+        }
+    }
+}
+
+fn parse_int(digits: &[u8]) -> Option<i64> {
+    std::str::from_utf8(digits).ok()?.parse().ok()
+}
 
-        if len_i64 == -1 {
-            parts.push(String::new());
-            continue;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_all(parser: &mut RequestParser, buf: &[u8]) -> Vec<Vec<String>> {
+        let mut out = Vec::new();
+        let mut pos = 0;
+        while let Some(req) = parser.parse(&buf[pos..]).unwrap() {
+            pos += req.consumed();
+            out.push(req.to_strings().unwrap());
         }
+        assert_eq!(pos, buf.len(), "unparsed bytes left");
+        out
+    }
+
+    #[test]
+    fn multibulk() {
+        let buf = b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$5\r\nv\r\nv!\r\n";
+        let mut parser = RequestParser::new();
+        let req = parser.parse(buf).unwrap().unwrap();
+        assert_eq!(req.consumed(), buf.len());
+        assert_eq!(req.to_strings().unwrap(), ["SET", "k", "v\r\nv!"]);
+    }
+
+    #[test]
+    fn refuses_non_utf8_arguments() {
+        let mut parser = RequestParser::new();
+        let req = parser.parse(b"*2\r\n$3\r\nGET\r\n$2\r\n\xff\xfe\r\n").unwrap().unwrap();
+        assert_eq!(req.arg(1), b"\xff\xfe");
+        assert_eq!(req.to_strings(), None);
+    }
 
-        let len = len_i64 as usize;
+    #[test]
+    fn pipeline() {
+        let buf = b"*1\r\n$4\r\nPING\r\n*2\r\n$3\r\nGET\r\n$1\r\nk\r\nECHO hi\r\n";
+        let reqs = parse_all(&mut RequestParser::new(), buf);
+        assert_eq!(reqs, [vec!["PING"], vec!["GET", "k"], vec!["ECHO", "hi"]]);
+    }
 
-        if i + len + 2 > input.len() {
-            return Ok(None);
+    #[test]
+    fn resumes_across_reads() {
+        let buf = b"*2\r\n$3\r\nGET\r\n$10\r\n0123456789\r\n";
+        let mut parser = RequestParser::new();
+        // The caller keeps the buffer's start fixed and feeds more each time.
+        for end in 0..buf.len() {
+            assert!(parser.parse(&buf[..end]).unwrap().is_none(), "complete at {}", end);
         }
+        let req = parser.parse(buf).unwrap().unwrap();
+        assert_eq!(req.to_strings().unwrap(), ["GET", "0123456789"]);
+        assert_eq!(parser.pending_len(), None);
+    }
 
-        let data = &input[i..i + len];
-        i += len;
+    #[test]
+    fn reports_pending_bulk_length() {
+        let mut parser = RequestParser::new();
+        let buf = b"*1\r\n$100\r\nabc";
+        assert!(parser.parse(buf).unwrap().is_none());
+        assert_eq!(parser.pending_len(), Some(b"*1\r\n$100\r\n".len() + 100 + 2));
+    }
 
-        if input.get(i) != Some(&b'\r') || input.get(i + 1) != Some(&b'\n') {
-            return Err("bulk data not terminated with \\r\\n".into());
+    #[test]
+    fn empty_multibulk() {
+        let mut parser = RequestParser::new();
+        let req = parser.parse(b"*0\r\n").unwrap().unwrap();
+        assert!(req.is_empty());
+        assert_eq!(req.consumed(), 4);
+    }
+
+    #[test]
+    fn inline() {
+        let reqs = parse_all(&mut RequestParser::new(), b"SET k v\r\nGET k\n\r\n");
+        assert_eq!(reqs, [vec!["SET", "k", "v"], vec!["GET", "k"], vec![]]);
+    }
+
+    #[test]
+    fn inline_quotes() {
+        let reqs = parse_all(&mut RequestParser::new(), b"SET k \"a b\"\r\n");
+        assert_eq!(reqs, [vec!["SET", "k", "a b"]]);
+
+        let err = RequestParser::new().parse(b"SET k \"a b\r\n").err().unwrap();
+        assert_eq!(err.reason, "syntax");
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let cases: [(&[u8], &str); 4] = [
+            (b"*x\r\n", "multibulk_len"),
+            (b"*1\r\n+OK\r\n", "syntax"),
+            (b"*1\r\n$-1\r\n", "bulk_len"),
+            (b"*1\r\n$2\r\nabcd\r\n", "syntax"),
+        ];
+        for (buf, reason) in cases {
+            let err = RequestParser::new().parse(buf).err();
+            assert_eq!(err.map(|e| e.reason), Some(reason), "{:?}", String::from_utf8_lossy(buf));
         }
-        i += 2;
+    }
 
-        parts.push(String::from_utf8_lossy(data).to_string());
+    #[test]
+    fn enforces_limits() {
+        let limits = ProtocolLimits {
+            max_bulk_len: 4,
+            max_multibulk_len: 2,
+            max_inline_len: 8,
+            ..ProtocolLimits::default()
+        };
+        let reason = |buf: &[u8]| RequestParser::with_limits(limits).parse(buf).err().map(|e| e.reason);
+
+        assert_eq!(reason(b"*1\r\n$5\r\n"), Some("bulk_len"));
+        assert_eq!(reason(b"*3\r\n"), Some("multibulk_len"));
+        assert_eq!(reason(b"GET abcdefgh"), Some("inline_len"));
+        assert_eq!(reason(b"*2\r\n$4\r\nabcd\r\n"), None);
     }
 
-    Ok(Some((parts, i)))
+    #[test]
+    fn reset_drops_partial_request() {
+        let mut parser = RequestParser::new();
+        assert!(parser.parse(b"*2\r\n$3\r\nGET\r\n").unwrap().is_none());
+        parser.reset();
+        let req = parser.parse(b"PING\r\n").unwrap().unwrap();
+        assert_eq!(req.to_strings().unwrap(), ["PING"]);
+    }
 }
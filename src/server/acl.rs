@@ -0,0 +1,945 @@
+//! Users and what they may do: run commands (by name or category), touch keys
+//! and use channels, each limited by glob patterns. Rules follow Redis' ACL
+//! syntax. The `default` user starts with every permission and no password,
+//! so nothing is restricted until it is configured.
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use once_cell::sync::Lazy;
+
+use crate::protocol::resp::encoder::RespValue;
+use crate::server::clients;
+use crate::server::session::Session;
+use crate::util::glob::glob_match;
+
+pub const DEFAULT_USER: &str = "default";
+
+/// Entries kept by ACL LOG, Redis' `acllog-max-len`.
+const LOG_MAX_LEN: usize = 128;
+/// Denials this close together with the same reason, object and user are
+/// counted in one log entry.
+const LOG_GROUP_MS: u64 = 60_000;
+
+pub const CATEGORIES: &[&str] = &[
+    "keyspace",
+    "read",
+    "write",
+    "string",
+    "pubsub",
+    "admin",
+    "dangerous",
+    "connection",
+    "fast",
+    "slow",
+];
+
+/// A command's categories and where its keys are: arguments `first..=last`
+/// every `step`, with `last == -1` meaning through the end. `first == 0`
+/// means the command takes no keys.
+struct CommandSpec {
+    name: &'static str,
+    categories: &'static [&'static str],
+    first: usize,
+    last: isize,
+    step: usize,
+}
+
+const fn spec(
+    name: &'static str,
+    categories: &'static [&'static str],
+    first: usize,
+    last: isize,
+    step: usize,
+) -> CommandSpec {
+    CommandSpec {
+        name,
+        categories,
+        first,
+        last,
+        step,
+    }
+}
+
+static COMMANDS: &[CommandSpec] = &[
+    spec("ping", &["fast", "connection"], 0, 0, 0),
+    spec("hello", &["fast", "connection"], 0, 0, 0),
+    spec("auth", &["fast", "connection"], 0, 0, 0),
+    spec("client", &["slow", "connection"], 0, 0, 0),
+    // Subcommands that act on other clients. The rest of CLIENT goes by
+    // the command's own categories.
+    spec("client|kill", &["admin", "slow", "dangerous"], 0, 0, 0),
+    spec("client|pause", &["admin", "slow", "dangerous"], 0, 0, 0),
+    spec("client|unpause", &["admin", "slow", "dangerous"], 0, 0, 0),
+    spec("client|no-evict", &["admin", "slow", "dangerous"], 0, 0, 0),
+    spec("select", &["fast", "connection"], 0, 0, 0),
+    spec("debug", &["admin", "slow", "dangerous"], 0, 0, 0),
+    spec("info", &["slow", "dangerous"], 0, 0, 0),
+    spec("acl", &["admin", "slow", "dangerous"], 0, 0, 0),
+    spec("config", &["admin", "slow", "dangerous"], 0, 0, 0),
+    spec("shutdown", &["admin", "slow", "dangerous"], 0, 0, 0),
+    spec("slowlog", &["admin", "slow", "dangerous"], 0, 0, 0),
+    spec("monitor", &["admin", "slow", "dangerous"], 0, 0, 0),
+    spec("latency", &["admin", "slow", "dangerous"], 0, 0, 0),
+    spec("set", &["write", "string", "slow"], 1, 1, 1),
+    spec("get", &["read", "string", "fast"], 1, 1, 1),
+    spec("incr", &["write", "string", "fast"], 1, 1, 1),
+    spec("del", &["keyspace", "write", "slow"], 1, -1, 1),
+    spec("unlink", &["keyspace", "write", "fast"], 1, -1, 1),
+    spec("exists", &["keyspace", "read", "fast"], 1, -1, 1),
+    spec("touch", &["keyspace", "read", "fast"], 1, -1, 1),
+    spec("expire", &["keyspace", "write", "fast"], 1, 1, 1),
+    spec("keys", &["keyspace", "read", "slow", "dangerous"], 0, 0, 0),
+    spec("scan", &["keyspace", "read", "slow"], 0, 0, 0),
+    spec("type", &["keyspace", "read", "fast"], 1, 1, 1),
+    spec("rename", &["keyspace", "write", "slow"], 1, 2, 1),
+    spec("renamenx", &["keyspace", "write", "fast"], 1, 2, 1),
+    spec("copy", &["keyspace", "write", "slow"], 1, 2, 1),
+    spec("randomkey", &["keyspace", "read", "slow"], 0, 0, 0),
+    spec("dbsize", &["keyspace", "read", "fast"], 0, 0, 0),
+    spec("object", &["keyspace", "read", "slow"], 2, 2, 1),
+    spec("dump", &["keyspace", "read", "slow"], 1, 1, 1),
+    spec(
+        "restore",
+        &["keyspace", "write", "slow", "dangerous"],
+        1,
+        1,
+        1,
+    ),
+    spec("move", &["keyspace", "write", "fast"], 1, 1, 1),
+    spec(
+        "swapdb",
+        &["keyspace", "write", "fast", "dangerous"],
+        0,
+        0,
+        0,
+    ),
+    spec(
+        "flushdb",
+        &["keyspace", "write", "slow", "dangerous"],
+        0,
+        0,
+        0,
+    ),
+    spec(
+        "flushall",
+        &["keyspace", "write", "slow", "dangerous"],
+        0,
+        0,
+        0,
+    ),
+    spec("publish", &["pubsub", "fast"], 0, 0, 0),
+    spec("pubsub", &["pubsub", "slow"], 0, 0, 0),
+    spec("subscribe", &["pubsub", "slow"], 0, 0, 0),
+    spec("psubscribe", &["pubsub", "slow"], 0, 0, 0),
+    spec("unsubscribe", &["pubsub", "slow"], 0, 0, 0),
+    spec("punsubscribe", &["pubsub", "slow"], 0, 0, 0),
+];
+
+fn command_spec(name: &str) -> Option<&'static CommandSpec> {
+    COMMANDS.iter().find(|c| c.name == name)
+}
+
+/// The name rules are matched against: `command|subcommand` where the
+/// subcommand has its own spec, such as `client|kill`, else `cmd`.
+fn rule_name(cmd: &str, parts: &[String]) -> String {
+    if let Some(sub) = parts.get(1) {
+        let name = format!("{}|{}", cmd, sub.to_ascii_lowercase());
+        if command_spec(&name).is_some() {
+            return name;
+        }
+    }
+    cmd.to_string()
+}
+
+/// Commands a client may send before authenticating.
+fn allowed_before_auth(cmd: &str) -> bool {
+    matches!(cmd, "auth" | "hello")
+}
+
+/// Redis stores passwords as SHA-256 hex digests; so do we.
+pub fn hash_password(password: &str) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA256, password.as_bytes());
+    digest
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct User {
+    pub name: String,
+    enabled: bool,
+    nopass: bool,
+    /// SHA-256 hex digests.
+    passwords: BTreeSet<String>,
+    /// Command rules in the order given, e.g. `+@all`, `-flushall`.
+    commands: Vec<String>,
+    keys: Vec<String>,
+    channels: Vec<String>,
+}
+
+impl User {
+    /// A new user: disabled, without passwords or permissions.
+    pub fn new(name: &str) -> User {
+        User {
+            name: name.to_string(),
+            enabled: false,
+            nopass: false,
+            passwords: BTreeSet::new(),
+            commands: Vec::new(),
+            keys: Vec::new(),
+            channels: Vec::new(),
+        }
+    }
+
+    fn default_user() -> User {
+        let mut user = User::new(DEFAULT_USER);
+        for rule in ["on", "nopass", "allkeys", "allchannels", "allcommands"] {
+            user.apply(rule).expect("valid default rules");
+        }
+        user
+    }
+
+    /// Applies one ACL SETUSER rule.
+    pub fn apply(&mut self, rule: &str) -> Result<(), String> {
+        let syntax = || format!("Error in ACL SETUSER modifier '{}': Syntax error", rule);
+
+        match rule.to_ascii_lowercase().as_str() {
+            "on" => self.enabled = true,
+            "off" => self.enabled = false,
+            "nopass" => {
+                self.nopass = true;
+                self.passwords.clear();
+            }
+            "resetpass" => {
+                self.nopass = false;
+                self.passwords.clear();
+            }
+            "allkeys" => self.keys = vec!["*".into()],
+            "resetkeys" => self.keys.clear(),
+            "allchannels" => self.channels = vec!["*".into()],
+            "resetchannels" => self.channels.clear(),
+            "allcommands" => return self.apply("+@all"),
+            "nocommands" => return self.apply("-@all"),
+            "reset" => {
+                *self = User::new(&self.name);
+            }
+            _ => {
+                let (prefix, rest) = rule.split_at(rule.chars().next().map_or(0, char::len_utf8));
+                match prefix {
+                    ">" => {
+                        self.passwords.insert(hash_password(rest));
+                        self.nopass = false;
+                    }
+                    "<" => {
+                        self.passwords.remove(&hash_password(rest));
+                    }
+                    "#" if rest.len() == 64 && rest.bytes().all(|b| b.is_ascii_hexdigit()) => {
+                        self.passwords.insert(rest.to_ascii_lowercase());
+                        self.nopass = false;
+                    }
+                    "!" if rest.len() == 64 => {
+                        self.passwords.remove(&rest.to_ascii_lowercase());
+                    }
+                    "~" if !rest.is_empty() => self.keys.push(rest.to_string()),
+                    "&" if !rest.is_empty() => self.channels.push(rest.to_string()),
+                    "+" | "-" => self.add_command_rule(prefix, rest, rule)?,
+                    _ => return Err(syntax()),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn add_command_rule(&mut self, sign: &str, target: &str, rule: &str) -> Result<(), String> {
+        let target = target.to_ascii_lowercase();
+        if let Some(cat) = target.strip_prefix('@') {
+            if cat == "all" {
+                // Everything before is overridden.
+                self.commands.clear();
+            } else if !CATEGORIES.contains(&cat) {
+                return Err(format!(
+                    "Error in ACL SETUSER modifier '{}': Unknown command category",
+                    rule
+                ));
+            }
+        } else if command_spec(&target).is_none() {
+            return Err(format!(
+                "Error in ACL SETUSER modifier '{}': Unknown command",
+                rule
+            ));
+        }
+        self.commands.push(format!("{}{}", sign, target));
+        Ok(())
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn check_password(&self, password: &str) -> bool {
+        self.nopass || self.passwords.contains(&hash_password(password))
+    }
+
+    /// Whether `cmd` (lower case) is allowed, by the last rule that matches.
+    /// A rule naming a command also covers its subcommands.
+    fn can_run(&self, cmd: &str) -> bool {
+        let cats = command_spec(cmd).map_or(&[][..], |c| c.categories);
+        let mut allowed = false;
+        for rule in &self.commands {
+            let (sign, target) = rule.split_at(1);
+            let matches = match target.strip_prefix('@') {
+                Some("all") => true,
+                Some(cat) => cats.contains(&cat),
+                None => {
+                    target == cmd
+                        || cmd.strip_prefix(target).is_some_and(|sub| sub.starts_with('|'))
+                }
+            };
+            if matches {
+                allowed = sign == "+";
+            }
+        }
+        allowed
+    }
+
+    fn can_access_key(&self, key: &str) -> bool {
+        self.keys.iter().any(|p| glob_match(p, key))
+    }
+
+    fn can_use_channel(&self, channel: &str) -> bool {
+        self.channels.iter().any(|p| glob_match(p, channel))
+    }
+
+    /// A pattern subscription must be granted as such, not just match.
+    fn can_use_pattern(&self, pattern: &str) -> bool {
+        self.channels.iter().any(|p| p == "*" || p == pattern)
+    }
+
+    fn flags(&self) -> Vec<&'static str> {
+        let mut flags = vec![if self.enabled { "on" } else { "off" }];
+        if self.nopass {
+            flags.push("nopass");
+        }
+        flags
+    }
+
+    fn command_rules(&self) -> String {
+        if self.commands.is_empty() {
+            "-@all".into()
+        } else {
+            self.commands.join(" ")
+        }
+    }
+
+    fn key_rules(&self) -> String {
+        self.keys
+            .iter()
+            .map(|k| format!("~{}", k))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn channel_rules(&self) -> String {
+        self.channels
+            .iter()
+            .map(|c| format!("&{}", c))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// The user as one ACL LIST / ACL file line.
+    pub fn describe(&self) -> String {
+        let mut parts = vec!["user".to_string(), self.name.clone()];
+        parts.extend(self.flags().into_iter().map(String::from));
+        parts.extend(self.passwords.iter().map(|h| format!("#{}", h)));
+        if self.keys.is_empty() {
+            parts.push("resetkeys".into());
+        } else {
+            parts.push(self.key_rules());
+        }
+        if self.channels.is_empty() {
+            parts.push("resetchannels".into());
+        } else {
+            parts.push(self.channel_rules());
+        }
+        parts.push(self.command_rules());
+        parts.join(" ")
+    }
+
+    /// ACL GETUSER's reply.
+    pub fn to_resp(&self) -> RespValue {
+        let strings =
+            |items: Vec<String>| RespValue::Array(items.into_iter().map(RespValue::bulk).collect());
+        RespValue::Map(vec![
+            (
+                RespValue::bulk("flags"),
+                strings(self.flags().into_iter().map(String::from).collect()),
+            ),
+            (
+                RespValue::bulk("passwords"),
+                strings(self.passwords.iter().cloned().collect()),
+            ),
+            (
+                RespValue::bulk("commands"),
+                RespValue::bulk(self.command_rules()),
+            ),
+            (RespValue::bulk("keys"), RespValue::bulk(self.key_rules())),
+            (
+                RespValue::bulk("channels"),
+                RespValue::bulk(self.channel_rules()),
+            ),
+        ])
+    }
+}
+
+/// One ACL LOG entry.
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    pub id: u64,
+    pub count: u64,
+    /// `command`, `key`, `channel` or `auth`.
+    pub reason: &'static str,
+    pub object: String,
+    pub username: String,
+    pub client_info: String,
+    pub created_ms: u64,
+    pub updated_ms: u64,
+}
+
+struct Acl {
+    users: BTreeMap<String, User>,
+    log: VecDeque<LogEntry>,
+    next_log_id: u64,
+    file: Option<PathBuf>,
+}
+
+static ACL: Lazy<RwLock<Acl>> = Lazy::new(|| {
+    let mut users = BTreeMap::new();
+    users.insert(DEFAULT_USER.to_string(), User::default_user());
+    RwLock::new(Acl {
+        users,
+        log: VecDeque::new(),
+        next_log_id: 0,
+        file: None,
+    })
+});
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+fn log_denial(reason: &'static str, object: &str, username: &str, session_id: u64) {
+    let now = now_ms();
+    let client_info = clients::get(session_id)
+        .map(|c| c.describe())
+        .unwrap_or_default();
+    let mut acl = ACL.write().unwrap();
+
+    if let Some(entry) = acl.log.iter_mut().find(|e| {
+        e.reason == reason
+            && e.object == object
+            && e.username == username
+            && now.saturating_sub(e.updated_ms) < LOG_GROUP_MS
+    }) {
+        entry.count += 1;
+        entry.updated_ms = now;
+        entry.client_info = client_info;
+        return;
+    }
+
+    let id = acl.next_log_id;
+    acl.next_log_id += 1;
+    acl.log.push_front(LogEntry {
+        id,
+        count: 1,
+        reason,
+        object: object.to_string(),
+        username: username.to_string(),
+        client_info,
+        created_ms: now,
+        updated_ms: now,
+    });
+    acl.log.truncate(LOG_MAX_LEN);
+}
+
+/// Whether new connections start out authenticated as `default`.
+pub fn default_authenticated() -> bool {
+    let acl = ACL.read().unwrap();
+    acl.users
+        .get(DEFAULT_USER)
+        .is_some_and(|u| u.enabled && u.nopass)
+}
+
+/// Whether `name` exists and is enabled, e.g. for a TLS certificate's CN.
+pub fn is_enabled(name: &str) -> bool {
+    ACL.read()
+        .unwrap()
+        .users
+        .get(name)
+        .is_some_and(|u| u.enabled)
+}
+
+/// Checks a username/password pair; failures go to the ACL log.
+pub fn authenticate(username: &str, password: &str, session_id: u64) -> bool {
+    let ok = ACL
+        .read()
+        .unwrap()
+        .users
+        .get(username)
+        .is_some_and(|u| u.enabled && u.check_password(password));
+    if !ok {
+        log_denial("auth", "AUTH", username, session_id);
+    }
+    ok
+}
+
+/// Whether the default user has a password AUTH could check.
+pub fn default_has_password() -> bool {
+    ACL.read()
+        .unwrap()
+        .users
+        .get(DEFAULT_USER)
+        .is_some_and(|u| !u.nopass)
+}
+
+/// Runs before every command: the client must be authenticated and its
+/// user allowed to run the command on its keys and channels.
+pub fn check(session: &mut Session, parts: &[String]) -> Result<(), RespValue> {
+    let cmd = parts[0].to_ascii_lowercase();
+    if allowed_before_auth(&cmd) {
+        return Ok(());
+    }
+    if !session.authenticated {
+        return Err(RespValue::Error("NOAUTH Authentication required.".into()));
+    }
+
+    let acl = ACL.read().unwrap();
+    let Some(user) = acl.users.get(&session.user).filter(|u| u.enabled) else {
+        // Deleted or disabled since this client authenticated.
+        drop(acl);
+        session.authenticated = false;
+        return Err(RespValue::Error("NOAUTH Authentication required.".into()));
+    };
+
+    // ACL WHOAMI is for everyone, as it only reports on the caller.
+    let whoami = cmd == "acl"
+        && parts
+            .get(1)
+            .is_some_and(|s| s.eq_ignore_ascii_case("whoami"));
+    let name = rule_name(&cmd, parts);
+    if !whoami && !user.can_run(&name) {
+        let username = user.name.clone();
+        drop(acl);
+        log_denial("command", &name, &username, session.id);
+        return Err(RespValue::Error(format!(
+            "NOPERM User {} has no permissions to run the '{}' command",
+            username, name
+        )));
+    }
+
+    let mut denial: Option<(&'static str, &String)> = None;
+    if let Some(spec) = command_spec(&cmd).filter(|s| s.first > 0) {
+        let last = if spec.last < 0 {
+            parts.len().saturating_sub(1)
+        } else {
+            (spec.last as usize).min(parts.len().saturating_sub(1))
+        };
+        let mut i = spec.first;
+        while i <= last && i < parts.len() {
+            if !user.can_access_key(&parts[i]) {
+                denial = Some(("key", &parts[i]));
+                break;
+            }
+            i += spec.step;
+        }
+    }
+
+    if denial.is_none() {
+        let channels: &[String] = match cmd.as_str() {
+            "publish" => parts.get(1..2).unwrap_or(&[]),
+            "subscribe" | "psubscribe" => &parts[1..],
+            _ => &[],
+        };
+        for ch in channels {
+            let ok = if cmd == "psubscribe" {
+                user.can_use_pattern(ch)
+            } else {
+                user.can_use_channel(ch)
+            };
+            if !ok {
+                denial = Some(("channel", ch));
+                break;
+            }
+        }
+    }
+    let username = user.name.clone();
+    drop(acl);
+
+    match denial {
+        None => Ok(()),
+        Some((reason, object)) => {
+            log_denial(reason, object, &username, session.id);
+            Err(RespValue::Error(format!(
+                "NOPERM No permissions to access a {}",
+                reason
+            )))
+        }
+    }
+}
+
+/// Creates or changes a user. Rules are applied all or nothing.
+pub fn set_user(name: &str, rules: &[String]) -> Result<(), String> {
+    let mut acl = ACL.write().unwrap();
+    let mut user = acl
+        .users
+        .get(name)
+        .cloned()
+        .unwrap_or_else(|| User::new(name));
+    for rule in rules {
+        user.apply(rule)?;
+    }
+    acl.users.insert(name.to_string(), user);
+    Ok(())
+}
+
+pub fn get_user(name: &str) -> Option<User> {
+    ACL.read().unwrap().users.get(name).cloned()
+}
+
+/// Removes users and returns how many existed.
+pub fn del_users(names: &[String]) -> Result<usize, String> {
+    if names.iter().any(|n| n == DEFAULT_USER) {
+        return Err("The 'default' user cannot be removed".into());
+    }
+    let mut acl = ACL.write().unwrap();
+    Ok(names
+        .iter()
+        .filter(|n| acl.users.remove(n.as_str()).is_some())
+        .count())
+}
+
+pub fn users() -> Vec<User> {
+    ACL.read().unwrap().users.values().cloned().collect()
+}
+
+/// Whether a command, in lower case, belongs to a category such as `write`.
+/// Whether the server has a command by this (lower case) name.
+pub fn is_command(cmd: &str) -> bool {
+    command_spec(cmd).is_some()
+}
+
+pub fn in_category(cmd: &str, category: &str) -> bool {
+    command_spec(cmd).is_some_and(|c| c.categories.contains(&category))
+}
+
+/// Whether argument `i` of a command is a password, which logs and
+/// MONITOR show as `(redacted)`: everything after `AUTH`, and the
+/// credentials after `HELLO ... AUTH`.
+pub fn is_secret_arg(parts: &[String], i: usize) -> bool {
+    if i == 0 {
+        return false;
+    }
+    if parts[0].eq_ignore_ascii_case("auth") {
+        return true;
+    }
+    parts[0].eq_ignore_ascii_case("hello")
+        && parts[1..i].iter().rev().take(2).any(|a| a.eq_ignore_ascii_case("auth"))
+}
+
+/// Command names in a category, for ACL CAT.
+pub fn category_commands(cat: &str) -> Option<Vec<&'static str>> {
+    CATEGORIES.contains(&cat).then(|| {
+        COMMANDS
+            .iter()
+            .filter(|c| c.categories.contains(&cat))
+            .map(|c| c.name)
+            .collect()
+    })
+}
+
+pub fn log(count: usize) -> Vec<LogEntry> {
+    ACL.read()
+        .unwrap()
+        .log
+        .iter()
+        .take(count)
+        .cloned()
+        .collect()
+}
+
+pub fn reset_log() {
+    ACL.write().unwrap().log.clear();
+}
+
+/// Parses an ACL file: one `user <name> <rules...>` per line; blank lines
+/// and lines starting with `#` are skipped.
+fn parse_file(path: &Path) -> Result<BTreeMap<String, User>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut users = BTreeMap::new();
+
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.len() < 2 || words[0] != "user" {
+            return Err(format!(
+                "{}:{}: lines must start with 'user <name>'",
+                path.display(),
+                n + 1
+            ));
+        }
+        if users.contains_key(words[1]) {
+            return Err(format!(
+                "{}:{}: duplicate user '{}'",
+                path.display(),
+                n + 1,
+                words[1]
+            ));
+        }
+        let mut user = User::new(words[1]);
+        for rule in &words[2..] {
+            user.apply(rule)
+                .map_err(|e| format!("{}:{}: {}", path.display(), n + 1, e))?;
+        }
+        users.insert(user.name.clone(), user);
+    }
+
+    users
+        .entry(DEFAULT_USER.to_string())
+        .or_insert_with(User::default_user);
+    Ok(users)
+}
+
+/// Loads users from `path` and remembers it for ACL LOAD and ACL SAVE.
+/// Nothing changes if the file has an error.
+pub fn load_file(path: &Path) -> Result<(), String> {
+    let users = parse_file(path)?;
+    let mut acl = ACL.write().unwrap();
+    acl.users = users;
+    acl.file = Some(path.to_path_buf());
+    Ok(())
+}
+
+fn configured_file() -> Result<PathBuf, String> {
+    ACL.read().unwrap().file.clone().ok_or_else(|| {
+        "This instance is not configured to use an ACL file. Set KEYVAL_ACLFILE.".into()
+    })
+}
+
+/// ACL LOAD: re-reads the configured file.
+pub fn reload_file() -> Result<(), String> {
+    load_file(&configured_file()?)
+}
+
+/// ACL SAVE: writes the current users to the configured file.
+pub fn save_file() -> Result<(), String> {
+    let path = configured_file()?;
+    let text: String = users().iter().map(|u| u.describe() + "\n").collect();
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, text)
+        .and_then(|_| std::fs::rename(&tmp, &path))
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(rules: &[&str]) -> User {
+        let mut user = User::new("test");
+        for rule in rules {
+            user.apply(rule).unwrap();
+        }
+        user
+    }
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    /// The error line of a refused check.
+    fn refusal(result: Result<(), RespValue>) -> String {
+        match result {
+            Err(RespValue::Error(e)) => e,
+            _ => panic!("expected an error reply"),
+        }
+    }
+
+    /// A session logged in as a new user with `rules`. Names are unique per
+    /// test, since the user table is shared.
+    fn session_as(name: &str, rules: &[&str]) -> Session {
+        let rules: Vec<String> = rules.iter().map(|r| r.to_string()).collect();
+        set_user(name, &rules).unwrap();
+        let mut session = Session::new();
+        session.user = name.to_string();
+        session.authenticated = true;
+        session
+    }
+
+    #[test]
+    fn new_users_have_nothing() {
+        let u = User::new("test");
+        assert!(!u.enabled());
+        assert!(!u.check_password(""));
+        assert!(!u.can_run("get"));
+        assert!(!u.can_access_key("k"));
+    }
+
+    #[test]
+    fn passwords() {
+        let mut u = user(&["on", ">secret"]);
+        assert!(u.check_password("secret"));
+        assert!(!u.check_password("other"));
+
+        u.apply("<secret").unwrap();
+        assert!(!u.check_password("secret"));
+
+        u.apply(&format!("#{}", hash_password("hashed"))).unwrap();
+        assert!(u.check_password("hashed"));
+
+        u.apply("nopass").unwrap();
+        assert!(u.check_password("anything"));
+        u.apply("resetpass").unwrap();
+        assert!(!u.check_password("anything"));
+    }
+
+    #[test]
+    fn last_matching_command_rule_wins() {
+        let u = user(&["+@all", "-@dangerous"]);
+        assert!(u.can_run("get"));
+        assert!(!u.can_run("flushall"));
+
+        let u = user(&["-@all", "+get", "+@write", "-set"]);
+        assert!(u.can_run("get"));
+        assert!(u.can_run("incr"));
+        assert!(!u.can_run("set"));
+
+        // `+@all` replaces everything before it.
+        let u = user(&["-get", "+@all"]);
+        assert!(u.can_run("get"));
+        assert_eq!(u.command_rules(), "+@all");
+    }
+
+    #[test]
+    fn bad_rules() {
+        let mut u = User::new("test");
+        assert!(u.apply("+@nosuchcategory").unwrap_err().contains("Unknown command category"));
+        assert!(u.apply("+nosuchcommand").unwrap_err().contains("Unknown command"));
+        assert!(u.apply("~").unwrap_err().contains("Syntax error"));
+        assert!(u.apply("bogus").unwrap_err().contains("Syntax error"));
+        assert_eq!(u, User::new("test"));
+    }
+
+    #[test]
+    fn reset_keeps_only_the_name() {
+        let mut u = user(&["on", ">pw", "allkeys", "+@all"]);
+        u.apply("reset").unwrap();
+        assert_eq!(u, User::new("test"));
+    }
+
+    #[test]
+    fn key_and_channel_patterns() {
+        let u = user(&["~app:*", "&news.*"]);
+        assert!(u.can_access_key("app:1"));
+        assert!(!u.can_access_key("other"));
+        assert!(u.can_use_channel("news.sport"));
+        assert!(!u.can_use_channel("chat"));
+        // A pattern subscription needs the same pattern, not a match.
+        assert!(u.can_use_pattern("news.*"));
+        assert!(!u.can_use_pattern("news.s*"));
+    }
+
+    #[test]
+    fn check_requires_authentication() {
+        let mut session = session_as("acl-test-noauth", &["on", "nopass", "+@all"]);
+        session.authenticated = false;
+        assert_eq!(refusal(check(&mut session, &args("GET k"))), "NOAUTH Authentication required.");
+        assert!(check(&mut session, &args("AUTH user pw")).is_ok());
+    }
+
+    #[test]
+    fn check_commands_and_keys() {
+        let mut session = session_as(
+            "acl-test-keys",
+            &["on", "nopass", "+@read", "+set", "~app:*"],
+        );
+        assert!(check(&mut session, &args("GET app:1")).is_ok());
+        assert!(check(&mut session, &args("EXISTS app:1 app:2")).is_ok());
+        assert!(check(&mut session, &args("SET app:1 v")).is_ok());
+
+        assert_eq!(
+            refusal(check(&mut session, &args("EXISTS app:1 other"))),
+            "NOPERM No permissions to access a key"
+        );
+        // SET's value is not a key.
+        assert!(check(&mut session, &args("SET app:1 other")).is_ok());
+
+        assert_eq!(
+            refusal(check(&mut session, &args("DEL app:1"))),
+            "NOPERM User acl-test-keys has no permissions to run the 'del' command"
+        );
+        // Everyone may ask who they are.
+        assert!(check(&mut session, &args("ACL WHOAMI")).is_ok());
+        assert!(check(&mut session, &args("ACL LIST")).is_err());
+    }
+
+    #[test]
+    fn check_client_subcommands() {
+        let mut session = session_as(
+            "acl-test-client",
+            &["on", "nopass", "+@all", "-@dangerous"],
+        );
+        assert!(check(&mut session, &args("CLIENT ID")).is_ok());
+        assert!(check(&mut session, &args("CLIENT SETNAME app")).is_ok());
+        assert_eq!(
+            refusal(check(&mut session, &args("CLIENT KILL ID 1"))),
+            "NOPERM User acl-test-client has no permissions to run the 'client|kill' command"
+        );
+        assert!(check(&mut session, &args("client pause 100")).is_err());
+        assert!(check(&mut session, &args("CLIENT UNPAUSE")).is_err());
+        assert!(check(&mut session, &args("CLIENT NO-EVICT on")).is_err());
+
+        // A connection user gets CLIENT but not the admin subcommands.
+        let mut session = session_as("acl-test-client-conn", &["on", "nopass", "+@connection"]);
+        assert!(check(&mut session, &args("CLIENT GETNAME")).is_ok());
+        assert!(check(&mut session, &args("CLIENT KILL ID 1")).is_err());
+
+        // Naming the command grants its subcommands, and one can be named alone.
+        let mut session = session_as("acl-test-client-all", &["on", "nopass", "+client", "-client|kill"]);
+        assert!(check(&mut session, &args("CLIENT PAUSE 100")).is_ok());
+        assert!(check(&mut session, &args("CLIENT KILL ID 1")).is_err());
+    }
+
+    #[test]
+    fn check_channels() {
+        let mut session = session_as(
+            "acl-test-channels",
+            &["on", "nopass", "+@pubsub", "&news.*"],
+        );
+        assert!(check(&mut session, &args("PUBLISH news.a hi")).is_ok());
+        assert!(check(&mut session, &args("PUBLISH chat hi")).is_err());
+        assert!(check(&mut session, &args("SUBSCRIBE news.a news.b")).is_ok());
+        assert!(check(&mut session, &args("SUBSCRIBE news.a chat")).is_err());
+        assert!(check(&mut session, &args("PSUBSCRIBE news.*")).is_ok());
+        assert!(check(&mut session, &args("PSUBSCRIBE *")).is_err());
+    }
+
+    #[test]
+    fn check_logs_out_disabled_users() {
+        let mut session = session_as("acl-test-disabled", &["on", "nopass", "+@all"]);
+        assert!(check(&mut session, &args("PING")).is_ok());
+
+        set_user("acl-test-disabled", &["off".to_string()]).unwrap();
+        assert!(check(&mut session, &args("PING")).is_err());
+        assert!(!session.authenticated);
+    }
+}
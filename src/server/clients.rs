@@ -0,0 +1,324 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+use tokio::sync::{watch, Notify};
+
+use crate::server::acl;
+use crate::server::config;
+use crate::server::output::ClientClass;
+use crate::server::session::Session;
+use crate::server::stats;
+
+/// Every connected client, by id, so one connection can report on the others.
+static CLIENTS: Lazy<Mutex<BTreeMap<u64, Arc<ClientInfo>>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+/// Live view of a connection, shared between its task and the registry.
+/// The connection updates it as it reads, runs commands and writes.
+pub struct ClientInfo {
+    pub id: u64,
+    pub addr: String,
+    pub laddr: String,
+    created: Instant,
+    /// Milliseconds after `created` of the last command.
+    last_interaction_ms: AtomicU64,
+    db: AtomicUsize,
+    name: Mutex<Option<String>>,
+    user: Mutex<String>,
+    last_cmd: Mutex<String>,
+    net_in: AtomicU64,
+    net_out: AtomicU64,
+    cmds: AtomicU64,
+    /// Bytes read but not yet parsed, and free space after them.
+    qbuf: AtomicUsize,
+    qbuf_free: AtomicUsize,
+    /// Reply bytes waiting to be written.
+    omem: AtomicUsize,
+    no_evict: AtomicBool,
+    /// In MONITOR mode.
+    monitor: AtomicBool,
+    /// Subscribed to a channel or pattern.
+    pubsub: AtomicBool,
+    killed: AtomicBool,
+    kill: Notify,
+}
+
+impl ClientInfo {
+    pub fn record_in(&self, n: usize) {
+        self.net_in.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_out(&self, n: usize) {
+        self.net_out.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    /// Copies what CLIENT LIST shows from the session after a command ran.
+    pub fn record_command(&self, session: &Session, cmd: &str) {
+        self.cmds.fetch_add(1, Ordering::Relaxed);
+        self.last_interaction_ms
+            .store(self.created.elapsed().as_millis() as u64, Ordering::Relaxed);
+        self.db.store(session.db, Ordering::Relaxed);
+        self.pubsub
+            .store(session.class() == ClientClass::Pubsub, Ordering::Relaxed);
+
+        let mut name = self.name.lock().unwrap();
+        if *name != session.name {
+            name.clone_from(&session.name);
+        }
+        drop(name);
+
+        let mut user = self.user.lock().unwrap();
+        if *user != session.user {
+            user.clone_from(&session.user);
+        }
+        drop(user);
+
+        let mut last = self.last_cmd.lock().unwrap();
+        if !last.eq_ignore_ascii_case(cmd) {
+            *last = cmd.to_lowercase();
+        }
+    }
+
+    /// Records the buffer sizes CLIENT LIST shows as `qbuf`, `qbuf-free`
+    /// and `omem`.
+    pub fn record_buffers(&self, qbuf: usize, qbuf_free: usize, omem: usize) {
+        self.qbuf.store(qbuf, Ordering::Relaxed);
+        self.qbuf_free.store(qbuf_free, Ordering::Relaxed);
+        self.omem.store(omem, Ordering::Relaxed);
+    }
+
+    pub fn set_no_evict(&self, on: bool) {
+        self.no_evict.store(on, Ordering::Relaxed);
+    }
+
+    pub fn set_monitor(&self) {
+        self.monitor.store(true, Ordering::Relaxed);
+    }
+
+    /// Asks the connection to close; it does so before reading its next request.
+    pub fn kill(&self) {
+        self.killed.store(true, Ordering::Relaxed);
+        self.kill.notify_one();
+    }
+
+    /// Resolves once CLIENT KILL picked this client.
+    pub async fn killed(&self) {
+        if !self.killed.load(Ordering::Relaxed) {
+            self.kill.notified().await;
+        }
+    }
+
+    pub fn qbuf(&self) -> usize {
+        self.qbuf.load(Ordering::Relaxed)
+    }
+
+    pub fn omem(&self) -> usize {
+        self.omem.load(Ordering::Relaxed)
+    }
+
+    pub fn user(&self) -> String {
+        self.user.lock().unwrap().clone()
+    }
+
+    pub fn net_in(&self) -> u64 {
+        self.net_in.load(Ordering::Relaxed)
+    }
+
+    pub fn net_out(&self) -> u64 {
+        self.net_out.load(Ordering::Relaxed)
+    }
+
+    /// One line of CLIENT LIST, with the fields named as in Redis.
+    pub fn describe(&self) -> String {
+        let age = self.created.elapsed().as_secs();
+        let last = self.last_interaction_ms.load(Ordering::Relaxed) / 1000;
+        let last_cmd = self.last_cmd.lock().unwrap();
+        let mut flags = String::new();
+        if self.monitor.load(Ordering::Relaxed) {
+            flags.push('O');
+        }
+        if self.no_evict.load(Ordering::Relaxed) {
+            flags.push('e');
+        }
+        if self.pubsub.load(Ordering::Relaxed) {
+            flags.push('P');
+        }
+        if flags.is_empty() {
+            flags.push('N');
+        }
+
+        format!(
+            "id={} addr={} laddr={} name={} age={} idle={} flags={} db={} qbuf={} qbuf-free={} omem={} tot-net-in={} tot-net-out={} tot-cmds={} cmd={} user={}",
+            self.id,
+            self.addr,
+            self.laddr,
+            self.name.lock().unwrap().as_deref().unwrap_or(""),
+            age,
+            age.saturating_sub(last),
+            flags,
+            self.db.load(Ordering::Relaxed),
+            self.qbuf.load(Ordering::Relaxed),
+            self.qbuf_free.load(Ordering::Relaxed),
+            self.omem.load(Ordering::Relaxed),
+            self.net_in(),
+            self.net_out(),
+            self.cmds.load(Ordering::Relaxed),
+            if last_cmd.is_empty() { "NULL" } else { last_cmd.as_str() },
+            self.user.lock().unwrap(),
+        )
+    }
+}
+
+/// Keeps a client listed for as long as it is alive.
+pub struct ClientHandle(Arc<ClientInfo>);
+
+impl std::ops::Deref for ClientHandle {
+    type Target = ClientInfo;
+
+    fn deref(&self) -> &ClientInfo {
+        &self.0
+    }
+}
+
+impl Drop for ClientHandle {
+    fn drop(&mut self) {
+        CLIENTS.lock().unwrap().remove(&self.0.id);
+    }
+}
+
+pub fn register(id: u64, addr: String, laddr: String) -> ClientHandle {
+    let info = Arc::new(ClientInfo {
+        id,
+        addr,
+        laddr,
+        created: Instant::now(),
+        last_interaction_ms: AtomicU64::new(0),
+        db: AtomicUsize::new(0),
+        name: Mutex::new(None),
+        user: Mutex::new("default".into()),
+        last_cmd: Mutex::new(String::new()),
+        net_in: AtomicU64::new(0),
+        net_out: AtomicU64::new(0),
+        cmds: AtomicU64::new(0),
+        qbuf: AtomicUsize::new(0),
+        qbuf_free: AtomicUsize::new(0),
+        omem: AtomicUsize::new(0),
+        no_evict: AtomicBool::new(false),
+        pubsub: AtomicBool::new(false),
+        monitor: AtomicBool::new(false),
+        killed: AtomicBool::new(false),
+        kill: Notify::new(),
+    });
+    CLIENTS.lock().unwrap().insert(id, info.clone());
+    stats::count(&stats::TOTAL_CONNECTIONS);
+    ClientHandle(info)
+}
+
+pub fn get(id: u64) -> Option<Arc<ClientInfo>> {
+    CLIENTS.lock().unwrap().get(&id).cloned()
+}
+
+/// Connections holding a `maxclients` slot. They take it when accepted,
+/// before any handshake and before they register here.
+static SLOTS: AtomicUsize = AtomicUsize::new(0);
+
+/// A connection's place under `maxclients`, given back when it disconnects.
+pub struct Slot(());
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        SLOTS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Takes a `maxclients` slot, or `None` when all are taken.
+pub fn reserve() -> Option<Slot> {
+    let max = config::with(|c| c.maxclients);
+    SLOTS
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| (n < max).then_some(n + 1))
+        .ok()
+        .map(|_| Slot(()))
+}
+
+/// Snapshot of the connected clients in id order.
+pub fn all() -> Vec<Arc<ClientInfo>> {
+    CLIENTS.lock().unwrap().values().cloned().collect()
+}
+
+/// What CLIENT PAUSE holds back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseMode {
+    /// Commands that may change data, PUBLISH included.
+    Write,
+    All,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Pause {
+    mode: PauseMode,
+    until: Instant,
+}
+
+static PAUSE: Lazy<watch::Sender<Option<Pause>>> = Lazy::new(|| watch::channel(None).0);
+
+/// Holds back commands for `timeout`. Pausing again keeps the later end and
+/// the stricter mode, as in Redis. Returns false, pausing nothing, if the
+/// end is too far off to represent.
+pub fn pause(timeout: Duration, mode: PauseMode) -> bool {
+    let Some(until) = Instant::now().checked_add(timeout) else {
+        return false;
+    };
+    PAUSE.send_modify(|p| {
+        *p = Some(match *p {
+            Some(old) if old.until > Instant::now() => Pause {
+                mode: if old.mode == PauseMode::All { old.mode } else { mode },
+                until: old.until.max(until),
+            },
+            _ => Pause { mode, until },
+        });
+    });
+    true
+}
+
+pub fn unpause() {
+    PAUSE.send_replace(None);
+}
+
+fn held_back(pause: &Option<Pause>, cmd: &str) -> Option<Instant> {
+    let pause = (*pause)?;
+    if pause.until <= Instant::now() {
+        return None;
+    }
+    // CLIENT stays usable so a pause can be lifted early.
+    let held = match pause.mode {
+        PauseMode::All => cmd != "client",
+        PauseMode::Write => acl::in_category(cmd, "write") || cmd == "publish",
+    };
+    held.then_some(pause.until)
+}
+
+/// Whether writes are paused right now; expiry waits too, so a paused
+/// server's data does not change under a failover.
+pub fn writes_paused() -> bool {
+    held_back(&PAUSE.borrow(), "set").is_some()
+}
+
+/// Waits while CLIENT PAUSE holds back `cmd` (lower case).
+pub async fn wait_unpaused(cmd: &str) {
+    if held_back(&PAUSE.borrow(), cmd).is_none() {
+        return;
+    }
+    let mut rx = PAUSE.subscribe();
+    loop {
+        let Some(until) = held_back(&rx.borrow_and_update(), cmd) else {
+            return;
+        };
+        tokio::select! {
+            _ = tokio::time::sleep_until(until.into()) => {}
+            _ = rx.changed() => {}
+        }
+    }
+}
@@ -0,0 +1,663 @@
+//! Server configuration. Settings start from built-in defaults, then come
+//! from a Redis-style config file of `name value` lines, the `KEYVAL_*`
+//! environment variables and finally `--name value` command-line flags, each
+//! overriding the one before. CONFIG SET changes the mutable ones at runtime
+//! and CONFIG REWRITE saves the current values back to the file.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use once_cell::sync::Lazy;
+
+use crate::db::storage::DEFAULT_DATABASES;
+use crate::protocol::resp::parser::ProtocolLimits;
+use crate::server::output::{parse_memory, OutputLimits};
+use crate::server::tls::ClientAuth;
+use crate::server::{acl, latency, notify, slowlog};
+use crate::util::glob::glob_match;
+
+/// Every setting the server reads. Empty strings mean "off", as in Redis.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub bind: String,
+    pub metrics_bind: String,
+    pub databases: usize,
+    pub unixsocket: String,
+    /// Mode bits for the socket file; the umask applies if unset.
+    pub unixsocketperm: Option<u32>,
+    pub memcached_bind: String,
+    pub tls_cert_file: String,
+    pub tls_key_file: String,
+    pub tls_ca_cert_file: String,
+    /// Unset means `yes` when a CA file is given and `no` otherwise.
+    pub tls_auth_clients: Option<ClientAuth>,
+    pub aclfile: String,
+    pub requirepass: String,
+    pub notify_keyspace_events: String,
+    pub protocol_limits: ProtocolLimits,
+    pub output_limits: OutputLimits,
+    /// Pause between two passes of the expired-key cleaner.
+    pub expire_interval_ms: u64,
+    /// Minimum free space in a connection's read buffer before each read.
+    pub read_buffer_size: usize,
+    /// Longest a shutdown waits for running commands, in seconds.
+    pub shutdown_timeout: u64,
+    /// Most clients connected at once, WebSocket ones included.
+    pub maxclients: usize,
+    /// Most clients connected at once from one IP address; 0 for no limit.
+    pub maxclients_per_ip: usize,
+    /// Seconds a client may stay idle before it is closed; 0 for never.
+    pub timeout: u64,
+    /// Seconds between TCP keepalive probes; 0 turns them off.
+    pub tcp_keepalive: u64,
+    /// Commands slower than this many microseconds go to the slow log;
+    /// negative turns it off, 0 logs everything.
+    pub slowlog_log_slower_than: i64,
+    /// Most entries the slow log keeps; the oldest are dropped first.
+    pub slowlog_max_len: usize,
+    /// Spikes of at least this many ms go to the latency monitor; 0 turns
+    /// it off.
+    pub latency_monitor_threshold: u64,
+    /// Origins, e.g. `https://dash.example.com`, whose pages may open
+    /// `/v1/ws`, separated by spaces; `*` allows any. Same-origin pages and
+    /// clients that send no `Origin` are always allowed.
+    pub ws_allowed_origins: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            bind: "127.0.0.1:6374".into(),
+            metrics_bind: "127.0.0.1:9100".into(),
+            databases: DEFAULT_DATABASES,
+            unixsocket: String::new(),
+            unixsocketperm: None,
+            memcached_bind: String::new(),
+            tls_cert_file: String::new(),
+            tls_key_file: String::new(),
+            tls_ca_cert_file: String::new(),
+            tls_auth_clients: None,
+            aclfile: String::new(),
+            requirepass: String::new(),
+            notify_keyspace_events: String::new(),
+            protocol_limits: ProtocolLimits::default(),
+            output_limits: OutputLimits::default(),
+            expire_interval_ms: 5000,
+            read_buffer_size: 4096,
+            shutdown_timeout: 10,
+            maxclients: 10000,
+            maxclients_per_ip: 0,
+            timeout: 0,
+            tcp_keepalive: 300,
+            slowlog_log_slower_than: 10000,
+            slowlog_max_len: 128,
+            latency_monitor_threshold: 0,
+            ws_allowed_origins: String::new(),
+        }
+    }
+}
+
+/// One named parameter: how to read and write it, and whether CONFIG SET may
+/// touch it. Immutable ones are only read at startup.
+struct Param {
+    name: &'static str,
+    /// The environment variable that set it before there was a config file.
+    env: Option<&'static str>,
+    mutable: bool,
+    get: fn(&Config) -> String,
+    set: fn(&mut Config, &str) -> Result<(), String>,
+}
+
+fn parse_positive<T: std::str::FromStr + Default + PartialOrd>(v: &str) -> Result<T, String> {
+    match v.parse::<T>() {
+        Ok(n) if n > T::default() => Ok(n),
+        _ => Err(format!("argument must be a positive integer, got '{}'", v)),
+    }
+}
+
+fn parse_seconds(v: &str) -> Result<u64, String> {
+    v.parse()
+        .map_err(|_| format!("argument must be a number of seconds, got '{}'", v))
+}
+
+fn parse_size(v: &str) -> Result<usize, String> {
+    match parse_memory(v)? {
+        0 => Err(format!("argument must be greater than zero, got '{}'", v)),
+        n => Ok(n),
+    }
+}
+
+static PARAMS: &[Param] = &[
+    Param {
+        name: "bind",
+        env: Some("KEYVAL_BIND"),
+        mutable: false,
+        get: |c| c.bind.clone(),
+        set: |c, v| {
+            c.bind = v.into();
+            Ok(())
+        },
+    },
+    Param {
+        name: "metrics-bind",
+        env: Some("METRICS_BIND"),
+        mutable: false,
+        get: |c| c.metrics_bind.clone(),
+        set: |c, v| {
+            c.metrics_bind = v.into();
+            Ok(())
+        },
+    },
+    Param {
+        name: "databases",
+        env: Some("KEYVAL_DATABASES"),
+        mutable: false,
+        get: |c| c.databases.to_string(),
+        set: |c, v| {
+            c.databases = parse_positive(v)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "unixsocket",
+        env: Some("KEYVAL_UNIXSOCKET"),
+        mutable: false,
+        get: |c| c.unixsocket.clone(),
+        set: |c, v| {
+            c.unixsocket = v.into();
+            Ok(())
+        },
+    },
+    Param {
+        name: "unixsocketperm",
+        env: Some("KEYVAL_UNIXSOCKETPERM"),
+        mutable: false,
+        get: |c| c.unixsocketperm.map_or("0".into(), |p| format!("{:o}", p)),
+        set: |c, v| {
+            let perm = u32::from_str_radix(v, 8)
+                .map_err(|_| format!("invalid octal permissions '{}'", v))?;
+            c.unixsocketperm = (perm != 0).then_some(perm);
+            Ok(())
+        },
+    },
+    Param {
+        name: "memcached-bind",
+        env: Some("KEYVAL_MEMCACHED_BIND"),
+        mutable: false,
+        get: |c| c.memcached_bind.clone(),
+        set: |c, v| {
+            c.memcached_bind = v.into();
+            Ok(())
+        },
+    },
+    Param {
+        name: "tls-cert-file",
+        env: Some("KEYVAL_TLS_CERT_FILE"),
+        mutable: false,
+        get: |c| c.tls_cert_file.clone(),
+        set: |c, v| {
+            c.tls_cert_file = v.into();
+            Ok(())
+        },
+    },
+    Param {
+        name: "tls-key-file",
+        env: Some("KEYVAL_TLS_KEY_FILE"),
+        mutable: false,
+        get: |c| c.tls_key_file.clone(),
+        set: |c, v| {
+            c.tls_key_file = v.into();
+            Ok(())
+        },
+    },
+    Param {
+        name: "tls-ca-cert-file",
+        env: Some("KEYVAL_TLS_CA_CERT_FILE"),
+        mutable: false,
+        get: |c| c.tls_ca_cert_file.clone(),
+        set: |c, v| {
+            c.tls_ca_cert_file = v.into();
+            Ok(())
+        },
+    },
+    Param {
+        name: "tls-auth-clients",
+        env: Some("KEYVAL_TLS_AUTH_CLIENTS"),
+        mutable: false,
+        get: |c| match c.tls_auth_clients {
+            None => String::new(),
+            Some(ClientAuth::No) => "no".into(),
+            Some(ClientAuth::Optional) => "optional".into(),
+            Some(ClientAuth::Required) => "yes".into(),
+        },
+        set: |c, v| {
+            c.tls_auth_clients = if v.is_empty() {
+                None
+            } else {
+                Some(ClientAuth::parse(v)?)
+            };
+            Ok(())
+        },
+    },
+    Param {
+        name: "aclfile",
+        env: Some("KEYVAL_ACLFILE"),
+        mutable: false,
+        get: |c| c.aclfile.clone(),
+        set: |c, v| {
+            c.aclfile = v.into();
+            Ok(())
+        },
+    },
+    Param {
+        name: "requirepass",
+        env: Some("KEYVAL_REQUIREPASS"),
+        mutable: true,
+        get: |c| c.requirepass.clone(),
+        set: |c, v| {
+            c.requirepass = v.into();
+            Ok(())
+        },
+    },
+    Param {
+        name: "notify-keyspace-events",
+        env: Some("KEYVAL_NOTIFY_KEYSPACE_EVENTS"),
+        mutable: true,
+        get: |c| c.notify_keyspace_events.clone(),
+        set: |c, v| {
+            notify::parse_flags(v)?;
+            c.notify_keyspace_events = v.into();
+            Ok(())
+        },
+    },
+    Param {
+        name: "proto-max-bulk-len",
+        env: Some("KEYVAL_PROTO_MAX_BULK_LEN"),
+        mutable: true,
+        get: |c| c.protocol_limits.max_bulk_len.to_string(),
+        set: |c, v| {
+            c.protocol_limits.max_bulk_len = parse_size(v)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "proto-max-multibulk-len",
+        env: Some("KEYVAL_PROTO_MAX_MULTIBULK_LEN"),
+        mutable: true,
+        get: |c| c.protocol_limits.max_multibulk_len.to_string(),
+        set: |c, v| {
+            c.protocol_limits.max_multibulk_len = parse_positive(v)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "proto-max-inline-len",
+        env: Some("KEYVAL_PROTO_MAX_INLINE_LEN"),
+        mutable: true,
+        get: |c| c.protocol_limits.max_inline_len.to_string(),
+        set: |c, v| {
+            c.protocol_limits.max_inline_len = parse_size(v)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "client-query-buffer-limit",
+        env: Some("KEYVAL_CLIENT_QUERY_BUFFER_LIMIT"),
+        mutable: true,
+        get: |c| c.protocol_limits.query_buffer_limit.to_string(),
+        set: |c, v| {
+            c.protocol_limits.query_buffer_limit = parse_size(v)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "client-output-buffer-limit",
+        env: Some("KEYVAL_CLIENT_OUTPUT_BUFFER_LIMIT"),
+        mutable: true,
+        get: |c| c.output_limits.to_spec(),
+        // Classes left out keep their current limits.
+        set: |c, v| {
+            c.output_limits = c.output_limits.parse(v)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "expire-interval-ms",
+        env: None,
+        mutable: true,
+        get: |c| c.expire_interval_ms.to_string(),
+        set: |c, v| {
+            c.expire_interval_ms = parse_positive(v)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "read-buffer-size",
+        env: None,
+        mutable: true,
+        get: |c| c.read_buffer_size.to_string(),
+        set: |c, v| {
+            c.read_buffer_size = parse_size(v)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "shutdown-timeout",
+        env: None,
+        mutable: true,
+        get: |c| c.shutdown_timeout.to_string(),
+        set: |c, v| {
+            c.shutdown_timeout = parse_seconds(v)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "maxclients",
+        env: None,
+        mutable: true,
+        get: |c| c.maxclients.to_string(),
+        set: |c, v| {
+            c.maxclients = parse_positive(v)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "maxclients-per-ip",
+        env: None,
+        mutable: true,
+        get: |c| c.maxclients_per_ip.to_string(),
+        set: |c, v| {
+            c.maxclients_per_ip = v
+                .parse()
+                .map_err(|_| format!("argument must be a number, got '{}'", v))?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "timeout",
+        env: None,
+        mutable: true,
+        get: |c| c.timeout.to_string(),
+        set: |c, v| {
+            c.timeout = parse_seconds(v)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "tcp-keepalive",
+        env: None,
+        mutable: true,
+        get: |c| c.tcp_keepalive.to_string(),
+        set: |c, v| {
+            c.tcp_keepalive = parse_seconds(v)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "slowlog-log-slower-than",
+        env: None,
+        mutable: true,
+        get: |c| c.slowlog_log_slower_than.to_string(),
+        set: |c, v| {
+            c.slowlog_log_slower_than = v
+                .parse()
+                .map_err(|_| format!("argument must be a number, got '{}'", v))?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "slowlog-max-len",
+        env: None,
+        mutable: true,
+        get: |c| c.slowlog_max_len.to_string(),
+        set: |c, v| {
+            c.slowlog_max_len = v
+                .parse()
+                .map_err(|_| format!("argument must be a number, got '{}'", v))?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "latency-monitor-threshold",
+        env: None,
+        mutable: true,
+        get: |c| c.latency_monitor_threshold.to_string(),
+        set: |c, v| {
+            c.latency_monitor_threshold = v
+                .parse()
+                .map_err(|_| format!("argument must be a number, got '{}'", v))?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "ws-allowed-origins",
+        env: None,
+        mutable: true,
+        get: |c| c.ws_allowed_origins.clone(),
+        set: |c, v| {
+            c.ws_allowed_origins = v.into();
+            Ok(())
+        },
+    },
+];
+
+static CONFIG: Lazy<RwLock<Config>> = Lazy::new(|| RwLock::new(Config::default()));
+/// The file we were started with, which CONFIG REWRITE writes to.
+static CONFIG_FILE: Lazy<RwLock<Option<PathBuf>>> = Lazy::new(|| RwLock::new(None));
+
+fn find(name: &str) -> Option<&'static Param> {
+    PARAMS.iter().find(|p| p.name.eq_ignore_ascii_case(name))
+}
+
+fn set(config: &mut Config, name: &str, value: &str) -> Result<(), String> {
+    let param = find(name).ok_or_else(|| format!("unknown option '{}'", name))?;
+    (param.set)(config, value)
+}
+
+/// Splits a `name value` line; `None` for blanks and `#` comments. The value
+/// is the rest of the line, with one pair of surrounding quotes removed.
+fn parse_line(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let (name, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let value = value.trim();
+    let value = value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value);
+    Some((name, value))
+}
+
+fn format_line(param: &Param, config: &Config) -> String {
+    let value = (param.get)(config);
+    if value.is_empty() || (value.starts_with('"') && value.ends_with('"')) {
+        format!("{} \"{}\"", param.name, value)
+    } else {
+        format!("{} {}", param.name, value)
+    }
+}
+
+/// Builds the configuration from `rust-keyval [config-file] [--name value ...]`,
+/// makes it current and applies the settings that live elsewhere (ACL users,
+/// keyspace notifications).
+pub fn init(args: &[String]) -> Result<Config, String> {
+    let mut config = Config::default();
+    let mut args = args.iter().peekable();
+
+    let file = args.next_if(|a| !a.starts_with("--")).map(PathBuf::from);
+    if let Some(path) = &file {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        for (n, line) in text.lines().enumerate() {
+            if let Some((name, value)) = parse_line(line) {
+                set(&mut config, name, value)
+                    .map_err(|e| format!("{}:{}: {}", path.display(), n + 1, e))?;
+            }
+        }
+    }
+
+    for param in PARAMS {
+        let Some(var) = param.env else { continue };
+        if let Ok(value) = std::env::var(var) {
+            (param.set)(&mut config, &value).map_err(|e| format!("{}: {}", var, e))?;
+        }
+    }
+
+    while let Some(flag) = args.next() {
+        let name = flag
+            .strip_prefix("--")
+            .ok_or_else(|| format!("unexpected argument '{}'", flag))?;
+        let value = args
+            .next()
+            .ok_or_else(|| format!("missing value for --{}", name))?;
+        set(&mut config, name, value).map_err(|e| format!("--{}: {}", name, e))?;
+    }
+
+    if config.bind.is_empty() && config.unixsocket.is_empty() {
+        return Err("bind is empty and unixsocket is not set".into());
+    }
+
+    if !config.aclfile.is_empty() {
+        acl::load_file(std::path::Path::new(&config.aclfile))?;
+    }
+    // An empty requirepass leaves whatever the ACL file says alone.
+    if !config.requirepass.is_empty() {
+        apply("requirepass", &config);
+    }
+    apply("notify-keyspace-events", &config);
+    apply("latency-monitor-threshold", &config);
+
+    *CONFIG.write().unwrap() = config.clone();
+    *CONFIG_FILE.write().unwrap() = file;
+    Ok(config)
+}
+
+/// Pushes a changed parameter to the subsystem that keeps its own copy.
+/// Everything else is read from `CONFIG` where it is used. Runs with the
+/// `CONFIG` write lock held, so it must not read `CONFIG` itself.
+fn apply(name: &str, config: &Config) {
+    match name {
+        // Like Redis, requirepass is a password for the default user.
+        "requirepass" => {
+            let rules = if config.requirepass.is_empty() {
+                vec!["nopass".to_string()]
+            } else {
+                vec!["resetpass".to_string(), format!(">{}", config.requirepass)]
+            };
+            let _ = acl::set_user(acl::DEFAULT_USER, &rules);
+        }
+        "notify-keyspace-events" => {
+            notify::set_flags(notify::parse_flags(&config.notify_keyspace_events).unwrap_or(0));
+        }
+        "slowlog-max-len" => slowlog::trim(config.slowlog_max_len),
+        "latency-monitor-threshold" => latency::set_threshold(config.latency_monitor_threshold),
+        _ => {}
+    }
+}
+
+/// A copy of the current configuration.
+pub fn get() -> Config {
+    CONFIG.read().unwrap().clone()
+}
+
+/// Reads a few fields without copying the whole configuration.
+pub fn with<T>(f: impl FnOnce(&Config) -> T) -> T {
+    f(&CONFIG.read().unwrap())
+}
+
+pub fn file() -> Option<PathBuf> {
+    CONFIG_FILE.read().unwrap().clone()
+}
+
+/// Names and values of the parameters matching a glob, for CONFIG GET.
+pub fn matching(pattern: &str) -> Vec<(&'static str, String)> {
+    let pattern = pattern.to_ascii_lowercase();
+    let config = CONFIG.read().unwrap();
+    PARAMS
+        .iter()
+        .filter(|p| glob_match(&pattern, p.name))
+        .map(|p| (p.name, (p.get)(&config)))
+        .collect()
+}
+
+/// Sets several parameters at once: either all of them change or, if any
+/// is unknown, immutable or invalid, none do. The lock is held from
+/// validation to the store, so concurrent calls cannot undo each other.
+pub fn set_runtime(pairs: &[(String, String)]) -> Result<(), String> {
+    let mut current = CONFIG.write().unwrap();
+    let mut config = current.clone();
+    let mut seen = HashSet::new();
+
+    for (name, value) in pairs {
+        let failed = |reason: &str| {
+            format!(
+                "CONFIG SET failed (possibly related to argument '{}') - {}",
+                name, reason
+            )
+        };
+        let param = find(name).ok_or_else(|| {
+            format!(
+                "Unknown option or number of arguments for CONFIG SET - '{}'",
+                name
+            )
+        })?;
+        if !param.mutable {
+            return Err(failed("can't set immutable config"));
+        }
+        if !seen.insert(param.name) {
+            return Err(failed("duplicate parameter"));
+        }
+        (param.set)(&mut config, value).map_err(|e| failed(&e))?;
+    }
+
+    for name in &seen {
+        apply(name, &config);
+    }
+    *current = config;
+    Ok(())
+}
+
+/// Writes the current values into the config file. Comments and unrelated
+/// lines are kept, known parameters are updated in place (later duplicates
+/// dropped) and parameters that differ from the default are appended.
+pub fn rewrite() -> Result<(), String> {
+    let path = file().ok_or("The server is running without a config file")?;
+    let config = get();
+    let defaults = Config::default();
+
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(format!("Rewriting config file: {}", e)),
+    };
+
+    let mut written = HashSet::new();
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        match parse_line(line).and_then(|(name, _)| find(name)) {
+            Some(param) => {
+                if written.insert(param.name) {
+                    lines.push(format_line(param, &config));
+                }
+            }
+            None => lines.push(line.to_string()),
+        }
+    }
+    for param in PARAMS {
+        if !written.contains(param.name) && (param.get)(&config) != (param.get)(&defaults) {
+            lines.push(format_line(param, &config));
+        }
+    }
+
+    // Write next to the original and rename, so a crash never leaves half a file.
+    let tmp = path.with_extension("rewrite.tmp");
+    let mut out = lines.join("\n");
+    out.push('\n');
+    fs::write(&tmp, out)
+        .and_then(|_| fs::rename(&tmp, &path))
+        .map_err(|e| format!("Rewriting config file: {}", e))
+}
@@ -1,111 +1,312 @@
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
+use bytes::{Buf, BytesMut};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
-use crate::db::storage::Db;
+use crate::db::storage::Databases;
 use crate::protocol::parser;
 use crate::protocol::resp::encoder::RespValue;
-use crate::protocol::resp::parser::parse_resp_one;
+use crate::protocol::resp::parser::{ProtocolError, ProtocolLimits, RequestParser};
+use crate::server::acl;
+use crate::server::clients::{self, ClientInfo};
+use crate::server::config;
+use crate::server::latency;
 use crate::server::metrics_prom;
+use crate::server::monitor;
+use crate::server::output::{FlushError, OutputBuffer, OutputLimits};
+use crate::server::session::Session;
+use crate::server::slowlog;
+use crate::server::shutdown;
 
-pub async fn handle(mut stream: TcpStream, db: Db) {
+/// Most we grow the buffer ahead of data for an announced bulk, so a bare
+/// `$500000000` header cannot make us allocate half a gigabyte up front.
+const MAX_PREALLOC: usize = 1024 * 1024;
+
+/// Who is on the other end of a connection.
+pub struct Peer {
+    /// What CLIENT LIST shows as `addr` and `laddr`.
+    pub addr: String,
+    pub laddr: String,
+    /// User named by a verified TLS client certificate.
+    pub user: Option<String>,
+}
+
+/// Serves one client over any byte stream (TCP, TLS or a Unix socket).
+pub async fn handle<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: S,
+    peer: Peer,
+    dbs: Databases,
+    limits: ProtocolLimits,
+    output_limits: OutputLimits,
+) {
     metrics_prom::ACTIVE_CONNS.inc();
 
-    let result = async {
-        let mut buffer = [0u8; 4096];
-        let mut acc: Vec<u8> = Vec::new();
+    async {
+        // Minimum free space in the read buffer before each read.
+        let read_chunk = config::with(|c| c.read_buffer_size);
+        let mut buf = BytesMut::with_capacity(read_chunk);
+        let mut req_parser = RequestParser::with_limits(limits);
+        let mut session = Session::default();
+        // A certificate naming an enabled user logs the client in as it.
+        if let Some(user) = peer.user.filter(|u| acl::is_enabled(u)) {
+            session.user = user;
+            session.authenticated = true;
+        }
+        let mut out = OutputBuffer::new();
+        let client = clients::register(session.id, peer.addr, peer.laddr);
+        // Held while this read's commands run and their replies are written,
+        // so a shutdown drain waits for them.
+        let mut in_flight: Option<shutdown::InFlight> = None;
 
         loop {
-            let n = match stream.read(&mut buffer).await {
+            // Grow ahead of time to fit a large bulk that is already announced.
+            let wanted = req_parser
+                .pending_len()
+                .unwrap_or(0)
+                .min(buf.len() + MAX_PREALLOC)
+                .max(buf.len() + read_chunk);
+            buf.reserve(wanted - buf.len());
+
+            let idle_timeout = config::with(|c| c.timeout);
+            let read = tokio::select! {
+                read = stream.read_buf(&mut buf) => read,
+                _ = shutdown::stopped() => return,
+                _ = client.killed() => return,
+                _ = tokio::time::sleep(Duration::from_secs(idle_timeout)), if idle_timeout > 0 => {
+                    metrics_prom::IDLE_TIMEOUTS.inc();
+                    return;
+                }
+            };
+            match read {
                 Ok(0) => return,
-                Ok(n) => n,
+                Ok(n) => {
+                    metrics_prom::BYTES_IN.inc_by(n as u64);
+                    client.record_in(n);
+                    client.record_buffers(buf.len(), buf.capacity() - buf.len(), out.len());
+                }
                 Err(_) => return,
             };
 
-            acc.extend_from_slice(&buffer[..n]);
+            if buf.len() > limits.query_buffer_limit {
+                let e = ProtocolError {
+                    reason: "query_buffer",
+                    message: "client query buffer limit exceeded".into(),
+                };
+                reject(&mut stream, &mut out, &session, &client, &output_limits, &e).await;
+                return;
+            }
 
             loop {
-                if acc.is_empty() {
-                    break;
-                }
-
-                if acc[0] == b'*' {
-                    match parse_resp_one(&acc) {
-                        Ok(Some((parts, consumed))) => {
-                            let cmd = parts
-                                .get(0)
-                                .map(|s| s.to_uppercase())
-                                .unwrap_or_else(|| "UNKNOWN".into());
-
-                            metrics_prom::CMD_TOTAL
-                                .with_label_values(&[cmd.as_str()])
-                                .inc();
-
-                            let t0 = Instant::now();
-                            let resp = parser::process_parts(parts, &db).await;
-                            metrics_prom::CMD_LATENCY
-                                .with_label_values(&[cmd.as_str()])
-                                .observe(t0.elapsed().as_secs_f64());
-
-                            let bytes = resp.to_bytes();
-                            let _ = stream.write_all(&bytes).await;
-
-                            acc.drain(..consumed);
-                            continue;
-                        }
-                        Ok(None) => {
-                            break;
-                        }
-                        Err(e) => {
-                            let bytes = RespValue::Error(format!("ERR {}", e)).to_bytes();
-                            let _ = stream.write_all(&bytes).await;
-                            acc.clear();
-                            break;
-                        }
+                let (parts, consumed) = match req_parser.parse(&buf) {
+                    Ok(Some(req)) => (req.to_strings(), req.consumed()),
+                    Ok(None) => break,
+                    Err(e) => {
+                        // Like Redis: the stream cannot be resynchronised, so
+                        // report the error and drop the client.
+                        reject(&mut stream, &mut out, &session, &client, &output_limits, &e).await;
+                        return;
                     }
-                } else {
-                    if let Some(pos) = acc.iter().position(|&b| b == b'\n') {
-                        let line_bytes = acc[..pos].to_vec();
-                        acc.drain(..pos + 1);
+                };
+                buf.advance(consumed);
 
-                        let line = String::from_utf8_lossy(&line_bytes).trim().to_string();
-                        if line.is_empty() {
-                            continue;
-                        }
+                let Some(parts) = parts else {
+                    let bytes = RespValue::Error(parser::NOT_UTF8.into()).encode(session.protocol);
+                    if let Err(e) = out.push(&bytes, session.class(), &output_limits) {
+                        flush_failed(&e);
+                        return;
+                    }
+                    continue;
+                };
+                if parts.is_empty() {
+                    continue;
+                }
 
-                        let parts: Vec<String> =
-                            line.split_whitespace().map(|s| s.to_string()).collect();
+                let cmd = parts[0].to_uppercase();
 
-                        let cmd = parts
-                            .get(0)
-                            .map(|s| s.to_uppercase())
-                            .unwrap_or_else(|| "UNKNOWN".into());
+                // SHUTDOWN waits for the drain, so it must not count as in
+                // flight; other commands wait while a drain is going on.
+                if cmd == "SHUTDOWN" || shutdown::is_draining() {
+                    if !flush(&mut stream, &mut out, &session, &client, &output_limits).await {
+                        return;
+                    }
+                    in_flight = None;
+                }
+                if cmd != "SHUTDOWN" && in_flight.is_none() {
+                    match shutdown::admit().await {
+                        Some(guard) => in_flight = Some(guard),
+                        None => return,
+                    }
+                }
 
-                        metrics_prom::CMD_TOTAL
-                            .with_label_values(&[cmd.as_str()])
-                            .inc();
+                let label = metrics_prom::command_label(&cmd);
+                metrics_prom::CMD_TOTAL
+                    .with_label_values(&[label])
+                    .inc();
 
-                        let t0 = Instant::now();
-                        let resp = parser::process_parts(parts, &db).await;
-                        metrics_prom::CMD_LATENCY
-                            .with_label_values(&[cmd.as_str()])
-                            .observe(t0.elapsed().as_secs_f64());
+                let t0 = Instant::now();
+                let mut watcher = None;
+                // MONITOR takes over the connection, so it is handled here
+                // rather than by the dispatcher.
+                let resp = if cmd == "MONITOR" {
+                    match acl::check(&mut session, &parts) {
+                        Ok(()) => {
+                            watcher = Some(monitor::watch());
+                            RespValue::SimpleString("OK".into())
+                        }
+                        Err(e) => e,
+                    }
+                } else {
+                    parser::process_parts(&parts, &mut session, &dbs).await
+                };
+                let elapsed = t0.elapsed();
+                metrics_prom::CMD_LATENCY
+                    .with_label_values(&[label])
+                    .observe(elapsed.as_secs_f64());
+                slowlog::record(&parts, elapsed, &client.addr, session.name.as_deref());
+                latency::command(label, elapsed);
 
-                        let bytes = resp.to_bytes();
-                        let _ = stream.write_all(&bytes).await;
+                client.record_command(&session, label);
 
-                        continue;
-                    } else {
-                        break;
+                let bytes = resp.encode(session.protocol);
+                metrics_prom::CMD_REPLY_BYTES
+                    .with_label_values(&[label])
+                    .observe(bytes.len() as f64);
+                if let Err(e) = out.push(&bytes, session.class(), &output_limits) {
+                    flush_failed(&e);
+                    return;
+                }
+                if let Some(watcher) = watcher {
+                    // Anything pipelined after MONITOR is ignored, as is
+                    // everything the client sends from now on.
+                    if flush(&mut stream, &mut out, &session, &client, &output_limits).await {
+                        drop(in_flight);
+                        monitor_feed(&mut stream, &mut out, &session, &client, &output_limits, watcher).await;
                     }
+                    return;
                 }
+                if out.should_flush() && !flush(&mut stream, &mut out, &session, &client, &output_limits).await {
+                    return;
+                }
+            }
+
+            // One write for everything this read produced.
+            if !flush(&mut stream, &mut out, &session, &client, &output_limits).await {
+                return;
             }
+            in_flight = None;
+            client.record_buffers(buf.len(), buf.capacity() - buf.len(), out.len());
         }
     }
         .await;
 
     metrics_prom::ACTIVE_CONNS.dec();
+}
 
-    let _ = result;
+/// Streams MONITOR lines to the client until it disconnects, is killed or
+/// falls too far behind. Lines keep being queued while a write is blocked,
+/// so a monitor that stops reading is held to its output buffer limits.
+async fn monitor_feed<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    out: &mut OutputBuffer,
+    session: &Session,
+    client: &ClientInfo,
+    output_limits: &OutputLimits,
+    mut watcher: monitor::Watcher,
+) {
+    client.set_monitor();
+    let class = session.class();
+    // Without a hard limit, stop taking lines once a flush worth is
+    // pending; a watcher that lags far enough is then dropped by the feed.
+    let unbounded = output_limits.for_class(class).hard == 0;
+    let (mut reader, mut writer) = tokio::io::split(stream);
+    let mut discard = [0u8; 1024];
+    loop {
+        let soft_deadline = out.soft_deadline(class, output_limits);
+        tokio::select! {
+            line = watcher.next(), if !(unbounded && out.should_flush()) => {
+                let Some(line) = line else { return };
+                let bytes = RespValue::SimpleString(line).encode(session.protocol);
+                if let Err(e) = out.push(&bytes, class, output_limits) {
+                    flush_failed(&e);
+                    return;
+                }
+            }
+            written = out.write_some(&mut writer), if !out.is_empty() => match written {
+                Ok(n) => {
+                    metrics_prom::BYTES_OUT.inc_by(n as u64);
+                    client.record_out(n);
+                }
+                Err(_) => return,
+            },
+            _ = tokio::time::sleep_until(soft_deadline.unwrap_or_else(Instant::now).into()), if soft_deadline.is_some() => {
+                flush_failed(&FlushError::LimitReached(class));
+                return;
+            }
+            read = reader.read(&mut discard) => match read {
+                Ok(0) | Err(_) => return,
+                Ok(n) => {
+                    metrics_prom::BYTES_IN.inc_by(n as u64);
+                    client.record_in(n);
+                }
+            },
+            _ = shutdown::stopped() => return,
+            _ = client.killed() => return,
+        }
+    }
+}
+
+/// Writes pending replies and accounts for them. Returns false when the
+/// connection has to be dropped.
+async fn flush<S: AsyncWrite + Unpin>(
+    stream: &mut S,
+    out: &mut OutputBuffer,
+    session: &Session,
+    client: &ClientInfo,
+    output_limits: &OutputLimits,
+) -> bool {
+    match out.flush(stream, session.class(), output_limits).await {
+        Ok(n) => {
+            metrics_prom::BYTES_OUT.inc_by(n as u64);
+            client.record_out(n);
+            true
+        }
+        Err(e) => {
+            flush_failed(&e);
+            false
+        }
+    }
+}
+
+fn flush_failed(e: &FlushError) {
+    if let FlushError::LimitReached(class) = e {
+        metrics_prom::OUTPUT_LIMIT_DISCONNECTS
+            .with_label_values(&[class.name()])
+            .inc();
+    }
+}
+
+/// Sends the replies produced so far plus the protocol error, then closes.
+async fn reject<S: AsyncWrite + Unpin>(
+    stream: &mut S,
+    out: &mut OutputBuffer,
+    session: &Session,
+    client: &ClientInfo,
+    output_limits: &OutputLimits,
+    e: &ProtocolError,
+) {
+    metrics_prom::PROTOCOL_REJECTIONS
+        .with_label_values(&[e.reason])
+        .inc();
+
+    let bytes = RespValue::Error(format!("ERR {}", e)).to_bytes();
+    if let Err(e) = out.push(&bytes, session.class(), output_limits) {
+        flush_failed(&e);
+        return;
+    }
+    if !flush(stream, out, session, client, output_limits).await {
+        return;
+    }
+    // Send FIN before the socket is dropped with unread input, which would
+    // otherwise reset the connection and could discard the error reply.
+    let _ = stream.shutdown().await;
 }
@@ -0,0 +1,607 @@
+//! JSON/HTTP access to the keyspace, served next to `/metrics` for clients
+//! that cannot hold a TCP connection open.
+
+use std::net::SocketAddr;
+use std::time::Instant;
+
+use axum::extract::{ConnectInfo, Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use base64::Engine;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use crate::db::storage::Databases;
+use crate::protocol::parser;
+use crate::protocol::resp::encoder::RespValue;
+use crate::server::acl;
+use crate::server::latency;
+use crate::server::metrics_prom;
+use crate::server::session::Session;
+use crate::server::shutdown;
+use crate::server::slowlog;
+
+/// Header that may carry a TTL in seconds on `PUT`.
+const TTL_HEADER: &str = "x-ttl";
+
+type ApiResult = Result<Response, ApiError>;
+
+pub struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+fn bad_request(msg: impl Into<String>) -> ApiError {
+    ApiError(StatusCode::BAD_REQUEST, msg.into())
+}
+
+/// The server stopped while the request waited out a shutdown drain.
+fn unavailable() -> ApiError {
+    ApiError(StatusCode::SERVICE_UNAVAILABLE, "server is shutting down".into())
+}
+
+/// An error reply as an HTTP error: 401 for NOAUTH, 403 for NOPERM.
+fn reply_error(e: String) -> ApiError {
+    let status = if e.starts_with("NOAUTH") || e.starts_with("WRONGPASS") {
+        StatusCode::UNAUTHORIZED
+    } else if e.starts_with("NOPERM") {
+        StatusCode::FORBIDDEN
+    } else {
+        StatusCode::BAD_REQUEST
+    };
+    ApiError(status, e)
+}
+
+/// The session a request runs as: the user from HTTP Basic credentials, or
+/// `default` when that needs no password.
+fn session(headers: &HeaderMap) -> Result<Session, ApiError> {
+    let mut session = Session::new();
+    let Some(auth) = headers.get(header::AUTHORIZATION) else {
+        return Ok(session);
+    };
+
+    let credentials = auth
+        .to_str()
+        .ok()
+        .and_then(|v| v.strip_prefix("Basic "))
+        .and_then(|b| base64::engine::general_purpose::STANDARD.decode(b.trim()).ok())
+        .and_then(|b| String::from_utf8(b).ok())
+        .ok_or_else(|| ApiError(StatusCode::UNAUTHORIZED, "malformed Authorization header".into()))?;
+    let (user, password) = credentials.split_once(':').unwrap_or((acl::DEFAULT_USER, &credentials));
+
+    if !acl::authenticate(user, password, session.id) {
+        return Err(reply_error("WRONGPASS invalid username-password pair or user is disabled.".into()));
+    }
+    session.user = user.to_string();
+    session.authenticated = true;
+    Ok(session)
+}
+
+/// Checks that the request's user may run `cmd` on `keys`, as if it had
+/// sent that command.
+fn authorize(headers: &HeaderMap, cmd: &str, keys: &[&String]) -> Result<(), ApiError> {
+    let mut session = session(headers)?;
+    let mut parts = vec![cmd.to_string()];
+    parts.extend(keys.iter().map(|k| k.to_string()));
+    acl::check(&mut session, &parts).map_err(|e| match e {
+        RespValue::Error(e) => reply_error(e),
+        _ => reply_error("NOPERM".into()),
+    })
+}
+
+#[derive(Deserialize, Default)]
+pub struct KeyParams {
+    db: Option<String>,
+    ttl: Option<u64>,
+}
+
+impl KeyParams {
+    /// The database picked with `?db=`, database 0 by default.
+    fn db(&self, dbs: &Databases) -> Result<usize, ApiError> {
+        match &self.db {
+            Some(s) => dbs.parse_index(s).map_err(bad_request),
+            None => Ok(0),
+        }
+    }
+
+    /// The session for a request on `/v1/keys` or `/v1/batch`.
+    fn session(&self, dbs: &Databases, headers: &HeaderMap) -> Result<Session, ApiError> {
+        let db = self.db(dbs)?;
+        let mut session = session(headers)?;
+        session.db = db;
+        Ok(session)
+    }
+}
+
+pub fn router(dbs: Databases) -> Router {
+    Router::new()
+        .route("/v1/keys/*key", get(get_key).put(put_key).delete(delete_key))
+        .route("/v1/batch/get", post(batch_get))
+        .route("/v1/batch/set", post(batch_set))
+        .route("/v1/batch/delete", post(batch_delete))
+        .route("/v1/command", post(command))
+        .route("/v1/slowlog", get(slowlog_entries))
+        .with_state(dbs)
+}
+
+/// Runs one command through the normal dispatcher, so REST calls get the
+/// same ACL checks, pauses, MONITOR output, notifications and statistics as
+/// RESP clients. Error replies come back as HTTP errors.
+async fn run(
+    dbs: &Databases,
+    session: &mut Session,
+    addr: SocketAddr,
+    args: Vec<String>,
+) -> Result<RespValue, ApiError> {
+    let cmd = args[0].to_uppercase();
+    // SHUTDOWN waits for the drain, so it must not count as in flight.
+    let _in_flight = if cmd == "SHUTDOWN" {
+        None
+    } else {
+        match shutdown::admit().await {
+            Some(guard) => Some(guard),
+            None => return Err(unavailable()),
+        }
+    };
+    let label = metrics_prom::command_label(&cmd);
+    metrics_prom::CMD_TOTAL.with_label_values(&[label]).inc();
+    let t0 = Instant::now();
+    let reply = parser::process_parts(&args, session, dbs).await;
+    let elapsed = t0.elapsed();
+    metrics_prom::CMD_LATENCY
+        .with_label_values(&[label])
+        .observe(elapsed.as_secs_f64());
+    slowlog::record(&args, elapsed, &addr.to_string(), None);
+    latency::command(label, elapsed);
+
+    match reply {
+        RespValue::Error(e) => Err(reply_error(e)),
+        reply => Ok(reply),
+    }
+}
+
+fn command_args(cmd: &str, args: &[&str]) -> Vec<String> {
+    std::iter::once(cmd).chain(args.iter().copied()).map(str::to_string).collect()
+}
+
+/// A `GET` reply as a string, or `None` for a missing key.
+fn bulk_string(reply: RespValue) -> Option<String> {
+    match reply {
+        RespValue::Bulk(Some(b)) => Some(String::from_utf8_lossy(&b).into_owned()),
+        _ => None,
+    }
+}
+
+/// Seconds until `key` expires, or -1. Only reads: `GET` has already
+/// checked access and dropped the key if it had expired.
+async fn remaining_ttl(dbs: &Databases, db: usize, key: &str) -> i64 {
+    let Some(db) = dbs.get(db) else { return -1 };
+    let ks = db.lock().await;
+    ks.get(key)
+        .and_then(|e| e.expire_at)
+        .map_or(-1, |exp| exp.saturating_duration_since(Instant::now()).as_secs() as i64)
+}
+
+/// Whether `key` holds a live value. Only reads, so a `PUT` can pick its
+/// status code without needing the `EXISTS` permission.
+async fn is_live(dbs: &Databases, db: usize, key: &str) -> bool {
+    let Some(db) = dbs.get(db) else { return false };
+    let ks = db.lock().await;
+    ks.get(key).is_some_and(|e| !e.is_expired(Instant::now()))
+}
+
+fn wants_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("application/json"))
+}
+
+/// `GET /v1/keys/{key}`: the raw value as text, or a JSON envelope with
+/// key, value and TTL when the client accepts `application/json`.
+async fn get_key(
+    State(dbs): State<Databases>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Path(key): Path<String>,
+    Query(params): Query<KeyParams>,
+    headers: HeaderMap,
+) -> ApiResult {
+    let mut session = params.session(&dbs, &headers)?;
+    let reply = run(&dbs, &mut session, addr, command_args("GET", &[&key])).await?;
+    let value = bulk_string(reply)
+        .ok_or_else(|| ApiError(StatusCode::NOT_FOUND, format!("no such key '{}'", key)))?;
+
+    if wants_json(&headers) {
+        let ttl = remaining_ttl(&dbs, session.db, &key).await;
+        return Ok(Json(json!({ "key": key, "value": value, "ttl": ttl })).into_response());
+    }
+    Ok(([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], value).into_response())
+}
+
+/// Checks a `PUT` body against its content type. Text, form bodies and JSON
+/// are stored as sent (JSON must parse); binary bodies must still be UTF-8.
+fn decode_body(headers: &HeaderMap, body: &[u8]) -> Result<String, ApiError> {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("text/plain");
+    let mime = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+
+    let is_json = mime == "application/json" || mime.ends_with("+json");
+    let is_text = mime.starts_with("text/")
+        || mime == "application/x-www-form-urlencoded"
+        || mime == "application/octet-stream";
+    if !(is_json || is_text) {
+        return Err(ApiError(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            format!("unsupported content type '{}'", mime),
+        ));
+    }
+
+    let text = String::from_utf8(body.to_vec()).map_err(|_| bad_request("value must be valid UTF-8"))?;
+    if is_json && serde_json::from_str::<Value>(&text).is_err() {
+        return Err(bad_request("body is not valid JSON"));
+    }
+    Ok(text)
+}
+
+/// TTL from `?ttl=` or the `X-TTL` header; the query string wins.
+fn ttl_param(params: &KeyParams, headers: &HeaderMap) -> Result<Option<u64>, ApiError> {
+    if params.ttl.is_some() {
+        return Ok(params.ttl);
+    }
+    match headers.get(TTL_HEADER) {
+        Some(v) => v
+            .to_str()
+            .ok()
+            .and_then(|s| s.trim().parse().ok())
+            .map(Some)
+            .ok_or_else(|| bad_request("X-TTL must be a number of seconds")),
+        None => Ok(None),
+    }
+}
+
+/// `SET key value [EX ttl]`.
+fn set_args(key: String, value: String, ttl: Option<u64>) -> Vec<String> {
+    let mut args = vec!["SET".to_string(), key, value];
+    if let Some(ttl) = ttl {
+        args.extend(["EX".to_string(), ttl.to_string()]);
+    }
+    args
+}
+
+/// `PUT /v1/keys/{key}`: 201 when the key is new, 200 when replaced.
+async fn put_key(
+    State(dbs): State<Databases>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Path(key): Path<String>,
+    Query(params): Query<KeyParams>,
+    headers: HeaderMap,
+    body: axum::body::Bytes,
+) -> ApiResult {
+    let mut session = params.session(&dbs, &headers)?;
+    let ttl = ttl_param(&params, &headers)?;
+    let value = decode_body(&headers, &body)?;
+
+    let existed = is_live(&dbs, session.db, &key).await;
+    run(&dbs, &mut session, addr, set_args(key, value, ttl)).await?;
+    let status = if existed { StatusCode::OK } else { StatusCode::CREATED };
+    Ok((status, Json(json!({ "result": "OK" }))).into_response())
+}
+
+/// `DELETE /v1/keys/{key}`: 204, or 404 when there was nothing to delete.
+async fn delete_key(
+    State(dbs): State<Databases>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Path(key): Path<String>,
+    Query(params): Query<KeyParams>,
+    headers: HeaderMap,
+) -> ApiResult {
+    let mut session = params.session(&dbs, &headers)?;
+    match run(&dbs, &mut session, addr, command_args("DEL", &[&key])).await? {
+        RespValue::Integer(n) if n > 0 => Ok(StatusCode::NO_CONTENT.into_response()),
+        _ => Err(ApiError(StatusCode::NOT_FOUND, format!("no such key '{}'", key))),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct KeysBody {
+    keys: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct BatchItem {
+    key: String,
+    value: String,
+    ttl: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct ItemsBody {
+    items: Vec<BatchItem>,
+}
+
+/// `POST /v1/batch/get` with `{"keys": [...]}`; missing keys map to `null`.
+async fn batch_get(
+    State(dbs): State<Databases>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(params): Query<KeyParams>,
+    headers: HeaderMap,
+    Json(body): Json<KeysBody>,
+) -> ApiResult {
+    let mut session = params.session(&dbs, &headers)?;
+    let mut values = Map::new();
+    for key in body.keys {
+        let reply = run(&dbs, &mut session, addr, command_args("GET", &[&key])).await?;
+        values.insert(key, bulk_string(reply).map_or(Value::Null, Value::String));
+    }
+    Ok(Json(json!({ "values": values })).into_response())
+}
+
+/// `POST /v1/batch/set` with `{"items": [{"key", "value", "ttl"?}, ...]}`,
+/// one `SET` per item in order. Every item is checked against the ACL
+/// first, so a refused one means nothing is stored.
+async fn batch_set(
+    State(dbs): State<Databases>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(params): Query<KeyParams>,
+    headers: HeaderMap,
+    Json(body): Json<ItemsBody>,
+) -> ApiResult {
+    let mut session = params.session(&dbs, &headers)?;
+    let commands: Vec<Vec<String>> = body
+        .items
+        .into_iter()
+        .map(|item| set_args(item.key, item.value, item.ttl))
+        .collect();
+    for (i, args) in commands.iter().enumerate() {
+        if let Err(RespValue::Error(e)) = acl::check(&mut session, args) {
+            let ApiError(status, e) = reply_error(e);
+            return Err(ApiError(status, format!("item {}: {}", i, e)));
+        }
+    }
+
+    let stored = commands.len();
+    for args in commands {
+        run(&dbs, &mut session, addr, args).await?;
+    }
+    Ok(Json(json!({ "stored": stored })).into_response())
+}
+
+/// `POST /v1/batch/delete` with `{"keys": [...]}`, as one `DEL`.
+async fn batch_delete(
+    State(dbs): State<Databases>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(params): Query<KeyParams>,
+    headers: HeaderMap,
+    Json(body): Json<KeysBody>,
+) -> ApiResult {
+    if body.keys.is_empty() {
+        return Ok(Json(json!({ "deleted": 0 })).into_response());
+    }
+    let mut session = params.session(&dbs, &headers)?;
+    let mut args = vec!["DEL".to_string()];
+    args.extend(body.keys);
+    let deleted = match run(&dbs, &mut session, addr, args).await? {
+        RespValue::Integer(n) => n,
+        _ => 0,
+    };
+    Ok(Json(json!({ "deleted": deleted })).into_response())
+}
+
+/// A command as either `["SET", "k", "v"]` or `{"args": [...], "db": n}`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum CommandBody {
+    Args(Vec<String>),
+    Full { args: Vec<String>, db: Option<usize> },
+}
+
+/// `POST /v1/command`: runs any command through the normal dispatcher, in a
+/// throwaway session, and returns the reply as JSON. Error replies are 400,
+/// or 401/403 when the ACL refuses them.
+async fn command(
+    State(dbs): State<Databases>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(body): Json<CommandBody>,
+) -> ApiResult {
+    let (args, db) = match body {
+        CommandBody::Args(args) => (args, None),
+        CommandBody::Full { args, db } => (args, db),
+    };
+    if args.is_empty() {
+        return Err(bad_request("empty command"));
+    }
+
+    let mut session = session(&headers)?;
+    if let Some(db) = db {
+        if db >= dbs.len() {
+            return Err(bad_request("ERR DB index is out of range"));
+        }
+        session.db = db;
+    }
+
+    let reply = run(&dbs, &mut session, addr, args).await?;
+    Ok(Json(json!({ "result": to_json(&reply) })).into_response())
+}
+
+#[derive(Deserialize)]
+pub struct SlowlogParams {
+    count: Option<usize>,
+}
+
+/// `GET /v1/slowlog[?count=n]`: the slow log, newest first, all of it by
+/// default. Needs the same permission as SLOWLOG.
+async fn slowlog_entries(headers: HeaderMap, Query(params): Query<SlowlogParams>) -> ApiResult {
+    authorize(&headers, "slowlog", &[])?;
+    let entries: Vec<Value> = slowlog::get(params.count.unwrap_or(usize::MAX))
+        .into_iter()
+        .map(|e| {
+            json!({
+                "id": e.id,
+                "timestamp": e.timestamp,
+                "duration_us": e.duration_us,
+                "args": e.args,
+                "client_addr": e.client_addr,
+                "client_name": e.client_name,
+            })
+        })
+        .collect();
+    Ok(Json(json!({ "len": slowlog::len(), "entries": entries })).into_response())
+}
+
+/// Maps a reply onto JSON. Maps with non-string keys become arrays of pairs.
+pub(crate) fn to_json(v: &RespValue) -> Value {
+    match v {
+        RespValue::SimpleString(s) => Value::String(s.clone()),
+        RespValue::Error(e) => json!({ "error": e }),
+        RespValue::Integer(n) => json!(n),
+        RespValue::Bulk(Some(b)) => Value::String(String::from_utf8_lossy(b).into_owned()),
+        RespValue::Bulk(None) | RespValue::Null => Value::Null,
+        RespValue::Double(d) => json!(d),
+        RespValue::Boolean(b) => Value::Bool(*b),
+        RespValue::BigNumber(n) => Value::String(n.clone()),
+        RespValue::Verbatim(_, text) => Value::String(text.clone()),
+        RespValue::Array(items) | RespValue::Set(items) | RespValue::Push(items) => {
+            Value::Array(items.iter().map(to_json).collect())
+        }
+        RespValue::Map(pairs) => {
+            let pairs: Vec<(Value, Value)> = pairs.iter().map(|(k, v)| (to_json(k), to_json(v))).collect();
+            if pairs.iter().all(|(k, _)| k.is_string()) {
+                Value::Object(
+                    pairs
+                        .into_iter()
+                        .map(|(k, v)| (k.as_str().unwrap_or_default().to_string(), v))
+                        .collect(),
+                )
+            } else {
+                Value::Array(pairs.into_iter().map(|(k, v)| json!([k, v])).collect())
+            }
+        }
+        RespValue::Attribute(_, inner) => to_json(inner),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::extract::connect_info::MockConnectInfo;
+    use axum::http::Request;
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    struct Api {
+        router: Router,
+    }
+
+    impl Api {
+        fn new() -> Api {
+            let addr = SocketAddr::from(([127, 0, 0, 1], 40000));
+            Api {
+                router: router(Databases::new(2)).layer(MockConnectInfo(addr)),
+            }
+        }
+
+        async fn send(&self, method: &str, uri: &str, auth: Option<&str>, body: Option<Value>) -> (StatusCode, Value) {
+            let mut req = Request::builder().method(method).uri(uri);
+            if let Some(credentials) = auth {
+                let encoded = base64::engine::general_purpose::STANDARD.encode(credentials);
+                req = req.header(header::AUTHORIZATION, format!("Basic {}", encoded));
+            }
+            let req = match body {
+                Some(json) => req
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(json.to_string())),
+                None => req.header(header::ACCEPT, "application/json").body(Body::empty()),
+            };
+            let resp = self.router.clone().oneshot(req.unwrap()).await.unwrap();
+            let status = resp.status();
+            let bytes = resp.into_body().collect().await.unwrap().to_bytes();
+            (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+        }
+    }
+
+    #[tokio::test]
+    async fn key_routes_round_trip() {
+        let _lock = shutdown::TEST_LOCK.lock().await;
+        let api = Api::new();
+
+        let (status, _) = api.send("PUT", "/v1/keys/a/b", None, Some(json!("v1"))).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (status, _) = api.send("PUT", "/v1/keys/a/b?ttl=100", None, Some(json!("v2"))).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, body) = api.send("GET", "/v1/keys/a/b", None, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["value"], "\"v2\"");
+        assert!(body["ttl"].as_u64().is_some_and(|t| (99..=100).contains(&t)), "{}", body);
+
+        let (status, body) = api.send("POST", "/v1/batch/get", None, Some(json!({ "keys": ["a/b", "nope"] }))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["values"], json!({ "a/b": "\"v2\"", "nope": null }));
+
+        let (status, _) = api.send("DELETE", "/v1/keys/a/b", None, None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _) = api.send("DELETE", "/v1/keys/a/b", None, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, body) = api.send("GET", "/v1/keys/a/b", None, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"], "no such key 'a/b'");
+    }
+
+    #[tokio::test]
+    async fn command_route_maps_replies_and_errors() {
+        let _lock = shutdown::TEST_LOCK.lock().await;
+        let api = Api::new();
+
+        let (status, body) = api.send("POST", "/v1/command", None, Some(json!(["INCR", "hits"]))).await;
+        assert_eq!((status, body), (StatusCode::OK, json!({ "result": 1 })));
+        let (status, body) = api.send("POST", "/v1/command", None, Some(json!({ "args": ["GET", "hits"], "db": 1 }))).await;
+        assert_eq!((status, body), (StatusCode::OK, json!({ "result": null })));
+        let (status, _) = api.send("POST", "/v1/command", None, Some(json!({ "args": ["GET", "hits"], "db": 9 }))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, body) = api.send("POST", "/v1/command", None, Some(json!(["NOSUCHCMD"]))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["error"].as_str().unwrap().starts_with("ERR unknown command"), "{}", body);
+    }
+
+    #[tokio::test]
+    async fn batch_set_stores_nothing_when_an_item_is_refused() {
+        let _lock = shutdown::TEST_LOCK.lock().await;
+        let rules = ["on", ">secret", "~allowed:*", "+@all"].map(String::from);
+        acl::set_user("http-test-limited", &rules).unwrap();
+        let api = Api::new();
+        let auth = Some("http-test-limited:secret");
+
+        let items = json!({ "items": [
+            { "key": "allowed:1", "value": "a" },
+            { "key": "other:1", "value": "b" },
+        ] });
+        let (status, body) = api.send("POST", "/v1/batch/set", auth, Some(items)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert!(body["error"].as_str().unwrap().starts_with("item 1: NOPERM"), "{}", body);
+        let (status, _) = api.send("GET", "/v1/keys/allowed:1", auth, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let items = json!({ "items": [
+            { "key": "allowed:1", "value": "a" },
+            { "key": "allowed:2", "value": "b", "ttl": 60 },
+        ] });
+        let (status, body) = api.send("POST", "/v1/batch/set", auth, Some(items)).await;
+        assert_eq!((status, body), (StatusCode::OK, json!({ "stored": 2 })));
+        let (status, body) = api.send("GET", "/v1/keys/allowed:2", auth, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["value"], "b");
+        assert!(body["ttl"].as_u64().is_some_and(|t| (59..=60).contains(&t)), "{}", body);
+
+        let (status, _) = api.send("POST", "/v1/batch/set", Some("http-test-limited:wrong"), Some(json!({ "items": [] }))).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        acl::del_users(&["http-test-limited".to_string()]).unwrap();
+    }
+}
@@ -1,22 +1,54 @@
 use axum::{routing::get, Router};
 use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::TcpListener;
 
-use crate::server::metrics_prom;
+use crate::db::storage::Databases;
+use crate::server::tls::Tls;
+use crate::server::{http_api, metrics_prom, shutdown, websocket};
 
 async fn metrics_handler() -> String {
     String::from_utf8_lossy(&metrics_prom::gather()).to_string()
 }
 
-pub async fn serve(bind: &str) {
-    let app = Router::new().route("/metrics", get(metrics_handler));
+/// How long open HTTP requests get to finish once the server is stopped.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
+/// Serves metrics, the REST API and WebSockets until the server is stopped.
+pub async fn serve(bind: &str, dbs: Databases, tls: Option<Arc<Tls>>) {
+    let app = Router::new()
+        .route("/metrics", get(metrics_handler))
+        .merge(http_api::router(dbs.clone()))
+        .merge(websocket::router(dbs));
 
     let addr: SocketAddr = bind.parse().expect("invalid METRICS_BIND");
+    // Peer addresses are needed for WebSocket clients in CLIENT LIST.
+    let app = app.into_make_service_with_connect_info::<SocketAddr>();
+
+    if let Some(tls) = tls {
+        let handle = axum_server::Handle::new();
+        tokio::spawn({
+            let handle = handle.clone();
+            async move {
+                shutdown::stopped().await;
+                handle.graceful_shutdown(Some(SHUTDOWN_GRACE));
+            }
+        });
+        axum_server::bind_rustls(addr, tls.http_config())
+            .handle(handle)
+            .serve(app)
+            .await
+            .expect("metrics server failed");
+        return;
+    }
+
     let listener = TcpListener::bind(addr)
         .await
         .expect("failed to bind METRICS_BIND");
 
     axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown::stopped())
         .await
         .expect("metrics server failed");
-}
\ No newline at end of file
+}
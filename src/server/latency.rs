@@ -0,0 +1,214 @@
+//! The latency monitor: spikes over `latency-monitor-threshold` ms, kept per
+//! event as in Redis. Each event keeps its worst sample per second, for the
+//! last `HISTORY_LEN` seconds that had one.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use prometheus::core::Collector;
+
+use crate::server::{acl, metrics_prom, stats};
+
+/// A command outside the `fast` category.
+pub const COMMAND: &str = "command";
+/// A command in the `fast` category, which should never be slow.
+pub const FAST_COMMAND: &str = "fast-command";
+/// Waiting for a database lock held by another client or the TTL cleaner.
+pub const LOCK_WAIT: &str = "lock-wait";
+/// The TTL cleaner's scan of one database, which holds its lock.
+pub const EXPIRE_CYCLE: &str = "expire-cycle";
+
+const HISTORY_LEN: usize = 160;
+
+#[derive(Default)]
+struct Series {
+    /// `(unix time, ms)`, oldest first.
+    samples: VecDeque<(u64, u64)>,
+    max: u64,
+}
+
+static EVENTS: Lazy<Mutex<BTreeMap<&'static str, Series>>> = Lazy::new(|| Mutex::new(BTreeMap::new()));
+/// Kept apart from the config, since database locks read it on every wait.
+static THRESHOLD_MS: AtomicU64 = AtomicU64::new(0);
+
+pub fn set_threshold(ms: u64) {
+    THRESHOLD_MS.store(ms, Ordering::Relaxed);
+}
+
+/// Records `elapsed` for `event` if it reaches the threshold. A threshold
+/// of 0 turns the monitor off.
+pub fn sample(event: &'static str, elapsed: Duration) {
+    let threshold = THRESHOLD_MS.load(Ordering::Relaxed);
+    let ms = elapsed.as_millis() as u64;
+    if threshold == 0 || ms < threshold {
+        return;
+    }
+
+    let now = stats::unix_time_secs();
+    let mut events = EVENTS.lock().unwrap();
+    let series = events.entry(event).or_default();
+    series.max = series.max.max(ms);
+    match series.samples.back_mut() {
+        Some((ts, worst)) if *ts == now => *worst = (*worst).max(ms),
+        _ => {
+            if series.samples.len() == HISTORY_LEN {
+                series.samples.pop_front();
+            }
+            series.samples.push_back((now, ms));
+        }
+    }
+}
+
+/// Samples a command's run time as `command` or `fast-command`.
+pub fn command(cmd: &str, elapsed: Duration) {
+    let threshold = THRESHOLD_MS.load(Ordering::Relaxed);
+    if threshold == 0 || (elapsed.as_millis() as u64) < threshold {
+        return;
+    }
+    let event = if acl::in_category(&cmd.to_lowercase(), "fast") {
+        FAST_COMMAND
+    } else {
+        COMMAND
+    };
+    sample(event, elapsed);
+}
+
+/// Per event: `(name, time of the latest spike, its ms, all-time max ms)`.
+pub fn latest() -> Vec<(&'static str, u64, u64, u64)> {
+    EVENTS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(|(name, s)| {
+            let (ts, ms) = *s.samples.back()?;
+            Some((*name, ts, ms, s.max))
+        })
+        .collect()
+}
+
+/// `(unix time, ms)` samples for one event, oldest first.
+pub fn history(event: &str) -> Vec<(u64, u64)> {
+    EVENTS
+        .lock()
+        .unwrap()
+        .get(event)
+        .map_or_else(Vec::new, |s| s.samples.iter().copied().collect())
+}
+
+/// Forgets the named events, or all of them. Returns how many were reset.
+pub fn reset(events: &[String]) -> usize {
+    let mut all = EVENTS.lock().unwrap();
+    if events.is_empty() {
+        let n = all.len();
+        all.clear();
+        return n;
+    }
+    events
+        .iter()
+        .filter(|e| all.remove(e.to_lowercase().as_str()).is_some())
+        .count()
+}
+
+/// A plain-language report on the recorded spikes, for LATENCY DOCTOR.
+pub fn doctor() -> String {
+    let threshold = THRESHOLD_MS.load(Ordering::Relaxed);
+    if threshold == 0 {
+        return "Latency monitoring is disabled. Set latency-monitor-threshold to a number \
+                of milliseconds with CONFIG SET to turn it on.\n"
+            .into();
+    }
+    let events = EVENTS.lock().unwrap();
+    if events.is_empty() {
+        return format!(
+            "No latency spike of {} ms or more was observed since the monitor was turned on \
+             or last reset.\n",
+            threshold
+        );
+    }
+
+    let mut out = format!(
+        "Latency spikes of {} ms or more were observed for these events:\n\n",
+        threshold
+    );
+    for (n, (name, s)) in events.iter().enumerate() {
+        let count = s.samples.len() as u64;
+        let avg = s.samples.iter().map(|(_, ms)| ms).sum::<u64>() / count.max(1);
+        let dev = s.samples.iter().map(|(_, ms)| ms.abs_diff(avg)).sum::<u64>() / count.max(1);
+        let period = match (s.samples.front(), s.samples.back()) {
+            (Some((first, _)), Some((last, _))) if count > 1 => (last - first) as f64 / (count - 1) as f64,
+            _ => 0.0,
+        };
+        out.push_str(&format!(
+            "{}. {}: {} latency spikes (average {}ms, mean deviation {}ms, period {:.2} sec). \
+             Worst all time event {}ms.\n",
+            n + 1,
+            name,
+            count,
+            avg,
+            dev,
+            period,
+            s.max
+        ));
+    }
+
+    out.push_str("\nAdvice:\n\n");
+    for name in events.keys() {
+        let advice = match *name {
+            COMMAND => "- Slow commands: check SLOWLOG GET for the calls involved. Commands \
+                        like KEYS or FLUSHDB on a large database hold its lock for the whole run.",
+            FAST_COMMAND => "- Fast commands were slow: the host is probably overloaded or \
+                             swapping. Check used_cpu_* and used_memory_rss in INFO.",
+            LOCK_WAIT => "- Clients waited for a database lock. A slow command or an expire \
+                          cycle on the same database holds it; see the other events and \
+                          keyval_lock_wait_seconds.",
+            EXPIRE_CYCLE => "- The TTL cleaner scans each database under its lock. Many keys \
+                             make passes slow; spread keys over databases or raise \
+                             expire-interval-ms to run it less often.",
+            _ => continue,
+        };
+        out.push_str(advice);
+        out.push('\n');
+    }
+    out
+}
+
+/// One command's run times, as in LATENCY HISTOGRAM.
+pub struct CommandHistogram {
+    pub name: String,
+    pub calls: u64,
+    /// `(upper bound in µs, calls that took at most that)`, cumulative.
+    pub buckets: Vec<(u64, u64)>,
+}
+
+/// Per-command histograms read from `CMD_LATENCY`, by name.
+pub fn command_histograms() -> Vec<CommandHistogram> {
+    let mut out = Vec::new();
+    for family in metrics_prom::CMD_LATENCY.collect() {
+        for m in family.get_metric() {
+            let Some(name) = m
+                .get_label()
+                .iter()
+                .find(|l| l.get_name() == "cmd")
+                .map(|l| l.get_value().to_lowercase())
+            else {
+                continue;
+            };
+            let h = m.get_histogram();
+            let buckets = h
+                .get_bucket()
+                .iter()
+                .map(|b| ((b.get_upper_bound() * 1e6) as u64, b.get_cumulative_count()))
+                .collect();
+            out.push(CommandHistogram {
+                name,
+                calls: h.get_sample_count(),
+                buckets,
+            });
+        }
+    }
+    out.sort_by(|a, b| a.name.cmp(&b.name));
+    out
+}
@@ -14,7 +14,28 @@ pub fn rss_bytes() -> Option<u64> {
     Some(resident_pages * page_size as u64)
 }
 
+/// Peak resident set size, from `VmHWM`.
+pub fn peak_rss_bytes() -> Option<u64> {
+    status_kb("/proc/self/status", "VmHWM:").map(|kb| kb * 1024)
+}
+
+pub fn total_memory_bytes() -> Option<u64> {
+    status_kb("/proc/meminfo", "MemTotal:").map(|kb| kb * 1024)
+}
+
+/// Reads a `Name:   123 kB` line.
+fn status_kb(path: &str, field: &str) -> Option<u64> {
+    let s = fs::read_to_string(path).ok()?;
+    let line = s.lines().find(|l| l.starts_with(field))?;
+    line[field.len()..].split_whitespace().next()?.parse().ok()
+}
+
 pub fn cpu_seconds_total() -> Option<f64> {
+    cpu_seconds().map(|(user, sys)| user + sys)
+}
+
+/// User and system CPU time, in seconds.
+pub fn cpu_seconds() -> Option<(f64, f64)> {
     let s = fs::read_to_string("/proc/self/stat").ok()?;
 
     let rparen = s.rfind(')')?;
@@ -30,6 +51,8 @@ pub fn cpu_seconds_total() -> Option<f64> {
         return None;
     }
 
-    let total_ticks = utime_ticks + stime_ticks;
-    Some(total_ticks as f64 / clk_tck as f64)
+    Some((
+        utime_ticks as f64 / clk_tck as f64,
+        stime_ticks as f64 / clk_tck as f64,
+    ))
 }
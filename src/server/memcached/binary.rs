@@ -0,0 +1,422 @@
+//! The memcached binary protocol: a 24-byte header followed by extras, key
+//! and value. Quiet variants only answer on failure (or, for `getq`/`getkq`,
+//! on a hit).
+
+use crate::db::storage::Db;
+use crate::server::memcached::store::{self, DeltaResult, StoreMode, StoreResult};
+use crate::server::memcached::{count, feed_monitor, refused, stats, Step, MAX_ITEM_SIZE, MAX_KEY_LEN, STATS};
+use crate::server::clients::{self, ClientInfo};
+use crate::server::session::Session;
+
+pub(crate) const REQUEST_MAGIC: u8 = 0x80;
+const RESPONSE_MAGIC: u8 = 0x81;
+const HEADER_LEN: usize = 24;
+
+const STATUS_OK: u16 = 0x00;
+const STATUS_NOT_FOUND: u16 = 0x01;
+const STATUS_EXISTS: u16 = 0x02;
+const STATUS_TOO_LARGE: u16 = 0x03;
+const STATUS_INVALID: u16 = 0x04;
+const STATUS_NOT_STORED: u16 = 0x05;
+const STATUS_NON_NUMERIC: u16 = 0x06;
+const STATUS_AUTH_ERROR: u16 = 0x20;
+const STATUS_UNKNOWN_COMMAND: u16 = 0x81;
+
+mod op {
+    pub const GET: u8 = 0x00;
+    pub const SET: u8 = 0x01;
+    pub const ADD: u8 = 0x02;
+    pub const REPLACE: u8 = 0x03;
+    pub const DELETE: u8 = 0x04;
+    pub const INCREMENT: u8 = 0x05;
+    pub const DECREMENT: u8 = 0x06;
+    pub const QUIT: u8 = 0x07;
+    pub const FLUSH: u8 = 0x08;
+    pub const GETQ: u8 = 0x09;
+    pub const NOOP: u8 = 0x0a;
+    pub const VERSION: u8 = 0x0b;
+    pub const GETK: u8 = 0x0c;
+    pub const GETKQ: u8 = 0x0d;
+    pub const APPEND: u8 = 0x0e;
+    pub const PREPEND: u8 = 0x0f;
+    pub const STAT: u8 = 0x10;
+    pub const SETQ: u8 = 0x11;
+    pub const ADDQ: u8 = 0x12;
+    pub const REPLACEQ: u8 = 0x13;
+    pub const DELETEQ: u8 = 0x14;
+    pub const INCREMENTQ: u8 = 0x15;
+    pub const DECREMENTQ: u8 = 0x16;
+    pub const QUITQ: u8 = 0x17;
+    pub const FLUSHQ: u8 = 0x18;
+    pub const APPENDQ: u8 = 0x19;
+    pub const PREPENDQ: u8 = 0x1a;
+    pub const TOUCH: u8 = 0x1c;
+    pub const GAT: u8 = 0x1d;
+    pub const GATQ: u8 = 0x1e;
+    pub const GATK: u8 = 0x23;
+    pub const GATKQ: u8 = 0x24;
+}
+
+struct Header {
+    opcode: u8,
+    opaque: [u8; 4],
+    cas: u64,
+}
+
+struct Response<'a> {
+    status: u16,
+    cas: u64,
+    extras: &'a [u8],
+    key: &'a [u8],
+    value: &'a [u8],
+}
+
+impl<'a> Response<'a> {
+    fn status(status: u16) -> Self {
+        Response { status, cas: 0, extras: &[], key: &[], value: &[] }
+    }
+
+    fn write(&self, req: &Header, out: &mut Vec<u8>) {
+        let body = self.extras.len() + self.key.len() + self.value.len();
+        out.push(RESPONSE_MAGIC);
+        out.push(req.opcode);
+        out.extend_from_slice(&(self.key.len() as u16).to_be_bytes());
+        out.push(self.extras.len() as u8);
+        out.push(0);
+        out.extend_from_slice(&self.status.to_be_bytes());
+        out.extend_from_slice(&(body as u32).to_be_bytes());
+        out.extend_from_slice(&req.opaque);
+        out.extend_from_slice(&self.cas.to_be_bytes());
+        out.extend_from_slice(self.extras);
+        out.extend_from_slice(self.key);
+        out.extend_from_slice(self.value);
+    }
+}
+
+fn error_text(status: u16) -> &'static [u8] {
+    match status {
+        STATUS_NOT_FOUND => b"Not found",
+        STATUS_EXISTS => b"Data exists for key.",
+        STATUS_TOO_LARGE => b"Too large.",
+        STATUS_INVALID => b"Invalid arguments",
+        STATUS_NOT_STORED => b"Not stored.",
+        STATUS_NON_NUMERIC => b"Non-numeric server-side value for incr or decr",
+        _ => b"Unknown command",
+    }
+}
+
+/// An opcode as CLIENT LIST's `cmd` shows it.
+fn name(opcode: u8) -> &'static str {
+    match opcode {
+        op::GET | op::GETQ | op::GETK | op::GETKQ => "get",
+        op::GAT | op::GATQ | op::GATK | op::GATKQ => "gat",
+        op::SET | op::SETQ => "set",
+        op::ADD | op::ADDQ => "add",
+        op::REPLACE | op::REPLACEQ => "replace",
+        op::APPEND | op::APPENDQ => "append",
+        op::PREPEND | op::PREPENDQ => "prepend",
+        op::DELETE | op::DELETEQ => "delete",
+        op::INCREMENT | op::INCREMENTQ => "incr",
+        op::DECREMENT | op::DECREMENTQ => "decr",
+        op::TOUCH => "touch",
+        op::FLUSH | op::FLUSHQ => "flush_all",
+        op::STAT => "stats",
+        op::NOOP => "noop",
+        op::VERSION => "version",
+        op::QUIT | op::QUITQ => "quit",
+        _ => "unknown",
+    }
+}
+
+/// The RESP commands whose permissions an opcode needs.
+fn permissions(opcode: u8) -> &'static [&'static str] {
+    match opcode {
+        op::GET | op::GETQ | op::GETK | op::GETKQ => &["get"],
+        op::GAT | op::GATQ | op::GATK | op::GATKQ => &["get", "expire"],
+        op::SET | op::SETQ | op::ADD | op::ADDQ | op::REPLACE | op::REPLACEQ
+        | op::APPEND | op::APPENDQ | op::PREPEND | op::PREPENDQ => &["set"],
+        op::DELETE | op::DELETEQ => &["del"],
+        op::INCREMENT | op::INCREMENTQ | op::DECREMENT | op::DECREMENTQ => &["incr"],
+        op::TOUCH => &["expire"],
+        op::FLUSH | op::FLUSHQ => &["flushdb"],
+        op::STAT => &["info"],
+        _ => &[],
+    }
+}
+
+fn be_u32(b: &[u8]) -> u32 {
+    u32::from_be_bytes([b[0], b[1], b[2], b[3]])
+}
+
+fn be_u64(b: &[u8]) -> u64 {
+    let mut a = [0u8; 8];
+    a.copy_from_slice(&b[..8]);
+    u64::from_be_bytes(a)
+}
+
+pub(crate) async fn process(
+    buf: &[u8],
+    db: &Db,
+    session: &mut Session,
+    client: &ClientInfo,
+    out: &mut Vec<u8>,
+) -> Step {
+    if buf.len() < HEADER_LEN {
+        return Step::Incomplete;
+    }
+    let key_len = u16::from_be_bytes([buf[2], buf[3]]) as usize;
+    let extras_len = buf[4] as usize;
+    let body_len = be_u32(&buf[8..12]) as usize;
+    let req = Header {
+        opcode: buf[1],
+        opaque: [buf[12], buf[13], buf[14], buf[15]],
+        cas: be_u64(&buf[16..24]),
+    };
+
+    // A body that cannot hold its own extras and key leaves no way to find
+    // the next request.
+    if extras_len + key_len > body_len {
+        Response { value: error_text(STATUS_INVALID), ..Response::status(STATUS_INVALID) }.write(&req, out);
+        return Step::Close;
+    }
+    if body_len > MAX_ITEM_SIZE + MAX_KEY_LEN + 64 {
+        Response { value: error_text(STATUS_TOO_LARGE), ..Response::status(STATUS_TOO_LARGE) }.write(&req, out);
+        return Step::Swallow { consumed: HEADER_LEN, discard: body_len };
+    }
+    let total = HEADER_LEN + body_len;
+    if buf.len() < total {
+        return Step::Incomplete;
+    }
+
+    let body = &buf[HEADER_LEN..total];
+    let extras = &body[..extras_len];
+    let key = &body[extras_len..extras_len + key_len];
+    let value = &body[extras_len + key_len..];
+
+    if key.len() > MAX_KEY_LEN {
+        fail(&req, STATUS_INVALID, out);
+        return Step::Consumed(total);
+    }
+    // Keys and values are stored as text, so binary data is refused rather
+    // than changed.
+    let Ok(key) = std::str::from_utf8(key) else {
+        fail(&req, STATUS_INVALID, out);
+        return Step::Consumed(total);
+    };
+
+    client.record_command(session, name(req.opcode));
+
+    let keys: &[&str] = if key.is_empty() { &[] } else { &[key] };
+    let resp_cmds = permissions(req.opcode);
+    let denied = resp_cmds.iter().find_map(|cmd| refused(session, cmd, keys));
+    if let Some(e) = denied {
+        Response { value: e.as_bytes(), ..Response::status(STATUS_AUTH_ERROR) }.write(&req, out);
+        return Step::Consumed(total);
+    }
+    // CLIENT PAUSE holds these back as it would the RESP commands.
+    for cmd in resp_cmds {
+        clients::wait_unpaused(cmd).await;
+    }
+    feed_monitor(session, &[&[name(req.opcode)], keys].concat());
+
+    let close = execute(&req, extras, key, value, db, out).await;
+    if close {
+        Step::Close
+    } else {
+        Step::Consumed(total)
+    }
+}
+
+fn fail(req: &Header, status: u16, out: &mut Vec<u8>) {
+    Response { value: error_text(status), ..Response::status(status) }.write(req, out);
+}
+
+/// Runs one request. Returns true when the connection should be closed.
+async fn execute(req: &Header, extras: &[u8], key: &str, value: &[u8], db: &Db, out: &mut Vec<u8>) -> bool {
+    let cas = (req.cas != 0).then_some(req.cas);
+
+    match req.opcode {
+        op::GET | op::GETQ | op::GETK | op::GETKQ | op::GAT | op::GATQ | op::GATK | op::GATKQ => {
+            let touch = matches!(req.opcode, op::GAT | op::GATQ | op::GATK | op::GATKQ);
+            let quiet = matches!(req.opcode, op::GETQ | op::GETKQ | op::GATQ | op::GATKQ);
+            let with_key = matches!(req.opcode, op::GETK | op::GETKQ | op::GATK | op::GATKQ);
+            if key.is_empty() || (touch && extras.len() != 4) || (!touch && !extras.is_empty()) {
+                fail(req, STATUS_INVALID, out);
+                return false;
+            }
+
+            count(&STATS.cmd_get);
+            let item = if touch {
+                count(&STATS.cmd_touch);
+                store::get_and_touch(db, key, be_u32(extras) as i64).await
+            } else {
+                store::get(db, key).await
+            };
+            match item {
+                Some(item) => {
+                    count(&STATS.get_hits);
+                    let flags = item.flags.to_be_bytes();
+                    Response {
+                        status: STATUS_OK,
+                        cas: item.cas,
+                        extras: &flags,
+                        key: if with_key { key.as_bytes() } else { &[] },
+                        value: item.value.as_bytes(),
+                    }
+                        .write(req, out);
+                }
+                None => {
+                    count(&STATS.get_misses);
+                    if !quiet {
+                        Response {
+                            key: if with_key { key.as_bytes() } else { &[] },
+                            value: error_text(STATUS_NOT_FOUND),
+                            ..Response::status(STATUS_NOT_FOUND)
+                        }
+                            .write(req, out);
+                    }
+                }
+            }
+        }
+
+        op::SET | op::SETQ | op::ADD | op::ADDQ | op::REPLACE | op::REPLACEQ
+        | op::APPEND | op::APPENDQ | op::PREPEND | op::PREPENDQ => {
+            let (mode, quiet) = match req.opcode {
+                op::SET => (StoreMode::Set, false),
+                op::SETQ => (StoreMode::Set, true),
+                op::ADD => (StoreMode::Add, false),
+                op::ADDQ => (StoreMode::Add, true),
+                op::REPLACE => (StoreMode::Replace, false),
+                op::REPLACEQ => (StoreMode::Replace, true),
+                op::APPEND => (StoreMode::Append, false),
+                op::APPENDQ => (StoreMode::Append, true),
+                op::PREPEND => (StoreMode::Prepend, false),
+                _ => (StoreMode::Prepend, true),
+            };
+            let concat = matches!(mode, StoreMode::Append | StoreMode::Prepend);
+            let extras_ok = if concat { extras.is_empty() } else { extras.len() == 8 };
+            if key.is_empty() || !extras_ok {
+                fail(req, STATUS_INVALID, out);
+                return false;
+            }
+            if value.len() > MAX_ITEM_SIZE {
+                fail(req, STATUS_TOO_LARGE, out);
+                return false;
+            }
+
+            let (flags, exptime) = if concat {
+                (0, 0)
+            } else {
+                (be_u32(&extras[..4]), be_u32(&extras[4..8]) as i64)
+            };
+            count(&STATS.cmd_set);
+            let Ok(value) = std::str::from_utf8(value).map(str::to_string) else {
+                fail(req, STATUS_INVALID, out);
+                return false;
+            };
+            match store::store(db, mode, key, value, flags, exptime, cas).await {
+                StoreResult::Stored(new_cas) => {
+                    if !quiet {
+                        Response { cas: new_cas, ..Response::status(STATUS_OK) }.write(req, out);
+                    }
+                }
+                // As in memcached, a failed `add` reports that the key exists.
+                StoreResult::NotStored if mode == StoreMode::Add => fail(req, STATUS_EXISTS, out),
+                StoreResult::NotStored if concat => fail(req, STATUS_NOT_STORED, out),
+                StoreResult::NotStored | StoreResult::NotFound => fail(req, STATUS_NOT_FOUND, out),
+                StoreResult::Exists => fail(req, STATUS_EXISTS, out),
+            }
+        }
+
+        op::DELETE | op::DELETEQ => {
+            if key.is_empty() || !extras.is_empty() {
+                fail(req, STATUS_INVALID, out);
+                return false;
+            }
+            match store::delete(db, key, cas).await {
+                StoreResult::NotFound => fail(req, STATUS_NOT_FOUND, out),
+                StoreResult::Exists => fail(req, STATUS_EXISTS, out),
+                _ if req.opcode == op::DELETEQ => {}
+                _ => Response::status(STATUS_OK).write(req, out),
+            }
+        }
+
+        op::INCREMENT | op::INCREMENTQ | op::DECREMENT | op::DECREMENTQ => {
+            if key.is_empty() || extras.len() != 20 {
+                fail(req, STATUS_INVALID, out);
+                return false;
+            }
+            let by = be_u64(&extras[..8]);
+            let initial = be_u64(&extras[8..16]);
+            let exptime = be_u32(&extras[16..20]);
+            // An exptime of all ones means "do not create the item".
+            let initial = (exptime != u32::MAX).then_some((initial, exptime as i64));
+            let incr = matches!(req.opcode, op::INCREMENT | op::INCREMENTQ);
+            let quiet = matches!(req.opcode, op::INCREMENTQ | op::DECREMENTQ);
+
+            match store::delta(db, key, incr, by, initial).await {
+                DeltaResult::Value(n, new_cas) => {
+                    if !quiet {
+                        let n = n.to_be_bytes();
+                        Response { cas: new_cas, value: &n, ..Response::status(STATUS_OK) }.write(req, out);
+                    }
+                }
+                DeltaResult::NotFound => fail(req, STATUS_NOT_FOUND, out),
+                DeltaResult::NonNumeric => fail(req, STATUS_NON_NUMERIC, out),
+            }
+        }
+
+        op::TOUCH => {
+            if key.is_empty() || extras.len() != 4 {
+                fail(req, STATUS_INVALID, out);
+                return false;
+            }
+            count(&STATS.cmd_touch);
+            match store::get_and_touch(db, key, be_u32(extras) as i64).await {
+                Some(_) => Response::status(STATUS_OK).write(req, out),
+                None => fail(req, STATUS_NOT_FOUND, out),
+            }
+        }
+
+        op::FLUSH | op::FLUSHQ => {
+            let delay = match extras.len() {
+                0 => 0,
+                4 => be_u32(extras) as u64,
+                _ => {
+                    fail(req, STATUS_INVALID, out);
+                    return false;
+                }
+            };
+            count(&STATS.cmd_flush);
+            store::flush_all(db, delay).await;
+            if req.opcode == op::FLUSH {
+                Response::status(STATUS_OK).write(req, out);
+            }
+        }
+
+        op::STAT => {
+            if key.is_empty() {
+                for (name, value) in stats(db).await {
+                    Response { key: name.as_bytes(), value: value.as_bytes(), ..Response::status(STATUS_OK) }
+                        .write(req, out);
+                }
+                Response::status(STATUS_OK).write(req, out);
+            } else {
+                fail(req, STATUS_NOT_FOUND, out);
+            }
+        }
+
+        op::NOOP => Response::status(STATUS_OK).write(req, out),
+        op::VERSION => {
+            Response { value: env!("CARGO_PKG_VERSION").as_bytes(), ..Response::status(STATUS_OK) }.write(req, out);
+        }
+        op::QUIT => {
+            Response::status(STATUS_OK).write(req, out);
+            return true;
+        }
+        op::QUITQ => return true,
+
+        _ => fail(req, STATUS_UNKNOWN_COMMAND, out),
+    }
+    false
+}
@@ -0,0 +1,308 @@
+//! Optional memcached listener. Clients speaking the memcached text, meta or
+//! binary protocol share database 0 with RESP clients; memcached flags and
+//! CAS uniques are stored on each `ValueEntry`. They cannot authenticate, so
+//! they act as the `default` user and are held to its ACL rules. With TLS
+//! configured, the listener serves TLS only, like the RESP port.
+
+mod binary;
+pub mod store;
+mod text;
+
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bytes::{Buf, BytesMut};
+use once_cell::sync::Lazy;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use crate::db::storage::{Databases, Db};
+use crate::protocol::resp::encoder::RespValue;
+use crate::server::clients::{self, ClientInfo};
+use crate::server::session::Session;
+use crate::server::tls::Tls;
+use crate::server::{acl, config, metrics_prom, monitor, shutdown, tcp_server};
+
+/// Largest value accepted, memcached's default `-I 1m`.
+pub const MAX_ITEM_SIZE: usize = 1024 * 1024;
+/// Longest key accepted, as in memcached.
+pub const MAX_KEY_LEN: usize = 250;
+
+/// What handling the front of the read buffer produced.
+pub(crate) enum Step {
+    /// A full request is not buffered yet.
+    Incomplete,
+    /// A request of this many bytes was handled.
+    Consumed(usize),
+    /// The request line was handled but its data block of `discard` bytes
+    /// must be skipped, possibly across reads (e.g. an oversized value).
+    Swallow { consumed: usize, discard: usize },
+    /// The client asked to quit or the stream cannot be recovered.
+    Close,
+}
+
+/// Counters reported by `stats`.
+#[derive(Default)]
+pub(crate) struct Stats {
+    pub curr_connections: AtomicU64,
+    pub total_connections: AtomicU64,
+    pub cmd_get: AtomicU64,
+    pub cmd_set: AtomicU64,
+    pub cmd_touch: AtomicU64,
+    pub cmd_flush: AtomicU64,
+    pub get_hits: AtomicU64,
+    pub get_misses: AtomicU64,
+    pub bytes_read: AtomicU64,
+    pub bytes_written: AtomicU64,
+}
+
+pub(crate) static STATS: Lazy<Stats> = Lazy::new(Stats::default);
+static STARTED: Lazy<Instant> = Lazy::new(Instant::now);
+
+pub(crate) fn count(c: &AtomicU64) {
+    c.fetch_add(1, Ordering::Relaxed);
+}
+
+/// The `stats` name/value pairs, in the order memcached prints them.
+pub(crate) async fn stats(db: &Db) -> Vec<(&'static str, String)> {
+    let s = &*STATS;
+    let load = |c: &AtomicU64| c.load(Ordering::Relaxed).to_string();
+    let unix_now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+
+    vec![
+        ("pid", std::process::id().to_string()),
+        ("uptime", STARTED.elapsed().as_secs().to_string()),
+        ("time", unix_now.to_string()),
+        ("version", env!("CARGO_PKG_VERSION").to_string()),
+        ("curr_connections", load(&s.curr_connections)),
+        ("total_connections", load(&s.total_connections)),
+        ("cmd_get", load(&s.cmd_get)),
+        ("cmd_set", load(&s.cmd_set)),
+        ("cmd_flush", load(&s.cmd_flush)),
+        ("cmd_touch", load(&s.cmd_touch)),
+        ("get_hits", load(&s.get_hits)),
+        ("get_misses", load(&s.get_misses)),
+        ("bytes_read", load(&s.bytes_read)),
+        ("bytes_written", load(&s.bytes_written)),
+        ("curr_items", db.lock().await.len().to_string()),
+    ]
+}
+
+/// Checks a memcached request as the RESP command that needs the same
+/// permissions, e.g. `set k` as `SET k`, once per key. Returns the refusal
+/// to report, if any.
+pub(crate) fn refused(session: &mut Session, cmd: &str, keys: &[&str]) -> Option<String> {
+    // Without AUTH, the connection is logged in only while `default` needs
+    // no password, which CONFIG SET requirepass can change at any time.
+    session.authenticated = acl::default_authenticated();
+
+    let mut requests: Vec<Vec<String>> = keys
+        .iter()
+        .map(|k| vec![cmd.to_string(), k.to_string()])
+        .collect();
+    if requests.is_empty() {
+        requests.push(vec![cmd.to_string()]);
+    }
+    for parts in &requests {
+        if let Err(RespValue::Error(e)) = acl::check(session, parts) {
+            return Some(e);
+        }
+    }
+    None
+}
+
+/// Shows a request to MONITOR clients as its memcached command words.
+pub(crate) fn feed_monitor(session: &Session, words: &[&str]) {
+    if monitor::active() {
+        let parts: Vec<String> = words.iter().map(|w| w.to_string()).collect();
+        monitor::feed(&parts, session);
+    }
+}
+
+pub async fn bind(addr: &str) -> io::Result<TcpListener> {
+    let listener = TcpListener::bind(addr).await?;
+    println!("Memcached listener running on {}", addr);
+    Ok(listener)
+}
+
+/// Accepts memcached clients until the server is stopped. Accept errors,
+/// TLS and `maxclients`/`maxclients-per-ip` are handled as on the RESP port.
+pub async fn start(listener: TcpListener, dbs: Databases, tls: Option<Arc<Tls>>) {
+    let db = dbs.get(0).expect("at least one database").clone();
+    Lazy::force(&STARTED);
+
+    let mut backoff = tcp_server::ACCEPT_BACKOFF_MIN;
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = shutdown::stopped() => return,
+        };
+        let (stream, addr) = match accepted {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                tcp_server::accept_failed(e, &mut backoff).await;
+                continue;
+            }
+        };
+        backoff = tcp_server::ACCEPT_BACKOFF_MIN;
+        tcp_server::set_keepalive(&stream);
+        let admission = tcp_server::admit(Some(addr.ip()));
+        let laddr = stream
+            .local_addr()
+            .map(|a| a.to_string())
+            .unwrap_or_default();
+        let (db, tls) = (db.clone(), tls.clone());
+
+        tokio::spawn(async move {
+            let addr = addr.to_string();
+            let Some(tls) = tls else {
+                match admission {
+                    Ok(_slots) => handle(stream, addr, laddr, db).await,
+                    Err(reason) => refuse(stream, reason).await,
+                }
+                return;
+            };
+            // Refusals are sent after the handshake, so TLS clients can read them.
+            let handshake = tls.acceptor().accept(stream);
+            match tokio::time::timeout(tcp_server::TLS_HANDSHAKE_TIMEOUT, handshake).await {
+                Ok(Ok(stream)) => match admission {
+                    Ok(_slots) => handle(stream, addr, laddr, db).await,
+                    Err(reason) => refuse(stream, reason).await,
+                },
+                Ok(Err(_)) | Err(_) => metrics_prom::TLS_HANDSHAKE_FAILURES.inc(),
+            }
+        });
+    }
+}
+
+/// Tells a refused client why, as a `SERVER_ERROR`, then closes.
+async fn refuse<S: AsyncWrite + Unpin>(mut stream: S, reason: &str) {
+    let reason = reason.strip_prefix("ERR ").unwrap_or(reason);
+    let reply = format!("SERVER_ERROR {}\r\n", reason);
+    let _ = tokio::time::timeout(tcp_server::REFUSE_TIMEOUT, async {
+        stream.write_all(reply.as_bytes()).await?;
+        stream.shutdown().await
+    })
+    .await;
+}
+
+/// Serves one client. Like a RESP connection it is listed in CLIENT LIST,
+/// and closes when killed, when the server stops or after `timeout` idle
+/// seconds.
+async fn handle<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, addr: String, laddr: String, db: Db) {
+    metrics_prom::ACTIVE_CONNS.inc();
+    STATS.curr_connections.fetch_add(1, Ordering::Relaxed);
+    count(&STATS.total_connections);
+
+    let read_chunk = config::with(|c| c.read_buffer_size);
+    let mut buf = BytesMut::with_capacity(read_chunk);
+    let mut out: Vec<u8> = Vec::new();
+    let mut session = Session::new();
+    let client = clients::register(session.id, addr, laddr);
+    // Bytes of an unwanted data block still to be dropped from the input.
+    let mut discard = 0usize;
+
+    'conn: loop {
+        // Held from the first request of a batch until its replies are
+        // written, so a drain waits for them.
+        let mut in_flight = None;
+        buf.reserve(read_chunk);
+        let idle_timeout = config::with(|c| c.timeout);
+        let read = tokio::select! {
+            read = stream.read_buf(&mut buf) => read,
+            _ = shutdown::stopped() => break,
+            _ = client.killed() => break,
+            _ = tokio::time::sleep(Duration::from_secs(idle_timeout)), if idle_timeout > 0 => {
+                metrics_prom::IDLE_TIMEOUTS.inc();
+                break;
+            }
+        };
+        match read {
+            Ok(0) | Err(_) => break,
+            Ok(n) => {
+                metrics_prom::BYTES_IN.inc_by(n as u64);
+                STATS.bytes_read.fetch_add(n as u64, Ordering::Relaxed);
+                client.record_in(n);
+            }
+        }
+
+        loop {
+            if discard > 0 {
+                let n = discard.min(buf.len());
+                buf.advance(n);
+                discard -= n;
+                if discard > 0 {
+                    break;
+                }
+            }
+            if buf.is_empty() {
+                break;
+            }
+
+            // Requests wait while a drain is going on; write what has been
+            // answered so far first.
+            if in_flight.is_some() && shutdown::is_draining() {
+                if write_out(&mut stream, &mut out, &client).await.is_err() {
+                    break 'conn;
+                }
+                in_flight = None;
+            }
+            if in_flight.is_none() {
+                match shutdown::admit().await {
+                    Some(guard) => in_flight = Some(guard),
+                    None => {
+                        let _ = write_out(&mut stream, &mut out, &client).await;
+                        break 'conn;
+                    }
+                }
+            }
+
+            // The binary protocol is told apart by its magic byte, per request.
+            let step = if buf[0] == binary::REQUEST_MAGIC {
+                binary::process(&buf, &db, &mut session, &client, &mut out).await
+            } else {
+                text::process(&buf, &db, &mut session, &client, &mut out).await
+            };
+
+            match step {
+                Step::Incomplete => break,
+                Step::Consumed(n) => buf.advance(n),
+                Step::Swallow { consumed, discard: d } => {
+                    buf.advance(consumed);
+                    discard = d;
+                }
+                Step::Close => {
+                    let _ = write_out(&mut stream, &mut out, &client).await;
+                    break 'conn;
+                }
+            }
+        }
+
+        if write_out(&mut stream, &mut out, &client).await.is_err() {
+            break;
+        }
+    }
+
+    STATS.curr_connections.fetch_sub(1, Ordering::Relaxed);
+    metrics_prom::ACTIVE_CONNS.dec();
+}
+
+async fn write_out<S: AsyncWrite + Unpin>(
+    stream: &mut S,
+    out: &mut Vec<u8>,
+    client: &ClientInfo,
+) -> std::io::Result<()> {
+    if out.is_empty() {
+        return Ok(());
+    }
+    stream.write_all(out).await?;
+    metrics_prom::BYTES_OUT.inc_by(out.len() as u64);
+    client.record_out(out.len());
+    STATS.bytes_written.fetch_add(out.len() as u64, Ordering::Relaxed);
+    out.clear();
+    Ok(())
+}
@@ -0,0 +1,246 @@
+//! Memcached operations on a keyval database, shared by the text, meta and
+//! binary protocols. Writes send the keyspace events of the RESP command
+//! that does the same, e.g. `incr` sends `incrby`.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::db::keyspace::Keyspace;
+use crate::db::storage::Db;
+use crate::db::value::ValueEntry;
+use crate::server::{notify, shutdown};
+
+/// Exptimes above this many seconds are absolute unix timestamps.
+const RELATIVE_EXPTIME_MAX: i64 = 60 * 60 * 24 * 30;
+
+pub struct Item {
+    pub value: String,
+    pub flags: u32,
+    pub cas: u64,
+    /// Seconds left to live, `-1` when the item does not expire.
+    pub ttl: i64,
+}
+
+impl Item {
+    fn from_entry(entry: &ValueEntry, now: Instant) -> Self {
+        Item {
+            value: entry.value.clone(),
+            flags: entry.flags,
+            cas: entry.cas,
+            ttl: entry
+                .expire_at
+                .map_or(-1, |exp| exp.saturating_duration_since(now).as_secs() as i64),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoreMode {
+    Set,
+    Add,
+    Replace,
+    Append,
+    Prepend,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoreResult {
+    Stored(u64),
+    NotStored,
+    /// The CAS unique did not match.
+    Exists,
+    /// A CAS store found no item.
+    NotFound,
+}
+
+pub enum DeltaResult {
+    Value(u64, u64),
+    NotFound,
+    NonNumeric,
+}
+
+/// Converts a memcached exptime into an expiry instant. Zero means never;
+/// negative or past times expire the item right away.
+pub fn expire_at(exptime: i64) -> Option<Instant> {
+    let now = Instant::now();
+    let secs = if exptime > RELATIVE_EXPTIME_MAX {
+        let unix_now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64);
+        exptime - unix_now
+    } else {
+        exptime
+    };
+
+    match secs {
+        0 if exptime == 0 => None,
+        s if s <= 0 => Some(now),
+        s => Some(now + Duration::from_secs(s as u64)),
+    }
+}
+
+/// Memcached clients use database 0.
+const DB: usize = 0;
+
+/// Counts `flush_all` calls, so a delayed flush can tell it was replaced.
+static FLUSH_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Looks a key up, dropping it if it has expired.
+fn live<'a>(ks: &'a mut Keyspace, key: &str) -> Option<&'a mut ValueEntry> {
+    ks.get_live(key, DB)
+}
+
+pub async fn get(db: &Db, key: &str) -> Option<Item> {
+    let now = Instant::now();
+    let mut ks = db.lock().await;
+    let entry = live(&mut ks, key)?;
+    entry.touch();
+    Some(Item::from_entry(entry, now))
+}
+
+/// Reads an item without counting it as an access, for `me`. Returns the
+/// item and its idle seconds.
+pub async fn peek(db: &Db, key: &str) -> Option<(Item, u64)> {
+    let now = Instant::now();
+    let mut ks = db.lock().await;
+    let entry = live(&mut ks, key)?;
+    Some((Item::from_entry(entry, now), entry.idle_secs()))
+}
+
+/// Fetches an item and gives it a new exptime (`gat`, `touch`).
+pub async fn get_and_touch(db: &Db, key: &str, exptime: i64) -> Option<Item> {
+    let now = Instant::now();
+    let mut ks = db.lock().await;
+    let entry = live(&mut ks, key)?;
+    entry.touch();
+    entry.expire_at = expire_at(exptime);
+    let item = Item::from_entry(entry, now);
+    notify::notify(notify::GENERIC, "expire", key, DB);
+    Some(item)
+}
+
+pub async fn store(
+    db: &Db,
+    mode: StoreMode,
+    key: &str,
+    value: String,
+    flags: u32,
+    exptime: i64,
+    cas: Option<u64>,
+) -> StoreResult {
+    let mut ks = db.lock().await;
+
+    let existing = live(&mut ks, key);
+    match (mode, existing, cas) {
+        (_, None, Some(_)) => StoreResult::NotFound,
+        (_, Some(e), Some(want)) if e.cas != want => StoreResult::Exists,
+        (StoreMode::Add, Some(_), _) => StoreResult::NotStored,
+        (StoreMode::Replace | StoreMode::Append | StoreMode::Prepend, None, _) => StoreResult::NotStored,
+        (StoreMode::Append, Some(e), _) => {
+            let mut v = std::mem::take(&mut e.value);
+            v.push_str(&value);
+            e.set_value(v);
+            let cas = e.cas;
+            notify::notify(notify::STRING, "append", key, DB);
+            StoreResult::Stored(cas)
+        }
+        (StoreMode::Prepend, Some(e), _) => {
+            let mut v = value;
+            v.push_str(&e.value);
+            e.set_value(v);
+            let cas = e.cas;
+            notify::notify(notify::STRING, "append", key, DB);
+            StoreResult::Stored(cas)
+        }
+        _ => {
+            let mut entry = ValueEntry::new(value, expire_at(exptime));
+            entry.flags = flags;
+            let (cas, expires) = (entry.cas, entry.expire_at.is_some());
+            ks.insert(key.to_string(), entry);
+            notify::notify(notify::STRING, "set", key, DB);
+            if expires {
+                notify::notify(notify::GENERIC, "expire", key, DB);
+            }
+            StoreResult::Stored(cas)
+        }
+    }
+}
+
+/// Deletes a key, only if its CAS unique matches when one is given.
+pub async fn delete(db: &Db, key: &str, cas: Option<u64>) -> StoreResult {
+    let mut ks = db.lock().await;
+    match live(&mut ks, key) {
+        None => StoreResult::NotFound,
+        Some(e) if cas.is_some_and(|c| c != e.cas) => StoreResult::Exists,
+        Some(_) => {
+            ks.remove(key);
+            notify::notify(notify::GENERIC, "del", key, DB);
+            StoreResult::Stored(0)
+        }
+    }
+}
+
+/// `incr`/`decr`. Increments wrap at 2^64 and decrements stop at zero, as in
+/// memcached. When `initial` is given a missing key is created with it.
+/// Returns the new value and CAS unique.
+pub async fn delta(db: &Db, key: &str, incr: bool, by: u64, initial: Option<(u64, i64)>) -> DeltaResult {
+    let mut ks = db.lock().await;
+    let event = if incr { "incrby" } else { "decrby" };
+
+    let entry = match live(&mut ks, key) {
+        Some(e) => e,
+        None => {
+            return match initial {
+                Some((init, exptime)) => {
+                    let entry = ValueEntry::new(init.to_string(), expire_at(exptime));
+                    let cas = entry.cas;
+                    ks.insert(key.to_string(), entry);
+                    notify::notify(notify::STRING, event, key, DB);
+                    DeltaResult::Value(init, cas)
+                }
+                None => DeltaResult::NotFound,
+            };
+        }
+    };
+
+    let current: u64 = match entry.value.trim_end().parse() {
+        Ok(n) => n,
+        Err(_) => return DeltaResult::NonNumeric,
+    };
+    let next = if incr {
+        current.wrapping_add(by)
+    } else {
+        current.saturating_sub(by)
+    };
+    entry.touch();
+    entry.set_value(next.to_string());
+    let cas = entry.cas;
+    notify::notify(notify::STRING, event, key, DB);
+    DeltaResult::Value(next, cas)
+}
+
+/// `flush_all`: drops every item now, or after `delay` seconds. As in
+/// memcached, a later `flush_all` replaces a delayed one. A delayed flush is
+/// dropped when the server stops and waits out a shutdown drain like any
+/// other command.
+pub async fn flush_all(db: &Db, delay: u64) {
+    let generation = FLUSH_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+    if delay == 0 {
+        db.lock().await.clear();
+        return;
+    }
+    let db = db.clone();
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(delay)) => {}
+            _ = shutdown::stopped() => return,
+        }
+        if FLUSH_GENERATION.load(Ordering::SeqCst) != generation {
+            return;
+        }
+        let Some(_in_flight) = shutdown::admit().await else { return };
+        if FLUSH_GENERATION.load(Ordering::SeqCst) == generation {
+            db.lock().await.clear();
+        }
+    });
+}
@@ -0,0 +1,612 @@
+//! The memcached ASCII protocol, including the meta commands
+//! (`mg`, `ms`, `md`, `ma`, `mn`, `me`).
+
+use crate::db::storage::Db;
+use crate::server::memcached::store::{self, DeltaResult, StoreMode, StoreResult};
+use crate::server::memcached::{count, feed_monitor, refused, stats, Step, MAX_ITEM_SIZE, MAX_KEY_LEN, STATS};
+use crate::server::clients::{self, ClientInfo};
+use crate::server::session::Session;
+
+/// Longest command line we wait for before dropping the client.
+const MAX_LINE: usize = 8192;
+
+const BAD_FORMAT: &str = "CLIENT_ERROR bad command line format";
+const BAD_DATA: &str = "CLIENT_ERROR bad data chunk";
+const TOO_LARGE: &str = "SERVER_ERROR object too large for cache";
+/// Values are stored as text, shared with the RESP side; binary data would
+/// not come back byte for byte, so it is refused.
+const NOT_UTF8: &str = "SERVER_ERROR value is not valid UTF-8";
+const NON_NUMERIC: &str = "CLIENT_ERROR cannot increment or decrement non-numeric value";
+const INVALID_FLAG: &str = "CLIENT_ERROR invalid flag";
+
+fn reply(out: &mut Vec<u8>, line: &str) {
+    out.extend_from_slice(line.as_bytes());
+    out.extend_from_slice(b"\r\n");
+}
+
+fn valid_key(key: &str) -> bool {
+    key.len() <= MAX_KEY_LEN && !key.bytes().any(|b| b.is_ascii_control())
+}
+
+/// The RESP commands whose permissions a request needs, with their keys.
+fn permissions<'t, 'a>(tokens: &'t [&'a str]) -> Vec<(&'static str, &'t [&'a str])> {
+    let key = tokens.get(1..2).unwrap_or(&[]);
+    let touches = tokens.iter().skip(2).any(|t| t.starts_with('T'));
+    match tokens[0] {
+        "get" | "gets" => vec![("get", &tokens[1..])],
+        "gat" | "gats" => {
+            let keys = tokens.get(2..).unwrap_or(&[]);
+            vec![("get", keys), ("expire", keys)]
+        }
+        "mg" if touches => vec![("get", key), ("expire", key)],
+        "mg" | "me" => vec![("get", key)],
+        "set" | "add" | "replace" | "append" | "prepend" | "cas" | "ms" => vec![("set", key)],
+        "delete" | "md" => vec![("del", key)],
+        "incr" | "decr" | "ma" => vec![("incr", key)],
+        "touch" => vec![("expire", key)],
+        "flush_all" => vec![("flushdb", &[])],
+        "stats" => vec![("info", &[])],
+        _ => Vec::new(),
+    }
+}
+
+/// Length of the data block after a storage command line, if it has one.
+fn data_len(tokens: &[&str]) -> Option<usize> {
+    let i = match tokens[0] {
+        "set" | "add" | "replace" | "append" | "prepend" | "cas" => 4,
+        "ms" => 2,
+        _ => return None,
+    };
+    tokens.get(i)?.parse().ok()
+}
+
+pub(crate) async fn process(
+    buf: &[u8],
+    db: &Db,
+    session: &mut Session,
+    client: &ClientInfo,
+    out: &mut Vec<u8>,
+) -> Step {
+    let lf = match memchr::memchr(b'\n', buf) {
+        Some(lf) if lf <= MAX_LINE => lf,
+        None if buf.len() <= MAX_LINE => return Step::Incomplete,
+        _ => {
+            reply(out, "CLIENT_ERROR line too long");
+            return Step::Close;
+        }
+    };
+    let next = lf + 1;
+    let line = &buf[..lf];
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+    let line = match std::str::from_utf8(line) {
+        Ok(l) => l,
+        Err(_) => {
+            reply(out, BAD_FORMAT);
+            return Step::Consumed(next);
+        }
+    };
+    let tokens: Vec<&str> = line.split_ascii_whitespace().collect();
+    let Some(&cmd) = tokens.first() else {
+        reply(out, "ERROR");
+        return Step::Consumed(next);
+    };
+    // Wait for a storage command's data block first, so the request is
+    // counted and shown to MONITOR once.
+    if data_len(&tokens).is_some_and(|len| len <= MAX_ITEM_SIZE && buf.len() < next + len + 2) {
+        return Step::Incomplete;
+    }
+    client.record_command(session, cmd);
+
+    let resp_cmds = permissions(&tokens);
+    let denied = resp_cmds
+        .iter()
+        .find_map(|(resp_cmd, keys)| refused(session, resp_cmd, keys));
+    if let Some(e) = denied {
+        reply(out, &format!("CLIENT_ERROR {}", e));
+        return match data_len(&tokens) {
+            Some(len) => Step::Swallow { consumed: next, discard: len + 2 },
+            None => Step::Consumed(next),
+        };
+    }
+    // CLIENT PAUSE holds these back as it would the RESP commands.
+    for (resp_cmd, _) in &resp_cmds {
+        clients::wait_unpaused(resp_cmd).await;
+    }
+    feed_monitor(session, &tokens);
+
+    match cmd {
+        "get" | "gets" => {
+            retrieve(&tokens[1..], cmd == "gets", None, db, out).await;
+        }
+        "gat" | "gats" => match tokens.get(1).and_then(|t| t.parse::<i64>().ok()) {
+            Some(exptime) if tokens.len() > 2 => {
+                retrieve(&tokens[2..], cmd == "gats", Some(exptime), db, out).await;
+            }
+            _ => reply(out, BAD_FORMAT),
+        },
+        "set" | "add" | "replace" | "append" | "prepend" | "cas" => {
+            return storage(&tokens, buf, next, db, out).await;
+        }
+        "delete" => delete(&tokens, db, out).await,
+        "incr" | "decr" => delta(&tokens, db, out).await,
+        "touch" => touch(&tokens, db, out).await,
+        "flush_all" => {
+            let noreply = tokens.last() == Some(&"noreply");
+            let args = &tokens[1..tokens.len() - noreply as usize];
+            match args {
+                [] => store::flush_all(db, 0).await,
+                [delay] => match delay.parse::<u64>() {
+                    Ok(d) => store::flush_all(db, d).await,
+                    Err(_) => {
+                        reply(out, BAD_FORMAT);
+                        return Step::Consumed(next);
+                    }
+                },
+                _ => {
+                    reply(out, BAD_FORMAT);
+                    return Step::Consumed(next);
+                }
+            }
+            count(&STATS.cmd_flush);
+            if !noreply {
+                reply(out, "OK");
+            }
+        }
+        "stats" if tokens.len() == 1 => {
+            for (name, value) in stats(db).await {
+                reply(out, &format!("STAT {} {}", name, value));
+            }
+            reply(out, "END");
+        }
+        "stats" => reply(out, "CLIENT_ERROR only general stats are supported"),
+        "version" => reply(out, &format!("VERSION {}", env!("CARGO_PKG_VERSION"))),
+        "verbosity" => {
+            if tokens.last() != Some(&"noreply") {
+                reply(out, "OK");
+            }
+        }
+        "quit" => return Step::Close,
+        "mg" => meta_get(&tokens, db, out).await,
+        "ms" => return meta_set(&tokens, buf, next, db, out).await,
+        "md" => meta_delete(&tokens, db, out).await,
+        "ma" => meta_arithmetic(&tokens, db, out).await,
+        "me" => meta_debug(&tokens, db, out).await,
+        "mn" => reply(out, "MN"),
+        _ => reply(out, "ERROR"),
+    }
+
+    Step::Consumed(next)
+}
+
+async fn retrieve(keys: &[&str], with_cas: bool, exptime: Option<i64>, db: &Db, out: &mut Vec<u8>) {
+    if keys.is_empty() || !keys.iter().all(|k| valid_key(k)) {
+        reply(out, BAD_FORMAT);
+        return;
+    }
+
+    for key in keys {
+        count(&STATS.cmd_get);
+        let item = match exptime {
+            Some(exptime) => {
+                count(&STATS.cmd_touch);
+                store::get_and_touch(db, key, exptime).await
+            }
+            None => store::get(db, key).await,
+        };
+        let Some(item) = item else {
+            count(&STATS.get_misses);
+            continue;
+        };
+        count(&STATS.get_hits);
+
+        let header = if with_cas {
+            format!("VALUE {} {} {} {}", key, item.flags, item.value.len(), item.cas)
+        } else {
+            format!("VALUE {} {} {}", key, item.flags, item.value.len())
+        };
+        reply(out, &header);
+        reply(out, &item.value);
+    }
+    reply(out, "END");
+}
+
+/// Reads the `<bytes>` data block that follows a storage command line.
+/// Returns the value and the total bytes used, or the step to return when
+/// the block is missing, too large, malformed or not UTF-8.
+fn data_block(buf: &[u8], next: usize, len: usize, out: &mut Vec<u8>) -> Result<(String, usize), Step> {
+    if len > MAX_ITEM_SIZE {
+        reply(out, TOO_LARGE);
+        return Err(Step::Swallow { consumed: next, discard: len + 2 });
+    }
+    let end = next + len;
+    if buf.len() < end + 2 {
+        return Err(Step::Incomplete);
+    }
+    if &buf[end..end + 2] != b"\r\n" {
+        reply(out, BAD_DATA);
+        return Err(Step::Consumed(end + 2));
+    }
+    match std::str::from_utf8(&buf[next..end]) {
+        Ok(value) => Ok((value.to_string(), end + 2)),
+        Err(_) => {
+            reply(out, NOT_UTF8);
+            Err(Step::Consumed(end + 2))
+        }
+    }
+}
+
+/// `<cmd> <key> <flags> <exptime> <bytes> [cas unique] [noreply]`
+async fn storage(tokens: &[&str], buf: &[u8], next: usize, db: &Db, out: &mut Vec<u8>) -> Step {
+    let is_cas = tokens[0] == "cas";
+    let fixed = if is_cas { 6 } else { 5 };
+    let noreply = tokens.len() == fixed + 1 && tokens[fixed] == "noreply";
+    if tokens.len() != fixed && !noreply {
+        reply(out, BAD_FORMAT);
+        return Step::Consumed(next);
+    }
+
+    let key = tokens[1];
+    let parsed = (
+        tokens[2].parse::<u32>(),
+        tokens[3].parse::<i64>(),
+        tokens[4].parse::<usize>(),
+        if is_cas { tokens[5].parse::<u64>().map(Some) } else { Ok(None) },
+    );
+    let (flags, exptime, len, cas) = match parsed {
+        (Ok(f), Ok(e), Ok(l), Ok(c)) if valid_key(key) => (f, e, l, c),
+        _ => {
+            reply(out, BAD_FORMAT);
+            return Step::Consumed(next);
+        }
+    };
+
+    let (value, used) = match data_block(buf, next, len, out) {
+        Ok(block) => block,
+        Err(step) => return step,
+    };
+
+    let mode = match tokens[0] {
+        "add" => StoreMode::Add,
+        "replace" => StoreMode::Replace,
+        "append" => StoreMode::Append,
+        "prepend" => StoreMode::Prepend,
+        _ => StoreMode::Set,
+    };
+    count(&STATS.cmd_set);
+    let result = store::store(db, mode, key, value, flags, exptime, cas).await;
+
+    if !noreply {
+        reply(
+            out,
+            match result {
+                StoreResult::Stored(_) => "STORED",
+                StoreResult::NotStored => "NOT_STORED",
+                StoreResult::Exists => "EXISTS",
+                StoreResult::NotFound => "NOT_FOUND",
+            },
+        );
+    }
+    Step::Consumed(used)
+}
+
+/// `delete <key> [noreply]`
+async fn delete(tokens: &[&str], db: &Db, out: &mut Vec<u8>) {
+    let noreply = tokens.len() == 3 && tokens[2] == "noreply";
+    if (tokens.len() != 2 && !noreply) || !valid_key(tokens[1]) {
+        reply(out, BAD_FORMAT);
+        return;
+    }
+
+    let result = store::delete(db, tokens[1], None).await;
+    if !noreply {
+        reply(out, if result == StoreResult::NotFound { "NOT_FOUND" } else { "DELETED" });
+    }
+}
+
+/// `incr|decr <key> <value> [noreply]`
+async fn delta(tokens: &[&str], db: &Db, out: &mut Vec<u8>) {
+    let noreply = tokens.len() == 4 && tokens[3] == "noreply";
+    if (tokens.len() != 3 && !noreply) || !valid_key(tokens[1]) {
+        reply(out, BAD_FORMAT);
+        return;
+    }
+    let Ok(by) = tokens[2].parse::<u64>() else {
+        reply(out, "CLIENT_ERROR invalid numeric delta argument");
+        return;
+    };
+
+    let line = match store::delta(db, tokens[1], tokens[0] == "incr", by, None).await {
+        DeltaResult::Value(n, _) => n.to_string(),
+        DeltaResult::NotFound => "NOT_FOUND".to_string(),
+        DeltaResult::NonNumeric => NON_NUMERIC.to_string(),
+    };
+    if !noreply {
+        reply(out, &line);
+    }
+}
+
+/// `touch <key> <exptime> [noreply]`
+async fn touch(tokens: &[&str], db: &Db, out: &mut Vec<u8>) {
+    let noreply = tokens.len() == 4 && tokens[3] == "noreply";
+    let exptime = tokens.get(2).and_then(|t| t.parse::<i64>().ok());
+    let Some(exptime) = exptime.filter(|_| (tokens.len() == 3 || noreply) && valid_key(tokens[1])) else {
+        reply(out, BAD_FORMAT);
+        return;
+    };
+
+    count(&STATS.cmd_touch);
+    let found = store::get_and_touch(db, tokens[1], exptime).await.is_some();
+    if !noreply {
+        reply(out, if found { "TOUCHED" } else { "NOT_FOUND" });
+    }
+}
+
+/// Splits meta flags like `v`, `T30` or `Oabc` into the flag and its token.
+fn meta_flags<'a>(tokens: &[&'a str]) -> Vec<(u8, &'a str)> {
+    tokens.iter().map(|t| (t.as_bytes()[0], &t[1..])).collect()
+}
+
+/// Writes a meta reply line: the status code and any returned flags.
+fn meta_reply(out: &mut Vec<u8>, code: &str, ret: &[String]) {
+    let mut line = code.to_string();
+    for r in ret {
+        line.push(' ');
+        line.push_str(r);
+    }
+    reply(out, &line);
+}
+
+/// Flags echoed back on every meta reply: the key with `k`, opaque with `O`.
+fn echoed(flags: &[(u8, &str)], key: &str) -> Vec<String> {
+    flags
+        .iter()
+        .filter_map(|&(f, tok)| match f {
+            b'k' => Some(format!("k{}", key)),
+            b'O' => Some(format!("O{}", tok)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// `mg <key> <flags>*`
+async fn meta_get(tokens: &[&str], db: &Db, out: &mut Vec<u8>) {
+    if tokens.len() < 2 || !valid_key(tokens[1]) {
+        reply(out, BAD_FORMAT);
+        return;
+    }
+    let key = tokens[1];
+    let flags = meta_flags(&tokens[2..]);
+
+    let mut touch = None;
+    for &(f, tok) in &flags {
+        match f {
+            b'T' => match tok.parse::<i64>() {
+                Ok(t) => touch = Some(t),
+                Err(_) => return reply(out, BAD_FORMAT),
+            },
+            b'v' | b'f' | b'c' | b't' | b's' | b'k' | b'O' | b'q' => {}
+            _ => return reply(out, INVALID_FLAG),
+        }
+    }
+    let quiet = flags.iter().any(|&(f, _)| f == b'q');
+
+    count(&STATS.cmd_get);
+    let item = match touch {
+        Some(exptime) => {
+            count(&STATS.cmd_touch);
+            store::get_and_touch(db, key, exptime).await
+        }
+        None => store::get(db, key).await,
+    };
+    let Some(item) = item else {
+        count(&STATS.get_misses);
+        if !quiet {
+            meta_reply(out, "EN", &[]);
+        }
+        return;
+    };
+    count(&STATS.get_hits);
+
+    let mut ret = Vec::new();
+    for &(f, _) in &flags {
+        match f {
+            b'f' => ret.push(format!("f{}", item.flags)),
+            b'c' => ret.push(format!("c{}", item.cas)),
+            b't' => ret.push(format!("t{}", item.ttl)),
+            b's' => ret.push(format!("s{}", item.value.len())),
+            _ => {}
+        }
+    }
+    ret.extend(echoed(&flags, key));
+
+    if flags.iter().any(|&(f, _)| f == b'v') {
+        meta_reply(out, &format!("VA {}", item.value.len()), &ret);
+        reply(out, &item.value);
+    } else {
+        meta_reply(out, "HD", &ret);
+    }
+}
+
+/// `ms <key> <datalen> <flags>*`
+async fn meta_set(tokens: &[&str], buf: &[u8], next: usize, db: &Db, out: &mut Vec<u8>) -> Step {
+    let len = tokens.get(2).and_then(|t| t.parse::<usize>().ok());
+    let Some(len) = len.filter(|_| valid_key(tokens[1])) else {
+        reply(out, BAD_FORMAT);
+        return Step::Consumed(next);
+    };
+    let key = tokens[1];
+    let flags = meta_flags(&tokens[3..]);
+
+    let (value, used) = match data_block(buf, next, len, out) {
+        Ok(block) => block,
+        Err(step) => return step,
+    };
+
+    let mut client_flags = 0u32;
+    let mut exptime = 0i64;
+    let mut cas = None;
+    let mut mode = StoreMode::Set;
+    for &(f, tok) in &flags {
+        let ok = match f {
+            b'F' => tok.parse().map(|v| client_flags = v).is_ok(),
+            b'T' => tok.parse().map(|v| exptime = v).is_ok(),
+            b'C' => tok.parse().map(|v| cas = Some(v)).is_ok(),
+            b'M' => {
+                mode = match tok {
+                    "S" | "s" => StoreMode::Set,
+                    "E" | "e" => StoreMode::Add,
+                    "A" | "a" => StoreMode::Append,
+                    "P" | "p" => StoreMode::Prepend,
+                    "R" | "r" => StoreMode::Replace,
+                    _ => {
+                        reply(out, "CLIENT_ERROR invalid mode for ms");
+                        return Step::Consumed(used);
+                    }
+                };
+                true
+            }
+            b'q' | b'k' | b'O' | b'c' => true,
+            _ => {
+                reply(out, INVALID_FLAG);
+                return Step::Consumed(used);
+            }
+        };
+        if !ok {
+            reply(out, BAD_FORMAT);
+            return Step::Consumed(used);
+        }
+    }
+    let quiet = flags.iter().any(|&(f, _)| f == b'q');
+
+    count(&STATS.cmd_set);
+    let result = store::store(db, mode, key, value, client_flags, exptime, cas).await;
+
+    let mut ret = Vec::new();
+    if let StoreResult::Stored(new_cas) = result {
+        if flags.iter().any(|&(f, _)| f == b'c') {
+            ret.push(format!("c{}", new_cas));
+        }
+    }
+    ret.extend(echoed(&flags, key));
+
+    match result {
+        StoreResult::Stored(_) if quiet => {}
+        StoreResult::Stored(_) => meta_reply(out, "HD", &ret),
+        StoreResult::NotStored => meta_reply(out, "NS", &ret),
+        StoreResult::Exists => meta_reply(out, "EX", &ret),
+        StoreResult::NotFound => meta_reply(out, "NF", &ret),
+    }
+    Step::Consumed(used)
+}
+
+/// `md <key> <flags>*`
+async fn meta_delete(tokens: &[&str], db: &Db, out: &mut Vec<u8>) {
+    if tokens.len() < 2 || !valid_key(tokens[1]) {
+        reply(out, BAD_FORMAT);
+        return;
+    }
+    let key = tokens[1];
+    let flags = meta_flags(&tokens[2..]);
+
+    let mut cas = None;
+    for &(f, tok) in &flags {
+        match f {
+            b'C' => match tok.parse::<u64>() {
+                Ok(c) => cas = Some(c),
+                Err(_) => return reply(out, BAD_FORMAT),
+            },
+            b'q' | b'k' | b'O' => {}
+            _ => return reply(out, INVALID_FLAG),
+        }
+    }
+    let quiet = flags.iter().any(|&(f, _)| f == b'q');
+
+    let ret = echoed(&flags, key);
+    match store::delete(db, key, cas).await {
+        StoreResult::Exists => meta_reply(out, "EX", &ret),
+        _ if quiet => {}
+        StoreResult::NotFound => meta_reply(out, "NF", &ret),
+        _ => meta_reply(out, "HD", &ret),
+    }
+}
+
+/// `ma <key> <flags>*`
+async fn meta_arithmetic(tokens: &[&str], db: &Db, out: &mut Vec<u8>) {
+    if tokens.len() < 2 || !valid_key(tokens[1]) {
+        reply(out, BAD_FORMAT);
+        return;
+    }
+    let key = tokens[1];
+    let flags = meta_flags(&tokens[2..]);
+
+    let mut incr = true;
+    let mut by = 1u64;
+    let mut initial = 0u64;
+    let mut autoviv = None;
+    for &(f, tok) in &flags {
+        let ok = match f {
+            b'D' => tok.parse().map(|v| by = v).is_ok(),
+            b'J' => tok.parse().map(|v| initial = v).is_ok(),
+            b'N' => tok.parse::<i64>().map(|v| autoviv = Some(v)).is_ok(),
+            b'M' => {
+                incr = match tok {
+                    "I" | "i" | "+" => true,
+                    "D" | "d" | "-" => false,
+                    _ => return reply(out, "CLIENT_ERROR invalid mode for ma"),
+                };
+                true
+            }
+            b'q' | b'k' | b'O' | b'c' | b'v' => true,
+            _ => return reply(out, INVALID_FLAG),
+        };
+        if !ok {
+            return reply(out, BAD_FORMAT);
+        }
+    }
+    let quiet = flags.iter().any(|&(f, _)| f == b'q');
+
+    let initial = autoviv.map(|exptime| (initial, exptime));
+    let ret = echoed(&flags, key);
+    match store::delta(db, key, incr, by, initial).await {
+        DeltaResult::Value(n, cas) => {
+            let mut ret_all = Vec::new();
+            if flags.iter().any(|&(f, _)| f == b'c') {
+                ret_all.push(format!("c{}", cas));
+            }
+            ret_all.extend(ret);
+
+            if flags.iter().any(|&(f, _)| f == b'v') {
+                let n = n.to_string();
+                meta_reply(out, &format!("VA {}", n.len()), &ret_all);
+                reply(out, &n);
+            } else if !quiet {
+                meta_reply(out, "HD", &ret_all);
+            }
+        }
+        DeltaResult::NotFound => meta_reply(out, "NF", &ret),
+        DeltaResult::NonNumeric => reply(out, NON_NUMERIC),
+    }
+}
+
+/// `me <key>`: item metadata, for debugging.
+async fn meta_debug(tokens: &[&str], db: &Db, out: &mut Vec<u8>) {
+    if tokens.len() != 2 || !valid_key(tokens[1]) {
+        reply(out, BAD_FORMAT);
+        return;
+    }
+    match store::peek(db, tokens[1]).await {
+        Some((item, idle)) => reply(
+            out,
+            &format!(
+                "ME {} exp={} la={} cas={} fetch=no cls=1 size={}",
+                tokens[1],
+                item.ttl,
+                idle,
+                item.cas,
+                item.value.len()
+            ),
+        ),
+        None => reply(out, "EN"),
+    }
+}
@@ -1,5 +1,5 @@
 use once_cell::sync::Lazy;
-use prometheus::{Counter, Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder};
+use prometheus::{exponential_buckets, Counter, Encoder, Histogram, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder};
 
 pub static REGISTRY: Lazy<Registry> = Lazy::new(Registry::new);
 
@@ -10,12 +10,26 @@ pub static ACTIVE_CONNS: Lazy<IntGauge> = Lazy::new(|| {
     g
 });
 
-pub static KEYS_COUNT: Lazy<IntGauge> = Lazy::new(|| {
-    let g = IntGauge::with_opts(Opts::new("keyval_keys_count", "Number of keys in DB")).unwrap();
+pub static KEYS_COUNT: Lazy<IntGaugeVec> = Lazy::new(|| {
+    let g = IntGaugeVec::new(
+        Opts::new("keyval_keys_count", "Number of keys per logical DB"),
+        &["db"],
+    )
+        .unwrap();
     REGISTRY.register(Box::new(g.clone())).unwrap();
     g
 });
 
+/// The `cmd` label for an upper-case command name: the name of a command the
+/// server has, `unknown` for anything else, so clients cannot add labels.
+pub fn command_label(cmd: &str) -> &str {
+    if crate::server::acl::is_command(&cmd.to_ascii_lowercase()) {
+        cmd
+    } else {
+        "unknown"
+    }
+}
+
 pub static CMD_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
     let c = IntCounterVec::new(
         Opts::new("keyval_cmd_total", "Total commands processed"),
@@ -36,6 +50,56 @@ pub static CMD_LATENCY: Lazy<HistogramVec> = Lazy::new(|| {
     h
 });
 
+pub static LOCK_WAIT: Lazy<Histogram> = Lazy::new(|| {
+    let h = Histogram::with_opts(
+        HistogramOpts::new(
+            "keyval_lock_wait_seconds",
+            "Time spent waiting for a database lock",
+        )
+            .buckets(exponential_buckets(0.000001, 4.0, 11).unwrap()),
+    )
+        .unwrap();
+    REGISTRY.register(Box::new(h.clone())).unwrap();
+    h
+});
+
+pub static CMD_REPLY_BYTES: Lazy<HistogramVec> = Lazy::new(|| {
+    let h = HistogramVec::new(
+        HistogramOpts::new("keyval_cmd_reply_bytes", "Encoded reply size in bytes")
+            .buckets(exponential_buckets(16.0, 4.0, 10).unwrap()),
+        &["cmd"],
+    )
+        .unwrap();
+    REGISTRY.register(Box::new(h.clone())).unwrap();
+    h
+});
+
+pub static PROTOCOL_REJECTIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    let c = IntCounterVec::new(
+        Opts::new(
+            "keyval_protocol_rejections_total",
+            "Requests rejected for violating protocol limits or syntax",
+        ),
+        &["reason"],
+    )
+        .unwrap();
+    REGISTRY.register(Box::new(c.clone())).unwrap();
+    c
+});
+
+pub static OUTPUT_LIMIT_DISCONNECTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    let c = IntCounterVec::new(
+        Opts::new(
+            "keyval_output_limit_disconnects_total",
+            "Clients disconnected for exceeding their output buffer limit",
+        ),
+        &["class"],
+    )
+        .unwrap();
+    REGISTRY.register(Box::new(c.clone())).unwrap();
+    c
+});
+
 pub static BYTES_IN: Lazy<prometheus::IntCounter> = Lazy::new(|| {
     let c = prometheus::IntCounter::new("keyval_bytes_in_total", "Total bytes read").unwrap();
     REGISTRY.register(Box::new(c.clone())).unwrap();
@@ -48,6 +112,26 @@ pub static BYTES_OUT: Lazy<prometheus::IntCounter> = Lazy::new(|| {
     c
 });
 
+pub static NET_INPUT_BYTES_PER_SEC: Lazy<IntGauge> = Lazy::new(|| {
+    let g = IntGauge::new(
+        "keyval_net_input_bytes_per_second",
+        "Bytes read per second, averaged over the last few seconds",
+    )
+        .unwrap();
+    REGISTRY.register(Box::new(g.clone())).unwrap();
+    g
+});
+
+pub static NET_OUTPUT_BYTES_PER_SEC: Lazy<IntGauge> = Lazy::new(|| {
+    let g = IntGauge::new(
+        "keyval_net_output_bytes_per_second",
+        "Bytes written per second, averaged over the last few seconds",
+    )
+        .unwrap();
+    REGISTRY.register(Box::new(g.clone())).unwrap();
+    g
+});
+
 pub static PROCESS_RSS_BYTES: Lazy<prometheus::IntGauge> = Lazy::new(|| {
     let g = prometheus::IntGauge::new(
         "keyval_process_resident_memory_bytes",
@@ -67,6 +151,16 @@ pub static PROCESS_CPU_SECONDS_TOTAL: Lazy<Counter> = Lazy::new(|| {
     c
 });
 
+pub static TLS_HANDSHAKE_FAILURES: Lazy<prometheus::IntCounter> = Lazy::new(|| {
+    let c = prometheus::IntCounter::new(
+        "keyval_tls_handshake_failures_total",
+        "TLS handshakes that failed or timed out",
+    )
+        .unwrap();
+    REGISTRY.register(Box::new(c.clone())).unwrap();
+    c
+});
+
 pub fn gather() -> Vec<u8> {
     let metric_families = REGISTRY.gather();
     let mut out = Vec::new();
@@ -74,3 +168,49 @@ pub fn gather() -> Vec<u8> {
     encoder.encode(&metric_families, &mut out).unwrap();
     out
 }
+
+pub static REJECTED_CONNECTIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    let c = IntCounterVec::new(
+        Opts::new(
+            "keyval_rejected_connections_total",
+            "Connections refused by maxclients, maxclients-per-ip or the WebSocket origin check",
+        ),
+        &["reason"],
+    )
+        .unwrap();
+    REGISTRY.register(Box::new(c.clone())).unwrap();
+    c
+});
+
+pub static IDLE_TIMEOUTS: Lazy<prometheus::IntCounter> = Lazy::new(|| {
+    let c = prometheus::IntCounter::new(
+        "keyval_idle_timeouts_total",
+        "Clients closed after being idle longer than the timeout",
+    )
+        .unwrap();
+    REGISTRY.register(Box::new(c.clone())).unwrap();
+    c
+});
+
+pub static ACCEPT_ERRORS: Lazy<prometheus::IntCounter> = Lazy::new(|| {
+    let c = prometheus::IntCounter::new(
+        "keyval_accept_errors_total",
+        "Failed accept() calls, e.g. when out of file descriptors",
+    )
+        .unwrap();
+    REGISTRY.register(Box::new(c.clone())).unwrap();
+    c
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_commands_share_one_label() {
+        assert_eq!(command_label("GET"), "GET");
+        assert_eq!(command_label("CLIENT"), "CLIENT");
+        assert_eq!(command_label("NOSUCHCMD"), "unknown");
+        assert_eq!(command_label("GET X\r\n"), "unknown");
+    }
+}
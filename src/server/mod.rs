@@ -1,6 +1,21 @@
+pub mod config;
+pub mod shutdown;
 pub mod tcp_server;
+pub mod tls;
 pub mod connection;
+pub mod session;
+pub mod acl;
+pub mod clients;
+pub mod output;
+pub mod pubsub;
+pub mod notify;
+pub mod memcached;
 pub mod metrics_prom;
+pub mod stats;
+pub mod slowlog;
+pub mod monitor;
+pub mod latency;
 pub mod http_metrics;
-pub mod linux_mem;
+pub mod http_api;
+pub mod websocket;
 pub mod linux_proc;
@@ -0,0 +1,101 @@
+//! MONITOR: a live feed of the commands every client runs, one line each,
+//! in Redis' format. Nothing is formatted while no one is watching.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use once_cell::sync::Lazy;
+use tokio::sync::broadcast;
+
+use crate::server::session::Session;
+use crate::server::{acl, clients};
+
+/// Lines a watcher may fall behind by before it is dropped.
+const FEED_CAPACITY: usize = 4096;
+
+static FEED: Lazy<broadcast::Sender<String>> = Lazy::new(|| broadcast::channel(FEED_CAPACITY).0);
+static WATCHERS: AtomicUsize = AtomicUsize::new(0);
+
+/// A connection in MONITOR mode. Dropping it stops the feed for it.
+pub struct Watcher {
+    rx: broadcast::Receiver<String>,
+}
+
+impl Watcher {
+    /// The next line; `None` if this watcher fell too far behind.
+    pub async fn next(&mut self) -> Option<String> {
+        // The sender lives in a static, so the channel never closes.
+        self.rx.recv().await.ok()
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        WATCHERS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+pub fn watch() -> Watcher {
+    WATCHERS.fetch_add(1, Ordering::Relaxed);
+    Watcher {
+        rx: FEED.subscribe(),
+    }
+}
+
+/// Whether anyone is watching; checked before each command.
+pub fn active() -> bool {
+    WATCHERS.load(Ordering::Relaxed) > 0
+}
+
+/// Sends a command to the watchers, e.g.
+/// `1760000000.123456 [0 127.0.0.1:51234] "set" "k" "v"`. Admin commands
+/// are left out, as in Redis, so CONFIG SET and ACL SETUSER cannot leak
+/// passwords; AUTH and HELLO credentials are redacted.
+pub fn feed(parts: &[String], session: &Session) {
+    if acl::in_category(&parts[0].to_lowercase(), "admin") {
+        return;
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    // HTTP requests are not registered clients.
+    let addr = clients::get(session.id).map_or_else(|| "http".to_string(), |c| c.addr.clone());
+
+    let mut line = format!(
+        "{}.{:06} [{} {}]",
+        now.as_secs(),
+        now.subsec_micros(),
+        session.db,
+        addr
+    );
+    for (i, arg) in parts.iter().enumerate() {
+        line.push(' ');
+        if acl::is_secret_arg(parts, i) {
+            line.push_str("\"(redacted)\"");
+        } else {
+            quote(&mut line, arg);
+        }
+    }
+    let _ = FEED.send(line);
+}
+
+/// Appends `arg` in double quotes with Redis' escapes, so the line stays
+/// on one line whatever the argument holds.
+fn quote(out: &mut String, arg: &str) {
+    out.push('"');
+    for c in arg.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{7}' => out.push_str("\\a"),
+            '\u{8}' => out.push_str("\\b"),
+            c if c.is_control() => out.push_str(&format!("\\x{:02x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
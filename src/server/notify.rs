@@ -0,0 +1,62 @@
+//! Keyspace notifications, published through `pubsub` on
+//! `__keyspace@<db>__:<key>` and `__keyevent@<db>__:<event>` like Redis'
+//! `notify-keyspace-events`. Off until some flags are set.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use crate::server::pubsub;
+
+/// `K`: publish on `__keyspace@<db>__:<key>` with the event as payload.
+pub const KEYSPACE: u32 = 1 << 0;
+/// `E`: publish on `__keyevent@<db>__:<event>` with the key as payload.
+pub const KEYEVENT: u32 = 1 << 1;
+/// `g`: generic events such as `del`, `expire` and `rename_from`.
+pub const GENERIC: u32 = 1 << 2;
+/// `$`: string events such as `set` and `incrby`.
+pub const STRING: u32 = 1 << 3;
+/// `x`: keys removed once their TTL ran out.
+pub const EXPIRED: u32 = 1 << 4;
+/// `A`: every event class.
+pub const ALL: u32 = GENERIC | STRING | EXPIRED;
+
+static FLAGS: AtomicU32 = AtomicU32::new(0);
+
+/// Parses a Redis-style class string such as `KEA` or `Kx`. The empty string
+/// turns notifications off.
+pub fn parse_flags(spec: &str) -> Result<u32, String> {
+    let mut flags = 0;
+    for c in spec.chars() {
+        flags |= match c {
+            'K' => KEYSPACE,
+            'E' => KEYEVENT,
+            'g' => GENERIC,
+            '$' => STRING,
+            'x' => EXPIRED,
+            'A' => ALL,
+            _ => return Err(format!("unsupported keyspace event class '{}'", c)),
+        };
+    }
+    Ok(flags)
+}
+
+pub fn set_flags(flags: u32) {
+    FLAGS.store(flags, Ordering::Relaxed);
+}
+
+pub fn flags() -> u32 {
+    FLAGS.load(Ordering::Relaxed)
+}
+
+/// Publishes `event` on `key` of database `db` if `class` is enabled.
+pub fn notify(class: u32, event: &str, key: &str, db: usize) {
+    let flags = flags();
+    if flags & class == 0 {
+        return;
+    }
+    if flags & KEYSPACE != 0 {
+        pubsub::publish(&format!("__keyspace@{}__:{}", db, key), event);
+    }
+    if flags & KEYEVENT != 0 {
+        pubsub::publish(&format!("__keyevent@{}__:{}", db, event), key);
+    }
+}
@@ -0,0 +1,329 @@
+use std::io;
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+/// Replies are written out once this much is pending, even mid-batch, so a
+/// long pipeline does not accumulate all of its replies in memory.
+const FLUSH_THRESHOLD: usize = 64 * 1024;
+
+/// Which `client-output-buffer-limit` class a connection falls into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientClass {
+    Normal,
+    Pubsub,
+    Replica,
+}
+
+impl ClientClass {
+    pub fn name(self) -> &'static str {
+        match self {
+            ClientClass::Normal => "normal",
+            ClientClass::Pubsub => "pubsub",
+            ClientClass::Replica => "replica",
+        }
+    }
+}
+
+/// Limits for one class. Zero disables a limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClassLimit {
+    /// Disconnect as soon as this many bytes are pending.
+    pub hard: usize,
+    /// Disconnect when at least this many bytes stay pending...
+    pub soft: usize,
+    /// ...for this long.
+    pub soft_seconds: u64,
+}
+
+impl ClassLimit {
+    const UNLIMITED: ClassLimit = ClassLimit { hard: 0, soft: 0, soft_seconds: 0 };
+}
+
+/// Per-class output buffer limits, as in Redis' `client-output-buffer-limit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputLimits {
+    pub normal: ClassLimit,
+    pub pubsub: ClassLimit,
+    pub replica: ClassLimit,
+}
+
+impl Default for OutputLimits {
+    fn default() -> Self {
+        const MB: usize = 1024 * 1024;
+        OutputLimits {
+            normal: ClassLimit::UNLIMITED,
+            pubsub: ClassLimit { hard: 32 * MB, soft: 8 * MB, soft_seconds: 60 },
+            replica: ClassLimit { hard: 256 * MB, soft: 64 * MB, soft_seconds: 60 },
+        }
+    }
+}
+
+impl OutputLimits {
+    pub fn for_class(&self, class: ClientClass) -> ClassLimit {
+        match class {
+            ClientClass::Normal => self.normal,
+            ClientClass::Pubsub => self.pubsub,
+            ClientClass::Replica => self.replica,
+        }
+    }
+
+    /// The limits in the format `parse` reads, as CONFIG GET shows them.
+    pub fn to_spec(&self) -> String {
+        [("normal", self.normal), ("pubsub", self.pubsub), ("replica", self.replica)]
+            .iter()
+            .map(|(name, l)| format!("{} {} {} {}", name, l.hard, l.soft, l.soft_seconds))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Parses Redis' format, e.g. `normal 0 0 0 pubsub 32mb 8mb 60`.
+    /// Classes that are not mentioned keep their current limits.
+    pub fn parse(&self, spec: &str) -> Result<OutputLimits, String> {
+        let words: Vec<&str> = spec.split_whitespace().collect();
+        if !words.len().is_multiple_of(4) {
+            return Err("wrong number of arguments in buffer limit configuration".into());
+        }
+
+        let mut out = *self;
+        for chunk in words.chunks(4) {
+            let limit = ClassLimit {
+                hard: parse_memory(chunk[1])?,
+                soft: parse_memory(chunk[2])?,
+                soft_seconds: chunk[3]
+                    .parse()
+                    .map_err(|_| format!("invalid soft limit seconds '{}'", chunk[3]))?,
+            };
+            match chunk[0].to_lowercase().as_str() {
+                "normal" => out.normal = limit,
+                "pubsub" => out.pubsub = limit,
+                "replica" | "slave" => out.replica = limit,
+                other => return Err(format!("invalid client class '{}'", other)),
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Parses a byte count with an optional `k`/`kb`/`m`/`mb`/`g`/`gb` suffix.
+pub fn parse_memory(s: &str) -> Result<usize, String> {
+    const UNITS: [(&str, usize); 7] = [
+        ("gb", 1 << 30),
+        ("g", 1_000_000_000),
+        ("mb", 1 << 20),
+        ("m", 1_000_000),
+        ("kb", 1 << 10),
+        ("k", 1_000),
+        ("b", 1),
+    ];
+
+    let lower = s.to_lowercase();
+    let (digits, mul) = UNITS
+        .iter()
+        .find_map(|(suffix, mul)| lower.strip_suffix(suffix).map(|d| (d, *mul)))
+        .unwrap_or((lower.as_str(), 1));
+
+    digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(mul))
+        .ok_or_else(|| format!("invalid memory value '{}'", s))
+}
+
+#[derive(Debug)]
+pub enum FlushError {
+    Io(io::Error),
+    /// The client went over its class limit and must be disconnected.
+    LimitReached(ClientClass),
+}
+
+impl From<io::Error> for FlushError {
+    fn from(e: io::Error) -> Self {
+        FlushError::Io(e)
+    }
+}
+
+/// Pending replies for one connection. Replies are appended as commands run
+/// and written with as few syscalls as the peer allows instead of one per
+/// reply. What has been written is only dropped once the buffer drains, so
+/// a write can be interrupted (e.g. by `select!`) and resumed.
+#[derive(Default)]
+pub struct OutputBuffer {
+    buf: Vec<u8>,
+    /// Bytes at the front of `buf` already written.
+    sent: usize,
+    /// When more than the soft limit started to be pending.
+    over_soft_since: Option<Instant>,
+}
+
+impl OutputBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bytes waiting to be written.
+    pub fn len(&self) -> usize {
+        self.buf.len() - self.sent
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Appends a reply. Fails once the limits for `class` are passed.
+    pub fn push(&mut self, bytes: &[u8], class: ClientClass, limits: &OutputLimits) -> Result<(), FlushError> {
+        self.buf.extend_from_slice(bytes);
+        self.check(class, limits)
+    }
+
+    /// Checks what is pending against the hard limit, and how long it has
+    /// been above the soft limit.
+    fn check(&mut self, class: ClientClass, limits: &OutputLimits) -> Result<(), FlushError> {
+        let limit = limits.for_class(class);
+        let pending = self.len();
+        let soft_expired = if limit.soft > 0 && limit.soft_seconds > 0 && pending > limit.soft {
+            let since = *self.over_soft_since.get_or_insert_with(Instant::now);
+            since.elapsed() >= Duration::from_secs(limit.soft_seconds)
+        } else {
+            self.over_soft_since = None;
+            false
+        };
+        if soft_expired || (limit.hard > 0 && pending > limit.hard) {
+            self.clear();
+            return Err(FlushError::LimitReached(class));
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        self.buf.clear();
+        self.sent = 0;
+        self.over_soft_since = None;
+    }
+
+    /// When the client goes over its soft limit unless it reads enough
+    /// before then; `None` while it is under it.
+    pub fn soft_deadline(&self, class: ClientClass, limits: &OutputLimits) -> Option<Instant> {
+        let seconds = limits.for_class(class).soft_seconds;
+        self.over_soft_since
+            .map(|since| since + Duration::from_secs(seconds))
+    }
+
+    /// Whether enough is pending that it should be written before
+    /// processing more of the batch.
+    pub fn should_flush(&self) -> bool {
+        self.len() >= FLUSH_THRESHOLD
+    }
+
+    /// Writes as much as the peer takes in one call. Cancel-safe: if the
+    /// future is dropped before it completes, nothing was written.
+    pub async fn write_some<W: AsyncWrite + Unpin>(&mut self, w: &mut W) -> io::Result<usize> {
+        let n = w.write(&self.buf[self.sent..]).await?;
+        if n == 0 {
+            return Err(io::ErrorKind::WriteZero.into());
+        }
+        self.sent += n;
+        if self.sent == self.buf.len() {
+            self.buf.clear();
+            self.sent = 0;
+        }
+        Ok(n)
+    }
+
+    /// Writes everything pending and returns how many bytes that was. While
+    /// more than the soft limit is pending, the peer has until
+    /// `soft_seconds` after it went over to take enough of it; a client that
+    /// reads slower than that is treated as over its limit.
+    pub async fn flush<W: AsyncWrite + Unpin>(
+        &mut self,
+        w: &mut W,
+        class: ClientClass,
+        limits: &OutputLimits,
+    ) -> Result<usize, FlushError> {
+        let mut written = 0;
+        while !self.is_empty() {
+            self.check(class, limits)?;
+            let n = match self.soft_deadline(class, limits) {
+                Some(deadline) => {
+                    match tokio::time::timeout_at(deadline.into(), self.write_some(w)).await {
+                        Ok(res) => res?,
+                        Err(_) => {
+                            self.clear();
+                            return Err(FlushError::LimitReached(class));
+                        }
+                    }
+                }
+                None => self.write_some(w).await?,
+            };
+            written += n;
+        }
+        self.over_soft_since = None;
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{duplex, AsyncReadExt};
+
+    fn limits(hard: usize, soft: usize, soft_seconds: u64) -> OutputLimits {
+        OutputLimits {
+            normal: ClassLimit { hard, soft, soft_seconds },
+            ..OutputLimits::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn hard_limit_applies_while_the_peer_is_not_reading() {
+        let limits = limits(8192, 0, 0);
+        // The peer holds 1 KiB and never reads it.
+        let (mut w, _peer) = duplex(1024);
+        let mut out = OutputBuffer::new();
+        let chunk = [b'x'; 3000];
+
+        out.push(&chunk, ClientClass::Normal, &limits).unwrap();
+        let blocked = tokio::time::timeout(Duration::from_millis(50), out.flush(&mut w, ClientClass::Normal, &limits));
+        assert!(blocked.await.is_err(), "flush should block");
+        assert_eq!(out.len(), 3000 - 1024);
+
+        out.push(&chunk, ClientClass::Normal, &limits).unwrap();
+        out.push(&chunk, ClientClass::Normal, &limits).unwrap();
+        let err = out.push(&chunk, ClientClass::Normal, &limits).unwrap_err();
+        assert!(matches!(err, FlushError::LimitReached(ClientClass::Normal)));
+        assert!(out.is_empty());
+    }
+
+    #[tokio::test]
+    async fn soft_limit_disconnects_a_peer_that_stops_reading() {
+        let limits = limits(0, 2000, 1);
+        let (mut w, _peer) = duplex(1024);
+        let mut out = OutputBuffer::new();
+        out.push(&[b'x'; 4000], ClientClass::Normal, &limits).unwrap();
+
+        let flushed = tokio::time::timeout(Duration::from_secs(5), out.flush(&mut w, ClientClass::Normal, &limits));
+        let err = flushed.await.expect("soft limit should end the flush").unwrap_err();
+        assert!(matches!(err, FlushError::LimitReached(ClientClass::Normal)));
+    }
+
+    #[tokio::test]
+    async fn interrupted_flush_resumes_where_it_stopped() {
+        let limits = OutputLimits::default();
+        let (mut w, mut peer) = duplex(1024);
+        let mut out = OutputBuffer::new();
+        let data: Vec<u8> = (0..5000u32).map(|i| i as u8).collect();
+        out.push(&data, ClientClass::Normal, &limits).unwrap();
+
+        let blocked = tokio::time::timeout(Duration::from_millis(50), out.flush(&mut w, ClientClass::Normal, &limits));
+        assert!(blocked.await.is_err());
+
+        let reader = tokio::spawn(async move {
+            let mut got = vec![0u8; 5000];
+            peer.read_exact(&mut got).await.unwrap();
+            got
+        });
+        let written = out.flush(&mut w, ClientClass::Normal, &limits).await.unwrap();
+        assert_eq!(written, 5000 - 1024);
+        assert_eq!(reader.await.unwrap(), data);
+    }
+}
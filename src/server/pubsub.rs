@@ -0,0 +1,247 @@
+//! Channel and pattern subscriptions. Subscribers get messages through a
+//! queue whose size counts against the `pubsub` class of
+//! `client-output-buffer-limit`; one that falls too far behind is dropped,
+//! like a pub/sub client over its output buffer limit.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::sync::Notify;
+
+use crate::server::config;
+use crate::server::output::ClassLimit;
+use crate::util::glob::glob_match;
+
+#[derive(Debug, Clone)]
+pub struct Message {
+    /// The pattern that matched, for pattern subscriptions.
+    pub pattern: Option<String>,
+    pub channel: String,
+    pub payload: String,
+}
+
+impl Message {
+    /// What the message counts for against the output limit.
+    fn size(&self) -> usize {
+        self.pattern.as_ref().map_or(0, String::len) + self.channel.len() + self.payload.len()
+    }
+}
+
+/// Bytes queued for one subscriber and undelivered so far.
+#[derive(Default)]
+struct Backlog {
+    bytes: usize,
+    /// When `bytes` last went over the soft limit, while it still is.
+    over_soft_since: Option<Instant>,
+}
+
+impl Backlog {
+    /// Adds a message, or returns false if that puts the subscriber over its
+    /// hard limit or keeps it over the soft limit for too long.
+    fn add(&mut self, size: usize, limit: &ClassLimit) -> bool {
+        let bytes = self.bytes + size;
+        if limit.hard > 0 && bytes > limit.hard {
+            return false;
+        }
+        if limit.soft > 0 && limit.soft_seconds > 0 && bytes > limit.soft {
+            let since = *self.over_soft_since.get_or_insert_with(Instant::now);
+            if since.elapsed() > Duration::from_secs(limit.soft_seconds) {
+                return false;
+            }
+        }
+        self.bytes = bytes;
+        true
+    }
+
+    fn remove(&mut self, size: usize, limit: &ClassLimit) {
+        self.bytes -= size;
+        if self.bytes <= limit.soft {
+            self.over_soft_since = None;
+        }
+    }
+}
+
+/// One client's handle on the hub: where its messages go and how it learns
+/// it was dropped for falling behind.
+#[derive(Clone)]
+pub struct Subscriber {
+    pub id: u64,
+    tx: UnboundedSender<Message>,
+    backlog: Arc<Mutex<Backlog>>,
+    evicted: Arc<Notify>,
+}
+
+/// The receiving end of a subscriber's queue.
+pub struct Inbox {
+    rx: UnboundedReceiver<Message>,
+    backlog: Arc<Mutex<Backlog>>,
+}
+
+impl Subscriber {
+    pub fn new(id: u64) -> (Subscriber, Inbox) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let backlog = Arc::new(Mutex::new(Backlog::default()));
+        (
+            Subscriber {
+                id,
+                tx,
+                backlog: backlog.clone(),
+                evicted: Arc::new(Notify::new()),
+            },
+            Inbox { rx, backlog },
+        )
+    }
+
+    /// Completes once the hub has dropped this subscriber for a full queue.
+    pub async fn evicted(&self) {
+        self.evicted.notified().await
+    }
+}
+
+impl Inbox {
+    pub async fn recv(&mut self) -> Option<Message> {
+        let msg = self.rx.recv().await?;
+        let limit = config::with(|c| c.output_limits.pubsub);
+        self.backlog.lock().unwrap().remove(msg.size(), &limit);
+        Some(msg)
+    }
+
+    /// Bytes queued and not yet received.
+    pub fn pending(&self) -> usize {
+        self.backlog.lock().unwrap().bytes
+    }
+}
+
+type Subscribers = HashMap<String, HashMap<u64, Subscriber>>;
+
+#[derive(Default)]
+struct Hub {
+    channels: Subscribers,
+    patterns: Subscribers,
+}
+
+impl Hub {
+    fn drop_client(&mut self, id: u64) {
+        for map in [&mut self.channels, &mut self.patterns] {
+            map.retain(|_, subs| {
+                subs.remove(&id);
+                !subs.is_empty()
+            });
+        }
+    }
+}
+
+static HUB: Lazy<Mutex<Hub>> = Lazy::new(|| Mutex::new(Hub::default()));
+
+pub fn subscribe(sub: &Subscriber, channel: &str) {
+    HUB.lock()
+        .unwrap()
+        .channels
+        .entry(channel.to_string())
+        .or_default()
+        .insert(sub.id, sub.clone());
+}
+
+pub fn psubscribe(sub: &Subscriber, pattern: &str) {
+    HUB.lock()
+        .unwrap()
+        .patterns
+        .entry(pattern.to_string())
+        .or_default()
+        .insert(sub.id, sub.clone());
+}
+
+fn remove(map: &mut Subscribers, name: &str, id: u64) {
+    if let Some(subs) = map.get_mut(name) {
+        subs.remove(&id);
+        if subs.is_empty() {
+            map.remove(name);
+        }
+    }
+}
+
+pub fn unsubscribe(id: u64, channel: &str) {
+    remove(&mut HUB.lock().unwrap().channels, channel, id);
+}
+
+pub fn punsubscribe(id: u64, pattern: &str) {
+    remove(&mut HUB.lock().unwrap().patterns, pattern, id);
+}
+
+/// Drops every subscription of a client, e.g. when it disconnects.
+pub fn unsubscribe_all(id: u64) {
+    HUB.lock().unwrap().drop_client(id);
+}
+
+/// Delivers a message and returns how many subscribers received it.
+pub fn publish(channel: &str, payload: &str) -> usize {
+    let mut hub = HUB.lock().unwrap();
+    let mut received = 0;
+    let mut gone: Vec<u64> = Vec::new();
+    let limit = config::with(|c| c.output_limits.pubsub);
+
+    let mut deliver = |sub: &Subscriber, pattern: Option<&String>| {
+        let msg = Message {
+            pattern: pattern.cloned(),
+            channel: channel.to_string(),
+            payload: payload.to_string(),
+        };
+        let mut backlog = sub.backlog.lock().unwrap();
+        if !backlog.add(msg.size(), &limit) {
+            sub.evicted.notify_one();
+            gone.push(sub.id);
+            return;
+        }
+        match sub.tx.send(msg) {
+            Ok(()) => received += 1,
+            Err(e) => {
+                backlog.bytes -= e.0.size();
+                gone.push(sub.id);
+            }
+        }
+    };
+
+    if let Some(subs) = hub.channels.get(channel) {
+        for sub in subs.values() {
+            deliver(sub, None);
+        }
+    }
+    for (pattern, subs) in &hub.patterns {
+        if glob_match(pattern, channel) {
+            for sub in subs.values() {
+                deliver(sub, Some(pattern));
+            }
+        }
+    }
+
+    for id in gone {
+        hub.drop_client(id);
+    }
+    received
+}
+
+/// Channels with at least one subscriber, optionally filtered by a pattern.
+pub fn channels(pattern: Option<&str>) -> Vec<String> {
+    let hub = HUB.lock().unwrap();
+    hub.channels
+        .keys()
+        .filter(|c| pattern.is_none_or(|p| glob_match(p, c)))
+        .cloned()
+        .collect()
+}
+
+pub fn numsub(channel: &str) -> usize {
+    HUB.lock()
+        .unwrap()
+        .channels
+        .get(channel)
+        .map_or(0, |s| s.len())
+}
+
+/// Number of distinct patterns subscribed to.
+pub fn numpat() -> usize {
+    HUB.lock().unwrap().patterns.len()
+}
@@ -0,0 +1,56 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::protocol::resp::encoder::Protocol;
+use crate::server::acl;
+use crate::server::output::ClientClass;
+
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Per-connection state that commands may read or change.
+pub struct Session {
+    /// Unique, monotonically increasing connection id.
+    pub id: u64,
+    /// Index of the database selected with `SELECT`.
+    pub db: usize,
+    /// Reply protocol negotiated with `HELLO`.
+    pub protocol: Protocol,
+    /// Name set with `HELLO ... SETNAME`.
+    pub name: Option<String>,
+    /// User the connection acts as, set by AUTH or a TLS client certificate.
+    pub user: String,
+    /// Whether `user` has been authenticated. New connections are when the
+    /// default user needs no password.
+    pub authenticated: bool,
+    /// Channels and patterns subscribed to, on connections that can.
+    pub subscriptions: usize,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Session::new()
+    }
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Session {
+            id: NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed),
+            db: 0,
+            protocol: Protocol::Resp2,
+            name: None,
+            user: acl::DEFAULT_USER.into(),
+            authenticated: acl::default_authenticated(),
+            subscriptions: 0,
+        }
+    }
+
+    /// Output buffer limit class: a pub/sub client while subscribed to
+    /// anything, a normal client otherwise.
+    pub fn class(&self) -> ClientClass {
+        if self.subscriptions > 0 {
+            ClientClass::Pubsub
+        } else {
+            ClientClass::Normal
+        }
+    }
+}
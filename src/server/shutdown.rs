@@ -0,0 +1,233 @@
+//! Graceful shutdown. SHUTDOWN, SIGTERM or SIGINT move the server from
+//! `Running` to `Draining`: listeners stop accepting, new commands wait, and
+//! commands already running finish and get their replies written. Once none
+//! are left, or `shutdown-timeout` passes, the server is `Stopped` and every
+//! listener and connection closes. SHUTDOWN ABORT turns a draining server
+//! back to `Running`.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::{watch, Notify};
+
+use crate::server::config;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Running,
+    Draining,
+    Stopped,
+}
+
+static STATE: Lazy<watch::Sender<State>> = Lazy::new(|| watch::channel(State::Running).0);
+/// Commands admitted and not yet answered.
+static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+/// Woken whenever `IN_FLIGHT` drops to zero.
+static IDLE: Lazy<Notify> = Lazy::new(Notify::new);
+
+pub fn state() -> State {
+    *STATE.borrow()
+}
+
+pub fn is_draining() -> bool {
+    state() == State::Draining
+}
+
+/// Starts a shutdown, or with `now` skips the rest of a drain. Returns
+/// false if the server is already stopped.
+pub fn request(now: bool) -> bool {
+    STATE.send_if_modified(|s| match (*s, now) {
+        (State::Running, false) => {
+            *s = State::Draining;
+            true
+        }
+        (State::Running | State::Draining, true) => {
+            *s = State::Stopped;
+            true
+        }
+        _ => false,
+    })
+}
+
+/// Cancels a shutdown that is still draining. Returns false if there was none.
+pub fn abort() -> bool {
+    STATE.send_if_modified(|s| {
+        if *s == State::Draining {
+            *s = State::Running;
+            true
+        } else {
+            false
+        }
+    })
+}
+
+/// Resolves once a shutdown has been requested.
+pub async fn requested() {
+    let _ = STATE.subscribe().wait_for(|s| *s != State::Running).await;
+}
+
+/// Resolves once the server is stopped.
+pub async fn stopped() {
+    let _ = STATE.subscribe().wait_for(|s| *s == State::Stopped).await;
+}
+
+/// Waits out a drain: `Running` if it was aborted, `Stopped` if it finished.
+pub async fn outcome() -> State {
+    let mut rx = STATE.subscribe();
+    let state = match rx.wait_for(|s| *s != State::Draining).await {
+        Ok(s) => *s,
+        Err(_) => State::Stopped,
+    };
+    state
+}
+
+/// Marks a command as running until dropped; the drain waits for these.
+pub struct InFlight(());
+
+impl InFlight {
+    fn new() -> InFlight {
+        IN_FLIGHT.fetch_add(1, Ordering::SeqCst);
+        InFlight(())
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if IN_FLIGHT.fetch_sub(1, Ordering::SeqCst) == 1 {
+            IDLE.notify_waiters();
+        }
+    }
+}
+
+/// Lets a command run, holding it while a drain is in progress. `None`
+/// means the server stopped and the connection should close.
+pub async fn admit() -> Option<InFlight> {
+    loop {
+        // Count first, then look: a drain that starts in between waits for us.
+        let guard = InFlight::new();
+        match state() {
+            State::Running => return Some(guard),
+            State::Stopped => return None,
+            State::Draining => drop(guard),
+        }
+        if outcome().await == State::Stopped {
+            return None;
+        }
+    }
+}
+
+/// Drives a shutdown from request to `Stopped`, going back to waiting when a
+/// drain is aborted. Returns once the server is stopped.
+pub async fn run() {
+    loop {
+        requested().await;
+        if state() == State::Stopped {
+            return;
+        }
+
+        println!("Shutting down, waiting for running commands");
+        let timeout = Duration::from_secs(config::with(|c| c.shutdown_timeout));
+        let drained = async {
+            loop {
+                let idle = IDLE.notified();
+                if IN_FLIGHT.load(Ordering::SeqCst) == 0 {
+                    return;
+                }
+                idle.await;
+            }
+        };
+        tokio::select! {
+            _ = drained => {}
+            _ = tokio::time::sleep(timeout) => {
+                eprintln!(
+                    "Shutdown timeout reached with {} command(s) still running",
+                    IN_FLIGHT.load(Ordering::SeqCst)
+                );
+            }
+            state = outcome() => {
+                if state == State::Running {
+                    println!("Shutdown aborted");
+                    continue;
+                }
+            }
+        }
+
+        // Stopped may already be set by SHUTDOWN NOW during the drain.
+        request(true);
+        return;
+    }
+}
+
+/// Shuts down on SIGTERM or SIGINT; a second signal skips the drain.
+pub fn handle_signals() {
+    tokio::spawn(async move {
+        let mut term = signal(SignalKind::terminate()).expect("failed to install SIGTERM handler");
+        let mut int = signal(SignalKind::interrupt()).expect("failed to install SIGINT handler");
+        loop {
+            let name = tokio::select! {
+                _ = term.recv() => "SIGTERM",
+                _ = int.recv() => "SIGINT",
+            };
+            let now = state() == State::Draining;
+            println!(
+                "Received {}, {}",
+                name,
+                if now { "stopping now" } else { "shutting down" }
+            );
+            request(now);
+        }
+    });
+}
+
+/// Held by tests that change the shutdown state or need it to be `Running`.
+#[cfg(test)]
+pub(crate) static TEST_LOCK: Lazy<tokio::sync::Mutex<()>> = Lazy::new(|| tokio::sync::Mutex::new(()));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn settle() {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    // One test, since the state is process-wide.
+    #[tokio::test]
+    async fn drain_finishes_running_commands_holds_new_ones_and_can_be_aborted() {
+        let _lock = TEST_LOCK.lock().await;
+        let running = admit().await.expect("admitted while running");
+        let driver = tokio::spawn(run());
+        assert!(request(false));
+        assert_eq!(state(), State::Draining);
+
+        // New commands wait; the drain waits for the running one.
+        let held = tokio::spawn(admit());
+        settle().await;
+        assert!(!held.is_finished());
+        assert!(!driver.is_finished());
+
+        // Aborting lets the held command in and the server keeps running.
+        assert!(abort());
+        let admitted = tokio::time::timeout(Duration::from_secs(1), held).await.unwrap().unwrap();
+        assert!(admitted.is_some());
+        assert_eq!(state(), State::Running);
+        drop(admitted);
+        settle().await;
+        assert!(!driver.is_finished());
+        assert!(!abort());
+
+        // The next drain ends as soon as the running command does.
+        assert!(request(false));
+        settle().await;
+        assert!(!driver.is_finished());
+        drop(running);
+        tokio::time::timeout(Duration::from_secs(1), driver).await.unwrap().unwrap();
+        assert_eq!(state(), State::Stopped);
+        assert!(admit().await.is_none());
+        assert!(!request(false));
+
+        STATE.send_replace(State::Running);
+    }
+}
@@ -0,0 +1,108 @@
+//! The slow log: the most recent commands that ran longer than
+//! `slowlog-log-slower-than`, newest first, as in Redis. Only the time spent
+//! running the command counts, not reading it or writing the reply.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+
+use crate::server::{acl, config, stats};
+
+/// Arguments kept per entry; the last one says how many more there were.
+const MAX_ARGS: usize = 32;
+/// Bytes kept per argument.
+const MAX_ARG_LEN: usize = 128;
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub id: u64,
+    /// Unix time, in seconds, at which the command finished.
+    pub timestamp: u64,
+    pub duration_us: u64,
+    pub args: Vec<String>,
+    pub client_addr: String,
+    pub client_name: String,
+}
+
+struct SlowLog {
+    entries: VecDeque<Entry>,
+    next_id: u64,
+}
+
+static LOG: Lazy<Mutex<SlowLog>> = Lazy::new(|| {
+    Mutex::new(SlowLog {
+        entries: VecDeque::new(),
+        next_id: 0,
+    })
+});
+
+/// The arguments as an entry stores them: at most `MAX_ARGS`, each cut to
+/// `MAX_ARG_LEN` bytes, with passwords redacted.
+pub fn capture(parts: &[String]) -> Vec<String> {
+    let kept = if parts.len() > MAX_ARGS { MAX_ARGS - 1 } else { parts.len() };
+    let mut args: Vec<String> = parts[..kept]
+        .iter()
+        .enumerate()
+        .map(|(i, arg)| {
+            if acl::is_secret_arg(parts, i) {
+                return "(redacted)".to_string();
+            }
+            if arg.len() <= MAX_ARG_LEN {
+                return arg.clone();
+            }
+            let mut end = MAX_ARG_LEN;
+            while !arg.is_char_boundary(end) {
+                end -= 1;
+            }
+            format!("{}... ({} more bytes)", &arg[..end], arg.len() - end)
+        })
+        .collect();
+    if kept < parts.len() {
+        args.push(format!("... ({} more arguments)", parts.len() - kept));
+    }
+    args
+}
+
+/// Logs a command if it took longer than the threshold. The arguments are
+/// only copied once it has.
+pub fn record(parts: &[String], duration: Duration, client_addr: &str, client_name: Option<&str>) {
+    let (threshold, max_len) = config::with(|c| (c.slowlog_log_slower_than, c.slowlog_max_len));
+    let duration_us = duration.as_micros() as u64;
+    if threshold < 0 || duration_us < threshold as u64 {
+        return;
+    }
+
+    let mut log = LOG.lock().unwrap();
+    let id = log.next_id;
+    log.next_id += 1;
+    log.entries.push_front(Entry {
+        id,
+        timestamp: stats::unix_time_secs(),
+        duration_us,
+        args: capture(parts),
+        client_addr: client_addr.to_string(),
+        client_name: client_name.unwrap_or("").to_string(),
+    });
+    log.entries.truncate(max_len);
+}
+
+/// Up to `count` entries, newest first.
+pub fn get(count: usize) -> Vec<Entry> {
+    LOG.lock().unwrap().entries.iter().take(count).cloned().collect()
+}
+
+pub fn len() -> usize {
+    LOG.lock().unwrap().entries.len()
+}
+
+/// Empties the log. Ids keep counting up.
+pub fn reset() {
+    LOG.lock().unwrap().entries.clear();
+}
+
+/// Drops the oldest entries beyond `max_len`, after CONFIG SET.
+pub fn trim(max_len: usize) {
+    LOG.lock().unwrap().entries.truncate(max_len);
+}
@@ -0,0 +1,113 @@
+//! Server-wide counters for INFO that Prometheus does not already keep,
+//! plus readers for the ones it does.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use once_cell::sync::Lazy;
+use prometheus::core::Collector;
+
+use crate::server::metrics_prom;
+
+/// When the server started; forced in `main`.
+pub static STARTED: Lazy<Instant> = Lazy::new(Instant::now);
+
+pub static KEYSPACE_HITS: AtomicU64 = AtomicU64::new(0);
+pub static KEYSPACE_MISSES: AtomicU64 = AtomicU64::new(0);
+/// Keys removed once expired, by the TTL cleaner or when accessed; the same ones `expired` events are sent for.
+pub static EXPIRED_KEYS: AtomicU64 = AtomicU64::new(0);
+/// RESP and WebSocket clients accepted since startup.
+pub static TOTAL_CONNECTIONS: AtomicU64 = AtomicU64::new(0);
+/// Commands per second, averaged like the network rates; set by `main`.
+pub static OPS_PER_SEC: AtomicU64 = AtomicU64::new(0);
+
+/// Keys with a TTL and their average remaining TTL in ms, per database, as
+/// of the TTL cleaner's last pass. Counting them on demand would mean
+/// walking every key under the lock.
+static EXPIRES: Lazy<Mutex<Vec<(usize, u64)>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+pub fn count(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn get(counter: &AtomicU64) -> u64 {
+    counter.load(Ordering::Relaxed)
+}
+
+pub fn uptime_secs() -> u64 {
+    STARTED.elapsed().as_secs()
+}
+
+pub fn unix_time_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+pub fn record_expires(db: usize, expires: usize, avg_ttl_ms: u64) {
+    let mut all = EXPIRES.lock().unwrap();
+    if all.len() <= db {
+        all.resize(db + 1, (0, 0));
+    }
+    all[db] = (expires, avg_ttl_ms);
+}
+
+/// `(expires, avg_ttl_ms)` for a database, zeros before the first pass.
+pub fn expires(db: usize) -> (usize, u64) {
+    EXPIRES.lock().unwrap().get(db).copied().unwrap_or((0, 0))
+}
+
+/// Calls and total run time of one command, as in `INFO commandstats`.
+pub struct CommandStat {
+    pub name: String,
+    pub calls: u64,
+    pub usec: u64,
+}
+
+/// Per-command totals read from `CMD_TOTAL` and `CMD_LATENCY`, by name.
+pub fn command_stats() -> Vec<CommandStat> {
+    let mut stats: Vec<CommandStat> = Vec::new();
+    let label = |m: &prometheus::proto::Metric| {
+        m.get_label()
+            .iter()
+            .find(|l| l.get_name() == "cmd")
+            .map(|l| l.get_value().to_lowercase())
+    };
+
+    for family in metrics_prom::CMD_TOTAL.collect() {
+        for m in family.get_metric() {
+            if let Some(name) = label(m) {
+                stats.push(CommandStat {
+                    name,
+                    calls: m.get_counter().get_value() as u64,
+                    usec: 0,
+                });
+            }
+        }
+    }
+    for family in metrics_prom::CMD_LATENCY.collect() {
+        for m in family.get_metric() {
+            let Some(name) = label(m) else { continue };
+            if let Some(s) = stats.iter_mut().find(|s| s.name == name) {
+                s.usec = (m.get_histogram().get_sample_sum() * 1e6) as u64;
+            }
+        }
+    }
+    stats.sort_by(|a, b| a.name.cmp(&b.name));
+    stats
+}
+
+pub fn total_commands() -> u64 {
+    command_stats().iter().map(|s| s.calls).sum()
+}
+
+/// Connections refused for any reason.
+pub fn rejected_connections() -> u64 {
+    metrics_prom::REJECTED_CONNECTIONS
+        .collect()
+        .iter()
+        .flat_map(|f| f.get_metric())
+        .map(|m| m.get_counter().get_value() as u64)
+        .sum()
+}
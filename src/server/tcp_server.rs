@@ -1,19 +1,305 @@
-use tokio::net::TcpListener;
+use std::collections::HashMap;
+use std::io;
+use std::net::IpAddr;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use crate::db::storage::Db;
-use crate::server::connection;
+use once_cell::sync::Lazy;
+use socket2::{SockRef, TcpKeepalive};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UnixListener};
 
-pub async fn start(addr: &str, db: Db) {
-    let listener: TcpListener = TcpListener::bind(addr).await.unwrap();
+use crate::db::storage::Databases;
+use crate::protocol::resp::encoder::RespValue;
+use crate::protocol::resp::parser::ProtocolLimits;
+use crate::server::clients;
+use crate::server::config;
+use crate::server::connection::{self, Peer};
+use crate::server::metrics_prom;
+use crate::server::shutdown;
+use crate::server::output::OutputLimits;
+use crate::server::tls::{self, Tls};
 
-    println!("Server running on {}", addr);
+/// How long a client gets to finish the TLS handshake.
+pub(crate) const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long we try to tell a refused client why before dropping it.
+pub(crate) const REFUSE_TIMEOUT: Duration = Duration::from_secs(1);
+/// Pause after the first failed accept; it doubles on each failure in a row.
+pub(crate) const ACCEPT_BACKOFF_MIN: Duration = Duration::from_millis(5);
+const ACCEPT_BACKOFF_MAX: Duration = Duration::from_secs(1);
 
-    loop {
-        let (stream, _) = listener.accept().await.unwrap();
-        let db_clone: Db = db.clone();
+/// A Unix domain socket to accept clients on, next to or instead of TCP.
+#[derive(Debug, Clone)]
+pub struct UnixSocket {
+    pub path: PathBuf,
+    /// Mode bits for the socket file, e.g. `0o700`; the umask applies if unset.
+    pub perm: Option<u32>,
+}
+
+/// The addresses clients can reach us on, as reported by INFO.
+#[derive(Debug, Clone, Default)]
+pub struct Listening {
+    pub tcp: Option<String>,
+    pub unix: Option<UnixSocket>,
+}
+
+static LISTENING: Lazy<Mutex<Listening>> = Lazy::new(|| Mutex::new(Listening::default()));
+
+pub fn listening() -> Listening {
+    LISTENING.lock().unwrap().clone()
+}
+
+/// Open connections per client IP, for `maxclients-per-ip`.
+static PER_IP: Lazy<Mutex<HashMap<IpAddr, usize>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// A client's place in the per-IP count, given back when it disconnects.
+struct IpSlot(IpAddr);
+
+impl Drop for IpSlot {
+    fn drop(&mut self) {
+        let mut counts = PER_IP.lock().unwrap();
+        if let Some(n) = counts.get_mut(&self.0) {
+            *n -= 1;
+            if *n == 0 {
+                counts.remove(&self.0);
+            }
+        }
+    }
+}
+
+/// The slots an admitted client holds until it disconnects.
+pub(crate) struct Slots {
+    _client: clients::Slot,
+    _ip: Option<IpSlot>,
+}
+
+/// Checks `maxclients` and `maxclients-per-ip` for a new client. Unix
+/// clients have no IP and only count towards `maxclients`.
+pub(crate) fn admit(ip: Option<IpAddr>) -> Result<Slots, &'static str> {
+    let Some(client) = clients::reserve() else {
+        metrics_prom::REJECTED_CONNECTIONS
+            .with_label_values(&["maxclients"])
+            .inc();
+        return Err("ERR max number of clients reached");
+    };
+    let Some(ip) = ip else {
+        return Ok(Slots { _client: client, _ip: None });
+    };
+    let per_ip = config::with(|c| c.maxclients_per_ip);
+
+    let mut counts = PER_IP.lock().unwrap();
+    let n = counts.entry(ip).or_insert(0);
+    if per_ip > 0 && *n >= per_ip {
+        metrics_prom::REJECTED_CONNECTIONS
+            .with_label_values(&["maxclients_per_ip"])
+            .inc();
+        return Err("ERR max number of clients per IP reached");
+    }
+    *n += 1;
+    Ok(Slots {
+        _client: client,
+        _ip: Some(IpSlot(ip)),
+    })
+}
+
+/// Tells a refused client why, then closes, as Redis does.
+async fn refuse<S: AsyncWrite + Unpin>(mut stream: S, reason: &str) {
+    let reply = RespValue::Error(reason.into()).to_bytes();
+    let _ = tokio::time::timeout(REFUSE_TIMEOUT, async {
+        stream.write_all(&reply).await?;
+        stream.shutdown().await
+    })
+    .await;
+}
+
+/// Probes after `tcp-keepalive` idle seconds, then every third of that,
+/// giving up after three, like Redis.
+pub(crate) fn set_keepalive(stream: &TcpStream) {
+    let secs = config::with(|c| c.tcp_keepalive);
+    if secs == 0 {
+        return;
+    }
+    let keepalive = TcpKeepalive::new()
+        .with_time(Duration::from_secs(secs))
+        .with_interval(Duration::from_secs((secs / 3).max(1)))
+        .with_retries(3);
+    let _ = SockRef::from(stream).set_tcp_keepalive(&keepalive);
+}
+
+/// Waits before accepting again. Errors like EMFILE usually pass once some
+/// clients disconnect, so they must not bring the listener down.
+pub(crate) async fn accept_failed(e: io::Error, backoff: &mut Duration) {
+    metrics_prom::ACCEPT_ERRORS.inc();
+    eprintln!("accept failed, retrying in {:?}: {}", backoff, e);
+    tokio::time::sleep(*backoff).await;
+    *backoff = (*backoff * 2).min(ACCEPT_BACKOFF_MAX);
+}
+
+/// Binds the socket, replacing a stale file left by an earlier run.
+fn bind_unix(socket: &UnixSocket) -> io::Result<UnixListener> {
+    match std::fs::remove_file(&socket.path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let listener = UnixListener::bind(&socket.path)?;
+    if let Some(perm) = socket.perm {
+        std::fs::set_permissions(&socket.path, std::fs::Permissions::from_mode(perm))?;
+    }
+    Ok(listener)
+}
+
+/// Runs a TCP client, after the TLS handshake when TLS is on.
+async fn serve_tcp_client(
+    stream: TcpStream,
+    peer: Peer,
+    admission: Result<Slots, &'static str>,
+    tls: Option<Arc<Tls>>,
+    dbs: Databases,
+    limits: ProtocolLimits,
+    output_limits: OutputLimits,
+) {
+    let Some(tls) = tls else {
+        match admission {
+            Ok(_slot) => connection::handle(stream, peer, dbs, limits, output_limits).await,
+            Err(reason) => refuse(stream, reason).await,
+        }
+        return;
+    };
+
+    // Refusals are sent after the handshake, so TLS clients can read them.
+    match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, tls.acceptor().accept(stream)).await {
+        Ok(Ok(stream)) => {
+            let user = tls::peer_common_name(stream.get_ref().1);
+            match admission {
+                Ok(_slot) => {
+                    connection::handle(stream, Peer { user, ..peer }, dbs, limits, output_limits)
+                        .await
+                }
+                Err(reason) => refuse(stream, reason).await,
+            }
+        }
+        Ok(Err(_)) | Err(_) => metrics_prom::TLS_HANDSHAKE_FAILURES.inc(),
+    }
+}
+
+/// Waits out a shutdown drain without accepting; new connections queue in
+/// the backlog. True if it was aborted and accepting should resume.
+async fn paused() -> bool {
+    shutdown::outcome().await == shutdown::State::Running
+}
+
+/// Accepts clients until the server is shut down. The listeners are closed
+/// when this returns, and the Unix socket file is removed.
+pub async fn start(
+    addr: Option<&str>,
+    unix: Option<UnixSocket>,
+    tls: Option<Arc<Tls>>,
+    dbs: Databases,
+) {
+    let tcp = match addr {
+        Some(addr) => {
+            let listener: TcpListener = TcpListener::bind(addr).await.unwrap();
+            println!("Server running on {}", addr);
+            Some(listener)
+        }
+        None => None,
+    };
+    let uds = unix.as_ref().map(|socket| {
+        let listener = bind_unix(socket).expect("failed to bind unix socket");
+        println!("Server running on {}", socket.path.display());
+        listener
+    });
+
+    *LISTENING.lock().unwrap() = Listening {
+        tcp: addr.map(str::to_string),
+        unix: unix.clone(),
+    };
+
+    let serve_tcp = async {
+        let Some(listener) = tcp else { return };
+        let mut backoff = ACCEPT_BACKOFF_MIN;
+        loop {
+            let accepted = tokio::select! {
+                accepted = listener.accept() => accepted,
+                _ = shutdown::requested() => match paused().await {
+                    true => continue,
+                    false => return,
+                },
+            };
+            let (stream, addr) = match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    accept_failed(e, &mut backoff).await;
+                    continue;
+                }
+            };
+            backoff = ACCEPT_BACKOFF_MIN;
+            set_keepalive(&stream);
+            let admission = admit(Some(addr.ip()));
+            let peer = Peer {
+                addr: addr.to_string(),
+                laddr: stream
+                    .local_addr()
+                    .map(|a| a.to_string())
+                    .unwrap_or_default(),
+                user: None,
+            };
+            let (tls, dbs) = (tls.clone(), dbs.clone());
+            // Limits changed with CONFIG SET apply from the next connection.
+            let (limits, output_limits) = config::with(|c| (c.protocol_limits, c.output_limits));
+
+            tokio::spawn(async move {
+                serve_tcp_client(stream, peer, admission, tls, dbs, limits, output_limits).await;
+            });
+        }
+    };
+
+    let serve_unix = async {
+        let (Some(listener), Some(socket)) = (uds, &unix) else {
+            return;
+        };
+        // Unix clients have no port; Redis shows them as `path:0`.
+        let name = format!("{}:0", socket.path.display());
+        let mut backoff = ACCEPT_BACKOFF_MIN;
+        loop {
+            let accepted = tokio::select! {
+                accepted = listener.accept() => accepted,
+                _ = shutdown::requested() => match paused().await {
+                    true => continue,
+                    false => return,
+                },
+            };
+            let stream = match accepted {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    accept_failed(e, &mut backoff).await;
+                    continue;
+                }
+            };
+            backoff = ACCEPT_BACKOFF_MIN;
+            let admission = admit(None);
+            let peer = Peer {
+                addr: name.clone(),
+                laddr: name.clone(),
+                user: None,
+            };
+            let dbs = dbs.clone();
+            let (limits, output_limits) = config::with(|c| (c.protocol_limits, c.output_limits));
+
+            tokio::spawn(async move {
+                match admission {
+                    Ok(_) => connection::handle(stream, peer, dbs, limits, output_limits).await,
+                    Err(reason) => refuse(stream, reason).await,
+                }
+            });
+        }
+    };
+
+    tokio::join!(serve_tcp, serve_unix);
 
-        tokio::spawn(async move {
-            connection::handle(stream, db_clone).await;
-        });
+    if let Some(socket) = &unix {
+        let _ = std::fs::remove_file(&socket.path);
     }
 }
@@ -0,0 +1,296 @@
+//! TLS for the RESP and HTTP listeners, with optional client certificates.
+//! Both listeners share one `RustlsConfig`, so a reload on SIGHUP applies to
+//! new connections everywhere while existing ones keep their session.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use axum_server::tls_rustls::RustlsConfig;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig, ServerConnection};
+use tokio_rustls::TlsAcceptor;
+
+/// Whether clients must present a certificate signed by the CA, as in
+/// Redis' `tls-auth-clients`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientAuth {
+    No,
+    Optional,
+    Required,
+}
+
+impl ClientAuth {
+    pub fn parse(s: &str) -> Result<ClientAuth, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "no" => Ok(ClientAuth::No),
+            "optional" => Ok(ClientAuth::Optional),
+            "yes" => Ok(ClientAuth::Required),
+            _ => Err(format!(
+                "invalid client auth mode '{}', expected yes, no or optional",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TlsSettings {
+    pub cert_file: PathBuf,
+    pub key_file: PathBuf,
+    /// CA bundle that client certificates are verified against.
+    pub ca_file: Option<PathBuf>,
+    pub client_auth: ClientAuth,
+}
+
+/// The live TLS configuration and where it was loaded from.
+pub struct Tls {
+    settings: TlsSettings,
+    config: RustlsConfig,
+}
+
+fn read_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, String> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    if certs.is_empty() {
+        return Err(format!("{}: no certificates found", path.display()));
+    }
+    Ok(certs)
+}
+
+fn read_key(path: &Path) -> Result<PrivateKeyDer<'static>, String> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .map_err(|e| format!("{}: {}", path.display(), e))?
+        .ok_or_else(|| format!("{}: no private key found", path.display()))
+}
+
+impl TlsSettings {
+    /// Reads the certificate, key and CA files into a server config.
+    pub fn load(&self) -> Result<Arc<ServerConfig>, String> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(|e| e.to_string())?;
+
+        let builder = match (&self.ca_file, self.client_auth) {
+            (Some(ca), auth) if auth != ClientAuth::No => {
+                let mut roots = RootCertStore::empty();
+                for cert in read_certs(ca)? {
+                    roots
+                        .add(cert)
+                        .map_err(|e| format!("{}: {}", ca.display(), e))?;
+                }
+                let verifier =
+                    WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+                let verifier = if auth == ClientAuth::Optional {
+                    verifier.allow_unauthenticated()
+                } else {
+                    verifier
+                };
+                builder.with_client_cert_verifier(verifier.build().map_err(|e| e.to_string())?)
+            }
+            (None, ClientAuth::Required | ClientAuth::Optional) => {
+                return Err("client certificate verification needs a CA file".into());
+            }
+            _ => builder.with_no_client_auth(),
+        };
+
+        let config = builder
+            .with_single_cert(read_certs(&self.cert_file)?, read_key(&self.key_file)?)
+            .map_err(|e| e.to_string())?;
+        Ok(Arc::new(config))
+    }
+}
+
+impl Tls {
+    pub fn new(settings: TlsSettings) -> Result<Tls, String> {
+        let config = RustlsConfig::from_config(settings.load()?);
+        Ok(Tls { settings, config })
+    }
+
+    /// Acceptor for the current configuration; take one per connection so
+    /// reloads are picked up.
+    pub fn acceptor(&self) -> TlsAcceptor {
+        TlsAcceptor::from(self.config.get_inner())
+    }
+
+    /// The same configuration, for the HTTP server.
+    pub fn http_config(&self) -> RustlsConfig {
+        self.config.clone()
+    }
+
+    /// Re-reads the files. On error the previous configuration stays.
+    pub fn reload(&self) -> Result<(), String> {
+        self.config.reload_from_config(self.settings.load()?);
+        Ok(())
+    }
+}
+
+/// Reloads certificates whenever the process gets SIGHUP.
+pub fn reload_on_sighup(tls: Arc<Tls>) {
+    tokio::spawn(async move {
+        let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+            .expect("failed to install SIGHUP handler");
+        while hangup.recv().await.is_some() {
+            match tls.reload() {
+                Ok(()) => println!("TLS certificates reloaded"),
+                Err(e) => eprintln!("TLS reload failed, keeping the old certificates: {}", e),
+            }
+        }
+    });
+}
+
+/// The common name of the verified client certificate, if one was sent.
+pub fn peer_common_name(conn: &ServerConnection) -> Option<String> {
+    let cert = conn.peer_certificates()?.first()?;
+    let (_, parsed) = x509_parser::parse_x509_certificate(cert.as_ref()).ok()?;
+    let cn = parsed
+        .subject()
+        .iter_common_name()
+        .next()?
+        .as_str()
+        .ok()?
+        .to_string();
+    Some(cn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rcgen::{
+        BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+    };
+    use rustls::pki_types::PrivatePkcs8KeyDer;
+    use rustls::{ClientConfig, ClientConnection};
+
+    struct Pki {
+        dir: PathBuf,
+        roots: RootCertStore,
+        client: (CertificateDer<'static>, PrivateKeyDer<'static>),
+    }
+
+    /// A CA, a server certificate for `localhost` and a client certificate
+    /// with common name `alice`, the first two written to a temp dir.
+    fn pki(name: &str) -> Pki {
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        ca_params.distinguished_name.push(DnType::CommonName, "test ca");
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+
+        let server_key = KeyPair::generate().unwrap();
+        let server = CertificateParams::new(vec!["localhost".to_string()])
+            .unwrap()
+            .signed_by(&server_key, &ca, &ca_key)
+            .unwrap();
+
+        let client_key = KeyPair::generate().unwrap();
+        let mut client_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        client_params.distinguished_name.push(DnType::CommonName, "alice");
+        client_params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+        let client = client_params.signed_by(&client_key, &ca, &ca_key).unwrap();
+
+        let dir = std::env::temp_dir().join(format!("keyval-tls-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("server.crt"), server.pem()).unwrap();
+        std::fs::write(dir.join("server.key"), server_key.serialize_pem()).unwrap();
+        std::fs::write(dir.join("ca.crt"), ca.pem()).unwrap();
+
+        let mut roots = RootCertStore::empty();
+        roots.add(ca.der().clone()).unwrap();
+        let client_key = PrivatePkcs8KeyDer::from(client_key.serialize_der()).into();
+        Pki { dir, roots, client: (client.der().clone(), client_key) }
+    }
+
+    impl Drop for Pki {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn settings(pki: &Pki, client_auth: ClientAuth) -> TlsSettings {
+        TlsSettings {
+            cert_file: pki.dir.join("server.crt"),
+            key_file: pki.dir.join("server.key"),
+            ca_file: Some(pki.dir.join("ca.crt")),
+            client_auth,
+        }
+    }
+
+    /// Runs a handshake in memory and returns the server's side of it.
+    fn handshake(pki: &Pki, client_auth: ClientAuth, send_cert: bool) -> ServerConnection {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let builder = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(pki.roots.clone());
+        let config = if send_cert {
+            let (cert, key) = &pki.client;
+            builder.with_client_auth_cert(vec![cert.clone()], key.clone_key()).unwrap()
+        } else {
+            builder.with_no_client_auth()
+        };
+
+        let mut client = ClientConnection::new(Arc::new(config), "localhost".try_into().unwrap()).unwrap();
+        let mut server = ServerConnection::new(settings(pki, client_auth).load().unwrap()).unwrap();
+        let mut buf = Vec::new();
+        while client.is_handshaking() || server.is_handshaking() {
+            buf.clear();
+            while client.wants_write() {
+                client.write_tls(&mut buf).unwrap();
+            }
+            let mut rd = &buf[..];
+            while !rd.is_empty() {
+                server.read_tls(&mut rd).unwrap();
+                server.process_new_packets().unwrap();
+            }
+
+            buf.clear();
+            while server.wants_write() {
+                server.write_tls(&mut buf).unwrap();
+            }
+            let mut rd = &buf[..];
+            while !rd.is_empty() {
+                client.read_tls(&mut rd).unwrap();
+                client.process_new_packets().unwrap();
+            }
+        }
+        server
+    }
+
+    #[test]
+    fn common_name_of_client_certificate() {
+        let pki = pki("cn");
+        let server = handshake(&pki, ClientAuth::Required, true);
+        assert_eq!(peer_common_name(&server).as_deref(), Some("alice"));
+    }
+
+    #[test]
+    fn no_common_name_without_certificate() {
+        let pki = pki("optional");
+        let server = handshake(&pki, ClientAuth::Optional, false);
+        assert_eq!(peer_common_name(&server), None);
+    }
+
+    #[test]
+    fn client_auth_needs_a_ca() {
+        let pki = pki("noca");
+        let mut settings = settings(&pki, ClientAuth::Required);
+        settings.ca_file = None;
+        assert!(settings.load().is_err());
+    }
+
+    #[test]
+    fn parses_client_auth_modes() {
+        assert_eq!(ClientAuth::parse("YES"), Ok(ClientAuth::Required));
+        assert_eq!(ClientAuth::parse("optional"), Ok(ClientAuth::Optional));
+        assert_eq!(ClientAuth::parse("no"), Ok(ClientAuth::No));
+        assert!(ClientAuth::parse("maybe").is_err());
+    }
+}
@@ -0,0 +1,470 @@
+//! WebSocket access for browser clients. Text frames carry JSON commands,
+//! binary frames carry RESP requests; either can subscribe to channels and
+//! get messages pushed on the same socket.
+
+use std::collections::BTreeSet;
+use std::net::SocketAddr;
+use std::time::Instant;
+
+use axum::extract::ws::{Message as Frame, WebSocket, WebSocketUpgrade};
+use axum::extract::{ConnectInfo, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::db::storage::Databases;
+use crate::protocol::parser;
+use crate::protocol::resp::encoder::RespValue;
+use crate::protocol::resp::parser::RequestParser;
+use crate::server::acl;
+use crate::server::clients::{self, ClientInfo};
+use crate::server::config;
+use crate::server::latency;
+use crate::server::http_api::to_json;
+use crate::server::metrics_prom;
+use crate::server::output::ClientClass;
+use crate::server::pubsub::{self, Subscriber};
+use crate::server::session::Session;
+use crate::server::slowlog;
+use crate::server::shutdown;
+
+pub fn router(dbs: Databases) -> Router {
+    Router::new().route("/v1/ws", get(upgrade)).with_state(dbs)
+}
+
+/// Whether a page from the request's `Origin` may open a socket. Browsers
+/// let any page connect to any host, localhost included, so only the
+/// server's own origin and the ones in `ws-allowed-origins` get in.
+/// Requests without an `Origin` do not come from a browser page.
+fn origin_allowed(headers: &HeaderMap) -> bool {
+    let Some(origin) = headers.get(header::ORIGIN) else {
+        return true;
+    };
+    let Ok(origin) = origin.to_str() else {
+        return false;
+    };
+    let same_origin = origin
+        .split_once("://")
+        .zip(headers.get(header::HOST).and_then(|h| h.to_str().ok()))
+        .is_some_and(|((_, authority), host)| authority.eq_ignore_ascii_case(host));
+    same_origin
+        || config::with(|c| {
+            c.ws_allowed_origins
+                .split_whitespace()
+                .any(|allowed| allowed == "*" || allowed.eq_ignore_ascii_case(origin))
+        })
+}
+
+async fn upgrade(
+    ws: WebSocketUpgrade,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    State(dbs): State<Databases>,
+    headers: HeaderMap,
+) -> Response {
+    if !origin_allowed(&headers) {
+        metrics_prom::REJECTED_CONNECTIONS
+            .with_label_values(&["origin"])
+            .inc();
+        return (StatusCode::FORBIDDEN, "origin not allowed").into_response();
+    }
+    let Some(slot) = clients::reserve() else {
+        metrics_prom::REJECTED_CONNECTIONS
+            .with_label_values(&["maxclients"])
+            .inc();
+        return (StatusCode::SERVICE_UNAVAILABLE, "max number of clients reached").into_response();
+    };
+    ws.on_upgrade(move |socket| async move {
+        handle(socket, addr, dbs).await;
+        drop(slot);
+    })
+}
+
+/// A JSON command as either `["GET", "k"]` or `{"args": [...], "id": ...}`;
+/// the optional `id` is echoed back so clients can match replies.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonCommand {
+    Args(Vec<String>),
+    Full {
+        args: Vec<String>,
+        id: Option<Value>,
+    },
+}
+
+/// How the client last talked to us; pushed messages follow suit.
+#[derive(Clone, Copy)]
+enum Format {
+    Json,
+    Resp,
+}
+
+/// A subscription change, sent once per channel or pattern like Redis does.
+struct Notice {
+    kind: &'static str,
+    name: Option<String>,
+    count: usize,
+}
+
+impl Notice {
+    fn to_resp(&self) -> RespValue {
+        RespValue::Push(vec![
+            RespValue::bulk(self.kind),
+            self.name.clone().map_or(RespValue::Null, RespValue::bulk),
+            RespValue::Integer(self.count as i64),
+        ])
+    }
+
+    fn to_json(&self) -> Value {
+        let field = if self.kind.starts_with('p') {
+            "pattern"
+        } else {
+            "channel"
+        };
+        json!({ "type": self.kind, field: self.name, "count": self.count })
+    }
+}
+
+/// Channels and patterns this socket is subscribed to.
+struct Subscriptions {
+    sub: Subscriber,
+    channels: BTreeSet<String>,
+    patterns: BTreeSet<String>,
+}
+
+impl Subscriptions {
+    fn count(&self) -> usize {
+        self.channels.len() + self.patterns.len()
+    }
+
+    /// Runs SUBSCRIBE and friends; `None` for any other command.
+    fn handle(
+        &mut self,
+        parts: &[String],
+        session: &mut Session,
+    ) -> Option<Result<Vec<Notice>, RespValue>> {
+        let cmd = parts[0].to_uppercase();
+        let args = &parts[1..];
+        let pattern = cmd.starts_with('P');
+
+        if !matches!(
+            cmd.as_str(),
+            "SUBSCRIBE" | "PSUBSCRIBE" | "UNSUBSCRIBE" | "PUNSUBSCRIBE"
+        ) {
+            return None;
+        }
+        // These never reach the dispatcher, so they are checked here.
+        if let Err(e) = acl::check(session, parts) {
+            return Some(Err(e));
+        }
+
+        let notices = match cmd.as_str() {
+            "SUBSCRIBE" | "PSUBSCRIBE" => {
+                if args.is_empty() {
+                    return Some(Err(RespValue::Error(format!(
+                        "ERR usage {} {} [...]",
+                        cmd,
+                        if pattern { "pattern" } else { "channel" }
+                    ))));
+                }
+                args.iter()
+                    .map(|name| {
+                        if pattern {
+                            pubsub::psubscribe(&self.sub, name);
+                            self.patterns.insert(name.clone());
+                        } else {
+                            pubsub::subscribe(&self.sub, name);
+                            self.channels.insert(name.clone());
+                        }
+                        Notice {
+                            kind: if pattern { "psubscribe" } else { "subscribe" },
+                            name: Some(name.clone()),
+                            count: self.count(),
+                        }
+                    })
+                    .collect()
+            }
+            "UNSUBSCRIBE" | "PUNSUBSCRIBE" => {
+                let kind = if pattern {
+                    "punsubscribe"
+                } else {
+                    "unsubscribe"
+                };
+                let current = if pattern {
+                    &self.patterns
+                } else {
+                    &self.channels
+                };
+                // Without arguments, everything of that kind is dropped.
+                let names: Vec<String> = if args.is_empty() {
+                    current.iter().cloned().collect()
+                } else {
+                    args.to_vec()
+                };
+                if names.is_empty() {
+                    return Some(Ok(vec![Notice {
+                        kind,
+                        name: None,
+                        count: self.count(),
+                    }]));
+                }
+                names
+                    .into_iter()
+                    .map(|name| {
+                        if pattern {
+                            pubsub::punsubscribe(self.sub.id, &name);
+                            self.patterns.remove(&name);
+                        } else {
+                            pubsub::unsubscribe(self.sub.id, &name);
+                            self.channels.remove(&name);
+                        }
+                        Notice {
+                            kind,
+                            name: Some(name),
+                            count: self.count(),
+                        }
+                    })
+                    .collect()
+            }
+            _ => return None,
+        };
+        session.subscriptions = self.count();
+        Some(Ok(notices))
+    }
+}
+
+async fn handle(mut socket: WebSocket, addr: SocketAddr, dbs: Databases) {
+    metrics_prom::ACTIVE_CONNS.inc();
+
+    let mut session = Session::default();
+    let client = clients::register(session.id, addr.to_string(), "/v1/ws".into());
+    let (sub, mut inbox) = Subscriber::new(session.id);
+    let mut subs = Subscriptions {
+        sub: sub.clone(),
+        channels: BTreeSet::new(),
+        patterns: BTreeSet::new(),
+    };
+    let mut format = Format::Json;
+
+    loop {
+        let reply = tokio::select! {
+            frame = socket.recv() => match frame {
+                Some(Ok(Frame::Text(text))) => {
+                    format = Format::Json;
+                    account_in(&client, text.len());
+                    Frame::Text(json_frame(&text, &mut session, &mut subs, &client, &dbs).await)
+                }
+                Some(Ok(Frame::Binary(bytes))) => {
+                    format = Format::Resp;
+                    account_in(&client, bytes.len());
+                    Frame::Binary(resp_frame(&bytes, &mut session, &mut subs, &client, &dbs).await)
+                }
+                Some(Ok(Frame::Ping(_) | Frame::Pong(_))) => continue,
+                Some(Ok(Frame::Close(_))) | Some(Err(_)) | None => break,
+            },
+            Some(msg) = inbox.recv() => {
+                client.record_buffers(0, 0, inbox.pending());
+                push_frame(&msg, format, &session)
+            }
+            _ = sub.evicted() => {
+                count_eviction();
+                break;
+            }
+            _ = shutdown::stopped() => break,
+            _ = client.killed() => break,
+        };
+
+        let len = match &reply {
+            Frame::Text(t) => t.len(),
+            Frame::Binary(b) => b.len(),
+            _ => 0,
+        };
+        // A client that stops reading blocks the send; the hub drops it once
+        // its backlog is over the limit.
+        let sent = tokio::select! {
+            sent = socket.send(reply) => sent.is_ok(),
+            _ = sub.evicted() => {
+                count_eviction();
+                false
+            }
+        };
+        if !sent {
+            break;
+        }
+        metrics_prom::BYTES_OUT.inc_by(len as u64);
+        client.record_out(len);
+    }
+
+    pubsub::unsubscribe_all(session.id);
+    metrics_prom::ACTIVE_CONNS.dec();
+}
+
+/// Counts a subscriber the hub dropped for not keeping up with its messages.
+fn count_eviction() {
+    metrics_prom::OUTPUT_LIMIT_DISCONNECTS
+        .with_label_values(&[ClientClass::Pubsub.name()])
+        .inc();
+}
+
+fn account_in(client: &ClientInfo, n: usize) {
+    metrics_prom::BYTES_IN.inc_by(n as u64);
+    client.record_in(n);
+}
+
+/// Runs one command, pub/sub ones included, with the usual accounting.
+async fn run(
+    parts: Vec<String>,
+    session: &mut Session,
+    subs: &mut Subscriptions,
+    client: &ClientInfo,
+    dbs: &Databases,
+) -> Result<RespValue, Vec<Notice>> {
+    let cmd = parts[0].to_uppercase();
+    // SHUTDOWN waits for the drain, so it must not count as in flight.
+    let _in_flight = if cmd == "SHUTDOWN" {
+        None
+    } else {
+        match shutdown::admit().await {
+            Some(guard) => Some(guard),
+            None => return Ok(RespValue::Error("ERR server is shutting down".into())),
+        }
+    };
+    let label = metrics_prom::command_label(&cmd);
+    metrics_prom::CMD_TOTAL
+        .with_label_values(&[label])
+        .inc();
+    let t0 = Instant::now();
+
+    let reply = match subs.handle(&parts, session) {
+        Some(Ok(notices)) => Err(notices),
+        Some(Err(e)) => Ok(e),
+        None => Ok(parser::process_parts(&parts, session, dbs).await),
+    };
+
+    let elapsed = t0.elapsed();
+    metrics_prom::CMD_LATENCY
+        .with_label_values(&[label])
+        .observe(elapsed.as_secs_f64());
+    slowlog::record(&parts, elapsed, &client.addr, session.name.as_deref());
+    latency::command(label, elapsed);
+    client.record_command(session, label);
+    reply
+}
+
+/// A JSON command in, `{"result": ...}` or `{"error": ...}` out. Pub/sub
+/// commands answer with `{"replies": [...]}`, one notice per channel.
+async fn json_frame(
+    text: &str,
+    session: &mut Session,
+    subs: &mut Subscriptions,
+    client: &ClientInfo,
+    dbs: &Databases,
+) -> String {
+    let (args, id) = match serde_json::from_str::<JsonCommand>(text) {
+        Ok(JsonCommand::Args(args)) => (args, None),
+        Ok(JsonCommand::Full { args, id }) => (args, id),
+        Err(e) => return json!({ "error": format!("invalid command: {}", e) }).to_string(),
+    };
+
+    let mut body = if args.is_empty() {
+        json!({ "error": "empty command" })
+    } else {
+        match run(args, session, subs, client, dbs).await {
+            Ok(RespValue::Error(e)) => json!({ "error": e }),
+            Ok(reply) => json!({ "result": to_json(&reply) }),
+            Err(notices) => {
+                json!({ "replies": notices.iter().map(Notice::to_json).collect::<Vec<_>>() })
+            }
+        }
+    };
+    if let Some(id) = id {
+        body["id"] = id;
+    }
+    body.to_string()
+}
+
+/// One or more complete RESP requests in, their replies out, encoded with
+/// the protocol picked by `HELLO`.
+async fn resp_frame(
+    bytes: &[u8],
+    session: &mut Session,
+    subs: &mut Subscriptions,
+    client: &ClientInfo,
+    dbs: &Databases,
+) -> Vec<u8> {
+    let mut req_parser = RequestParser::new();
+    let mut out = Vec::new();
+    let mut pos = 0;
+
+    while pos < bytes.len() {
+        let parts = match req_parser.parse(&bytes[pos..]) {
+            Ok(Some(req)) => {
+                pos += req.consumed();
+                match req.to_strings() {
+                    Some(parts) => parts,
+                    None => {
+                        out.extend(RespValue::Error(parser::NOT_UTF8.into()).encode(session.protocol));
+                        continue;
+                    }
+                }
+            }
+            Ok(None) => {
+                // Frames are self-contained: a request cannot span two.
+                out.extend(
+                    RespValue::Error("ERR Protocol error: incomplete request in frame".into())
+                        .encode(session.protocol),
+                );
+                break;
+            }
+            Err(e) => {
+                out.extend(RespValue::Error(format!("ERR {}", e)).encode(session.protocol));
+                break;
+            }
+        };
+        if parts.is_empty() {
+            continue;
+        }
+
+        match run(parts, session, subs, client, dbs).await {
+            Ok(reply) => out.extend(reply.encode(session.protocol)),
+            Err(notices) => {
+                for n in notices {
+                    out.extend(n.to_resp().encode(session.protocol));
+                }
+            }
+        }
+    }
+    out
+}
+
+/// A published message, in the format the client last used.
+fn push_frame(msg: &pubsub::Message, format: Format, session: &Session) -> Frame {
+    match format {
+        Format::Json => {
+            let body = match &msg.pattern {
+                Some(p) => {
+                    json!({ "type": "pmessage", "pattern": p, "channel": msg.channel, "payload": msg.payload })
+                }
+                None => {
+                    json!({ "type": "message", "channel": msg.channel, "payload": msg.payload })
+                }
+            };
+            Frame::Text(body.to_string())
+        }
+        Format::Resp => {
+            let mut items = Vec::with_capacity(4);
+            match &msg.pattern {
+                Some(p) => {
+                    items.push(RespValue::bulk("pmessage"));
+                    items.push(RespValue::bulk(p.clone()));
+                }
+                None => items.push(RespValue::bulk("message")),
+            }
+            items.push(RespValue::bulk(msg.channel.clone()));
+            items.push(RespValue::bulk(msg.payload.clone()));
+            Frame::Binary(RespValue::Push(items).encode(session.protocol))
+        }
+    }
+}
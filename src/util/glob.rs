@@ -0,0 +1,165 @@
+//! Redis-style glob matching, as used by `KEYS` and `SCAN ... MATCH`.
+//!
+//! Supported syntax: `*` (any run of bytes), `?` (any single byte),
+//! `[abc]`, `[^abc]` and `[a-z]` classes, and `\x` to match `x` literally.
+
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    match_bytes(pattern.as_bytes(), text.as_bytes(), false)
+}
+
+fn eq(a: u8, b: u8, nocase: bool) -> bool {
+    if nocase {
+        a.eq_ignore_ascii_case(&b)
+    } else {
+        a == b
+    }
+}
+
+/// Matches `c` against the class starting at `p[start] == b'['`.
+/// Returns whether it matched and the index just past the closing `]`.
+fn match_class(p: &[u8], start: usize, c: u8, nocase: bool) -> (bool, usize) {
+    let mut i = start + 1;
+    let negate = p.get(i) == Some(&b'^');
+    if negate {
+        i += 1;
+    }
+
+    let mut matched = false;
+    while i < p.len() {
+        if p[i] == b'\\' && i + 1 < p.len() {
+            if eq(p[i + 1], c, nocase) {
+                matched = true;
+            }
+            i += 2;
+        } else if p[i] == b']' {
+            i += 1;
+            return (matched != negate, i);
+        } else if i + 2 < p.len() && p[i + 1] == b'-' {
+            let (mut lo, mut hi) = (p[i], p[i + 2]);
+            if lo > hi {
+                std::mem::swap(&mut lo, &mut hi);
+            }
+            let (lo, hi, c) = if nocase {
+                (lo.to_ascii_lowercase(), hi.to_ascii_lowercase(), c.to_ascii_lowercase())
+            } else {
+                (lo, hi, c)
+            };
+            if c >= lo && c <= hi {
+                matched = true;
+            }
+            i += 3;
+        } else {
+            if eq(p[i], c, nocase) {
+                matched = true;
+            }
+            i += 1;
+        }
+    }
+
+    // Unterminated class: like Redis, treat the end of the pattern as `]`.
+    (matched != negate, i)
+}
+
+fn match_bytes(p: &[u8], s: &[u8], nocase: bool) -> bool {
+    let (mut pi, mut si) = (0, 0);
+    // Position right after the last `*` seen, and the text index it is
+    // currently assumed to cover up to; used to backtrack on mismatch.
+    let mut star: Option<(usize, usize)> = None;
+
+    while si < s.len() {
+        if pi < p.len() {
+            match p[pi] {
+                b'*' => {
+                    pi += 1;
+                    star = Some((pi, si));
+                    continue;
+                }
+                b'?' => {
+                    pi += 1;
+                    si += 1;
+                    continue;
+                }
+                b'[' => {
+                    let (matched, next) = match_class(p, pi, s[si], nocase);
+                    if matched {
+                        pi = next;
+                        si += 1;
+                        continue;
+                    }
+                }
+                b'\\' if pi + 1 < p.len() => {
+                    if eq(p[pi + 1], s[si], nocase) {
+                        pi += 2;
+                        si += 1;
+                        continue;
+                    }
+                }
+                c => {
+                    if eq(c, s[si], nocase) {
+                        pi += 1;
+                        si += 1;
+                        continue;
+                    }
+                }
+            }
+        }
+
+        match star {
+            Some((sp, ss)) => {
+                pi = sp;
+                si = ss + 1;
+                star = Some((sp, si));
+            }
+            None => return false,
+        }
+    }
+
+    while pi < p.len() && p[pi] == b'*' {
+        pi += 1;
+    }
+    pi == p.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::glob_match;
+
+    #[test]
+    fn wildcards() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*", "anything"));
+        assert!(glob_match("user:*", "user:42"));
+        assert!(!glob_match("user:*", "session:42"));
+        assert!(glob_match("*:42", "user:42"));
+        assert!(glob_match("a*b*c", "axxbyyc"));
+        assert!(!glob_match("a*b*c", "axxbyy"));
+        assert!(glob_match("h?llo", "hello"));
+        assert!(!glob_match("h?llo", "hllo"));
+        assert!(glob_match("**", "x"));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(glob_match("h[ae]llo", "hallo"));
+        assert!(!glob_match("h[ae]llo", "hillo"));
+        assert!(glob_match("h[^e]llo", "hallo"));
+        assert!(!glob_match("h[^e]llo", "hello"));
+        assert!(glob_match("key[0-9]", "key7"));
+        assert!(glob_match("key[9-0]", "key7"));
+        assert!(!glob_match("key[0-9]", "keyx"));
+        assert!(glob_match("a[\\]]b", "a]b"));
+    }
+
+    #[test]
+    fn escapes() {
+        assert!(glob_match("a\\*b", "a*b"));
+        assert!(!glob_match("a\\*b", "axb"));
+        assert!(glob_match("what\\?", "what?"));
+    }
+
+    #[test]
+    fn unterminated_class_ends_the_pattern() {
+        assert!(glob_match("a[bc", "ab"));
+        assert!(!glob_match("a[bc", "ad"));
+    }
+}